@@ -0,0 +1,85 @@
+use std::{fs::File, io::BufWriter, path::Path};
+
+use image::{
+    codecs::gif::{GifEncoder, Repeat},
+    Delay, Frame, Rgba, RgbaImage,
+};
+
+use crate::chart::{generate_time_chart, ChartTheme, SummaryMetric};
+use crate::dots::PowerSystem;
+use crate::state::time_fraction;
+
+const PX_WIDTH: u32 = 1024;
+const PX_HEIGHT: u32 = 768;
+
+/// Most frames written. Longer datasets are sampled evenly.
+const MAX_FRAMES: usize = 300;
+
+/// Time each frame is shown.
+const FRAME_MS: u32 = 100;
+
+/// Quantization speed passed to the GIF encoder, from 1 (best) to 30 (fastest).
+const GIF_SPEED: i32 = 10;
+
+const INDICATOR_COLOR: Rgba<u8> = Rgba([255, 3, 3, 255]);
+
+/// Renders the overview chart for `metric` as a looping GIF, with a time
+/// indicator sweeping across it.
+///
+/// There is one frame per time step, up to [`MAX_FRAMES`]. Traces are
+/// decimated to `max_points`, as on the live chart.
+pub fn export_animation(
+    system: &PowerSystem,
+    metric: SummaryMetric,
+    theme: ChartTheme,
+    max_points: Option<usize>,
+    path: &Path,
+) -> Result<(), anyhow::Error> {
+    let time_count = system.lines.len();
+
+    if time_count == 0 {
+        anyhow::bail!("Dataset has no time steps to animate");
+    }
+
+    let chart = generate_time_chart(system, metric, PX_WIDTH, PX_HEIGHT, 1, theme, max_points);
+
+    let background = image::load_from_memory(&chart.png)?.to_rgba8();
+
+    let mut encoder = GifEncoder::new_with_speed(BufWriter::new(File::create(path)?), GIF_SPEED);
+    encoder.set_repeat(Repeat::Infinite)?;
+
+    let frames = time_count.min(MAX_FRAMES);
+
+    for frame_i in 0..frames {
+        let time_step = frame_i * time_count / frames;
+
+        // Same placement as the indicator on the live chart
+        let frac = time_fraction(time_step as f32, time_count);
+        let x = chart.plot_x.start as f32 + (chart.plot_x.end - chart.plot_x.start) as f32 * frac;
+
+        let mut image = background.clone();
+        draw_indicator(&mut image, x.round() as u32);
+
+        encoder.encode_frame(Frame::from_parts(
+            image,
+            0,
+            0,
+            Delay::from_numer_denom_ms(FRAME_MS, 1),
+        ))?;
+    }
+
+    log::info!("Wrote {frames} frames to {}", path.display());
+
+    Ok(())
+}
+
+/// Draws a vertical line, three pixels wide, through the whole image.
+fn draw_indicator(image: &mut RgbaImage, x: u32) {
+    let columns = x.saturating_sub(1)..(x + 2).min(image.width());
+
+    for px in columns {
+        for py in 0..image.height() {
+            image.put_pixel(px, py, INDICATOR_COLOR);
+        }
+    }
+}
@@ -1,15 +1,406 @@
-use std::path::PathBuf;
+use std::{path::PathBuf, time::Duration};
 
-use clap::Parser;
+use clap::{error::ErrorKind, CommandFactory, Parser};
+
+use crate::{
+    chart::{ChartTheme, SummaryMetric},
+    domain::{Domain, DomainFit, HazardPlane},
+    instance::BusStyle,
+};
 
 #[derive(Debug, Parser)]
 #[command(version, about)]
 pub struct Arguments {
     /// Path to power systems data pack. This should in the
-    /// `PowerSystemsData` format
+    /// `PowerSystemsData` format, or a `.json` file following the JSON
+    /// dataset schema
     pub pack_path: PathBuf,
 
     /// Set the port of the server
     #[arg(short, long)]
     pub port: Option<u16>,
+
+    /// Memory-map the data pack instead of reading it into memory. Useful
+    /// for very large packs, as the OS pages data in on demand.
+    #[arg(long)]
+    pub mmap: bool,
+
+    /// Disable the decoding limit that protects against corrupt or hostile
+    /// packs. Only use this for trusted, very large files
+    #[arg(long)]
+    pub unlimited_traversal: bool,
+
+    /// Vertical exaggeration factor applied to line and bus heights
+    #[arg(long, value_parser = parse_positive)]
+    pub exaggeration: Option<f32>,
+
+    /// Minimum tube width, in domain units (the full extent of the data is
+    /// about 2 units)
+    #[arg(long, value_parser = parse_positive)]
+    pub tube_min: Option<f32>,
+
+    /// Maximum tube width, in domain units (the full extent of the data is
+    /// about 2 units)
+    #[arg(long, value_parser = parse_positive)]
+    pub tube_max: Option<f32>,
+
+    /// Horizontal spacing between phase tubes, across the direction of each
+    /// line, in domain units (the full extent of the data is about 2 units)
+    #[arg(long, value_parser = parse_positive)]
+    pub phase_offset: Option<f32>,
+
+    /// Distance of each transformer phase from the transformer location, in
+    /// domain units. Phases are spread around it in a triangle
+    #[arg(long, value_parser = parse_positive)]
+    pub tf_phase_radius: Option<f32>,
+
+    /// Let tubes for real power above the normalization bound grow past the
+    /// maximum tube width, so over-limit flows stand out
+    #[arg(long)]
+    pub open_tube_max: bool,
+
+    /// Divisor applied to line load on import, so that 1.0 means 100% of
+    /// the line rating. Use 100 if the dataset stores percentages
+    #[arg(long, value_parser = parse_positive)]
+    pub line_load_divisor: Option<f32>,
+
+    /// Line load (after the divisor) that maps to the maximum height
+    #[arg(long, value_parser = parse_positive)]
+    pub line_load_max: Option<f32>,
+
+    /// Real-world voltage, in kV, of 1.0 p.u. Overrides any value in the
+    /// dataset. Only affects chart labels
+    #[arg(long, value_parser = parse_positive)]
+    pub nominal_voltage_kv: Option<f32>,
+
+    /// Generator real power that maps to the largest generator glyph
+    #[arg(long, value_parser = parse_positive)]
+    pub gen_watt_bounds: Option<f32>,
+
+    /// Multiplier applied to generator glyph size
+    #[arg(long, value_parser = parse_positive)]
+    pub gen_size_scale: Option<f32>,
+
+    /// Shape used to draw buses. "sphere" sizes them by voltage deviation
+    /// from 1.0 p.u. rather than by power
+    #[arg(long, value_enum, default_value_t = BusStyle::Tube)]
+    pub bus_style: BusStyle,
+
+    /// Size generators by nameplate capacity, and brighten them by output.
+    /// Generators without a capacity are still sized by output
+    #[arg(long)]
+    pub gen_capacity: bool,
+
+    /// Animate line flow on every visible phase, rather than phase A only
+    #[arg(long)]
+    pub flow_all_phases: bool,
+
+    /// Number of previous time steps to draw as a faded trail behind the
+    /// lines. Zero disables the trail
+    #[arg(long, default_value_t = 0)]
+    pub trail_length: usize,
+
+    /// How the data is fitted to the scene. "square" fits the longer side to
+    /// 2 units; "area" does too, but widens the shorter side as if the data
+    /// covered a 2 unit square, so long and thin feeders are not a sliver
+    #[arg(long, value_enum, default_value_t = DomainFit::Square)]
+    pub domain_fit: DomainFit,
+
+    /// Map increasing data Y away from the default viewer rather than
+    /// toward it. Use for datasets whose Y axis points the opposite way,
+    /// which otherwise appear mirrored
+    #[arg(long)]
+    pub no_y_flip: bool,
+
+    /// Height of the floor in the scene. Everything is raised by this much,
+    /// for clients that do not place their floor at zero
+    #[arg(long, default_value_t = 0.0, allow_hyphen_values = true)]
+    pub scene_y_offset: f32,
+
+    /// Draw ground lines as dashes of this length, in domain units (the full
+    /// extent of the data is about 2 units). Ground lines are solid if unset
+    #[arg(long, value_parser = parse_positive)]
+    pub ground_dash: Option<f32>,
+
+    /// Gap between ground line dashes, in domain units
+    #[arg(long, default_value_t = 0.01, value_parser = parse_positive)]
+    pub ground_gap: f32,
+
+    /// Once a client reports a view position, lines farther than this from
+    /// it (along the ground, in domain units) are not drawn
+    #[arg(long, default_value_t = 1.0, value_parser = parse_positive)]
+    pub view_radius: f32,
+
+    /// Probes only attach to a line within this distance (along the ground,
+    /// in domain units). Probes placed farther away rest on the ground
+    #[arg(long, default_value_t = 0.2, value_parser = parse_positive)]
+    pub snap_radius: f32,
+
+    /// Extra voltage limit to generate hazard cells for, as `VOLTAGE` or
+    /// `VOLTAGE:SEVERITY` (default severity 1). Higher severities are drawn
+    /// in stronger colors. May be repeated
+    #[arg(long = "hazard-plane", value_parser = parse_hazard_plane)]
+    pub hazard_planes: Vec<HazardPlane>,
+
+    /// Number of recent time steps to accumulate hazard cells over. Cells
+    /// are then colored by how often lines crossed them, from faint yellow to
+    /// red. Zero draws only the current step
+    #[arg(long, default_value_t = 0)]
+    pub hazard_window: usize,
+
+    /// Most instances sent for any one group (lines, buses, ...). Larger
+    /// groups are thinned out evenly, whatever the view. Unlimited if unset
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    pub max_instances: Option<u32>,
+
+    /// Compare each instance group with its last upload, and skip groups that
+    /// have not changed. Saves bandwidth during slow playback
+    #[arg(long)]
+    pub diff_buffers: bool,
+
+    /// Append each line's dataset index to its instance, as one extra float
+    /// after the standard 64 bytes, so clients can pick lines themselves
+    #[arg(long)]
+    pub line_ids: bool,
+
+    /// Append the saturation at each end of a line to its instance, as two
+    /// extra floats after any line index, so clients can shade a gradient
+    #[arg(long)]
+    pub line_gradient: bool,
+
+    /// Draw lines, buses, and transformers with double sided materials. Helps
+    /// clients that cull thin tubes seen edge on, at some rendering cost
+    #[arg(long)]
+    pub double_sided: bool,
+
+    /// Distance along X, in domain units, from the live scene to a compared
+    /// time step (the full extent of the data is about 2 units)
+    #[arg(long, default_value_t = 2.5, allow_hyphen_values = true)]
+    pub compare_offset: f32,
+
+    /// Turn the overview chart to face each viewer, so it can be read from
+    /// any side of the scene
+    #[arg(long)]
+    pub billboard_summary: bool,
+
+    /// Skip building the overview chart. Speeds up startup on large datasets
+    #[arg(long)]
+    pub no_summary: bool,
+
+    /// Start with the dataset title banner hidden
+    #[arg(long)]
+    pub no_title: bool,
+
+    /// Build the scene and overview chart before accepting connections. By
+    /// default this happens in the background, and early clients see the
+    /// scene fill in
+    #[arg(long)]
+    pub blocking_startup: bool,
+
+    /// Render charts at this multiple of their final resolution and
+    /// downscale, for sharper text at the cost of CPU time
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..=8))]
+    pub chart_supersample: u32,
+
+    /// Most points plotted per trace on charts. Longer traces keep the
+    /// lowest and highest sample of each stretch of time, so peaks survive.
+    /// Every time step is plotted if unset
+    #[arg(long, value_parser = clap::value_parser!(u32).range(2..))]
+    pub chart_points: Option<u32>,
+
+    /// Largest floorplan texture, in pixels per side, to send to clients.
+    /// Larger floorplans are split into tiles
+    #[arg(long, default_value_t = 8192, value_parser = clap::value_parser!(u32).range(64..))]
+    pub basemap_max_texture: u32,
+
+    /// Stretch the floorplan over the bounds of the data, ignoring its own
+    /// coordinates. Use when the floorplan is in a different coordinate
+    /// system than the data
+    #[arg(long)]
+    pub fit_basemap: bool,
+
+    /// Sample number shown for the first time step on probe charts. Use a
+    /// negative value to label time relative to an event
+    #[arg(long, default_value_t = 0, allow_hyphen_values = true)]
+    pub time_origin: i64,
+
+    /// Time step of an event to mark on probe charts
+    #[arg(long)]
+    pub event_step: Option<usize>,
+
+    /// Color theme for charts
+    #[arg(long, value_enum, default_value_t = ChartTheme::Light)]
+    pub chart_theme: ChartTheme,
+
+    /// Voltage (p.u.) marked with a dashed line on probe charts, for
+    /// datasets normalized to something other than 1.0
+    #[arg(long, default_value_t = 1.0, value_parser = parse_positive)]
+    pub chart_nominal: f32,
+
+    /// Also mark the safe voltage limits on probe charts
+    #[arg(long)]
+    pub chart_limits: bool,
+
+    /// JSON file to restore probes from at startup. The "Save Probes"
+    /// method writes to this file
+    #[arg(long)]
+    pub probes: Option<PathBuf>,
+
+    /// OBJ file to use for generator glyphs instead of the built in mesh
+    #[arg(long)]
+    pub generator_obj: Option<PathBuf>,
+
+    /// OBJ file to use for hazard cells instead of the built in mesh
+    #[arg(long)]
+    pub hazard_obj: Option<PathBuf>,
+
+    /// OBJ file to use for line flow chevrons instead of the built in mesh.
+    /// It should be textured along its length
+    #[arg(long)]
+    pub line_flow_obj: Option<PathBuf>,
+
+    /// OBJ file to use for probes instead of the built in mesh
+    #[arg(long)]
+    pub probe_obj: Option<PathBuf>,
+
+    /// OBJ file to use for the probe chart close button instead of the
+    /// built in mesh
+    #[arg(long)]
+    pub close_obj: Option<PathBuf>,
+
+    /// Pause playback after this many seconds without any client method
+    /// calls, so unattended servers stop recomputing. Off by default, so
+    /// playback continues until stopped
+    #[arg(long, default_value = "off", value_parser = parse_idle_timeout)]
+    pub idle_timeout: IdleTimeout,
+
+    /// Number of times to retry advertising the server over mDNS if the
+    /// first attempt fails
+    #[arg(long, default_value_t = 3)]
+    pub mdns_retries: u32,
+
+    /// Advertise diagnostic methods, such as "Dump Instances", that expose
+    /// internal state. Intended for debugging clients
+    #[arg(long)]
+    pub allow_debug_methods: bool,
+
+    /// Write a looping GIF of the overview chart, with a time marker moving
+    /// across it, to this path and exit without starting a server
+    #[arg(long)]
+    pub export_animation: Option<PathBuf>,
+
+    /// Line quantity plotted in the exported animation: "voltage", "real",
+    /// "reactive", or "load"
+    #[arg(long, default_value = "voltage", value_parser = parse_summary_metric)]
+    pub animation_metric: SummaryMetric,
+
+    /// Log more detail: -v for info, -vv for debug, -vvv for trace. Only
+    /// errors are logged by default. RUST_LOG takes precedence
+    #[arg(short, long, action = clap::ArgAction::Count)]
+    pub verbose: u8,
+
+    /// Write log records as JSON objects, one per line
+    #[arg(long)]
+    pub log_json: bool,
+
+    /// Time step to start at. Clamped to the steps in the dataset. Defaults
+    /// to the middle of the dataset
+    #[arg(long)]
+    pub start_step: Option<usize>,
+}
+
+impl Arguments {
+    /// Checks constraints between arguments that cannot be expressed per
+    /// argument. Exits with a usage error if any fail.
+    pub fn validate(&self) {
+        let defaults = Domain::default();
+
+        let tube_min = self.tube_min.unwrap_or(defaults.tube_min);
+        let tube_max = self.tube_max.unwrap_or(defaults.tube_max);
+
+        if tube_min >= tube_max {
+            Self::command()
+                .error(
+                    ErrorKind::ArgumentConflict,
+                    format!("tube min ({tube_min}) must be less than tube max ({tube_max})"),
+                )
+                .exit();
+        }
+    }
+}
+
+/// An idle period, or `None` to never time out.
+#[derive(Debug, Clone, Copy)]
+pub struct IdleTimeout(pub Option<Duration>);
+
+/// Parses an idle timeout in seconds, or "off".
+fn parse_idle_timeout(s: &str) -> Result<IdleTimeout, String> {
+    if s.eq_ignore_ascii_case("off") {
+        return Ok(IdleTimeout(None));
+    }
+
+    let secs = parse_positive(s)?;
+    let timeout = Duration::try_from_secs_f32(secs).map_err(|e| format!("{secs}: {e}"))?;
+
+    Ok(IdleTimeout(Some(timeout)))
+}
+
+/// Parses a summary metric name.
+fn parse_summary_metric(s: &str) -> Result<SummaryMetric, String> {
+    SummaryMetric::parse(s).ok_or_else(|| format!("unknown metric {s:?}"))
+}
+
+/// Parses a strictly positive, finite floating point argument.
+fn parse_positive(s: &str) -> Result<f32, String> {
+    let v: f32 = s.parse().map_err(|e| format!("{e}"))?;
+
+    if v.is_finite() && v > 0.0 {
+        Ok(v)
+    } else {
+        Err(format!("{v} must be positive and finite"))
+    }
+}
+
+/// Parses a hazard plane given as `VOLTAGE` or `VOLTAGE:SEVERITY`.
+fn parse_hazard_plane(s: &str) -> Result<HazardPlane, String> {
+    let (voltage, severity) = s.split_once(':').unwrap_or((s, "1"));
+
+    let voltage = parse_positive(voltage)?;
+    let severity: u8 = severity.parse().map_err(|e| format!("{e}"))?;
+
+    Ok(HazardPlane { voltage, severity })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn positive_rejects_non_finite_values() {
+        for s in ["inf", "-inf", "NaN", "0", "-1"] {
+            assert!(parse_positive(s).is_err(), "{s} was accepted");
+        }
+
+        assert_eq!(parse_positive("2.5"), Ok(2.5));
+    }
+
+    #[test]
+    fn idle_timeout_rejects_unrepresentable_durations() {
+        assert!(parse_idle_timeout("inf").is_err());
+        assert!(parse_idle_timeout("1e30").is_err());
+        assert!(parse_idle_timeout("-5").is_err());
+
+        assert!(parse_idle_timeout("OFF").unwrap().0.is_none());
+        assert_eq!(
+            parse_idle_timeout("1.5").unwrap().0,
+            Some(Duration::from_millis(1500))
+        );
+    }
+
+    #[test]
+    fn idle_timeout_is_off_by_default() {
+        let args = Arguments::parse_from(["noodle_grid", "data.capnp"]);
+
+        assert!(args.idle_timeout.0.is_none());
+    }
 }
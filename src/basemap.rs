@@ -1,44 +1,216 @@
+use std::io::Cursor;
+
 use colabrodo_common::components::BufferState;
 use colabrodo_server::{server::*, server_messages::*};
 
+use image::GenericImageView;
 use nalgebra_glm::{self as glm, vec2, vec3, Mat4};
 
-use crate::{domain::Domain, geometry::make_plane, PowerSystem};
-/// Creates a textured basemap plane from the system's floorplan image.
+use crate::{domain::Domain, dots::Floorplan, geometry::make_plane, PowerSystem};
+/// Creates textured basemap planes from the system's floorplan image.
 ///
 /// This constructs a plane aligned with the floorplan's lower-left and upper-right
 /// coordinates, using a texture generated from the embedded image data.
+///
+/// Images larger than `max_texture` pixels on a side are split into a grid of
+/// tiles, each with its own texture, so clients with texture size limits can
+/// still show them. Returns no entities if there is no floorplan.
+///
+/// With `fit`, the floorplan coordinates are ignored and the image is
+/// stretched over the data bounds instead.
 pub fn make_basemap(
     state: &mut ServerState,
     system: &PowerSystem,
     domain: &Domain,
-) -> Option<EntityReference> {
-    let fp = system.floor_plan.as_ref()?; // Return None if no floorplan exists
+    max_texture: u32,
+    fit: bool,
+) -> Vec<EntityReference> {
+    let Some(fp) = system.floor_plan.as_ref() else {
+        return vec![]; // No floorplan exists
+    };
+
+    let (ll, ur) = if fit {
+        (
+            glm::vec2(domain.data_x.x, domain.data_y.x),
+            glm::vec2(domain.data_x.y, domain.data_y.y),
+        )
+    } else {
+        check_floorplan_bounds(fp, domain);
+
+        (glm::vec2(fp.ll_x, fp.ll_y), glm::vec2(fp.ur_x, fp.ur_y))
+    };
 
     // Convert floorplan world coordinates to normalized domain space
-    let ll = vec2(domain.lerp_x(fp.ll_x as f32), domain.lerp_y(fp.ll_y as f32));
-    let ur = vec2(domain.lerp_x(fp.ur_x as f32), domain.lerp_y(fp.ur_y as f32));
+    let ll = vec2(domain.lerp_x(ll.x as f32), domain.lerp_y(ll.y as f32));
+    let ur = vec2(domain.lerp_x(ur.x as f32), domain.lerp_y(ur.y as f32));
 
     // Compute center and scale for transform
     let center = (ll + ur) / 2.0;
     let scale = (ll - ur).abs();
 
-    // Build transform: translate to center, then scale the plane
-    let transform = glm::scale(
-        &glm::translate(&Mat4::identity(), &vec3(center.x, 0.0, center.y)),
-        &vec3(scale.x, 1.0, scale.y),
-    );
-
     // If no image data exists, skip rendering
     if fp.data.is_empty() {
         log::warn!("No basemap data!");
-        return None;
+        return vec![];
+    }
+
+    // Image rows run from high Y down. Without the Y flip, high Y is at high
+    // Z, so the image is mirrored to match
+    let flipped;
+    let data = if domain.y_flip {
+        &fp.data
+    } else {
+        match flip_vertical(&fp.data) {
+            Ok(d) => {
+                flipped = d;
+                &flipped
+            }
+            Err(e) => {
+                log::warn!("Unable to decode basemap for flipping: {e}");
+                return vec![];
+            }
+        }
+    };
+
+    let dimensions = image::ImageReader::new(Cursor::new(data))
+        .with_guessed_format()
+        .ok()
+        .and_then(|r| r.into_dimensions().ok());
+
+    let needs_tiling = dimensions.is_some_and(|(w, h)| w > max_texture || h > max_texture);
+
+    if !needs_tiling {
+        // Build transform: translate to center, then scale the plane
+        let transform = glm::scale(
+            &glm::translate(
+                &Mat4::identity(),
+                &vec3(center.x, domain.scene_y_offset, center.y),
+            ),
+            &vec3(scale.x, 1.0, scale.y),
+        );
+
+        return vec![make_textured_plane(
+            state,
+            data.clone(),
+            transform,
+            "Basemap",
+        )];
+    }
+
+    let image = match image::load_from_memory(data) {
+        Ok(image) => image,
+        Err(e) => {
+            log::warn!("Unable to decode basemap for tiling: {e}");
+            return vec![];
+        }
+    };
+
+    let (width, height) = image.dimensions();
+    let columns = width.div_ceil(max_texture);
+    let rows = height.div_ceil(max_texture);
+
+    log::info!("Splitting {width}x{height} basemap into {columns}x{rows} tiles");
+
+    // Image row 0 lies along the low Z edge of the plane
+    let origin = center - scale / 2.0;
+    let mut tiles = Vec::with_capacity((columns * rows) as usize);
+
+    for row in 0..rows {
+        for col in 0..columns {
+            let x = col * max_texture;
+            let y = row * max_texture;
+            let tile_w = max_texture.min(width - x);
+            let tile_h = max_texture.min(height - y);
+
+            let mut png = Cursor::new(Vec::<u8>::new());
+
+            if let Err(e) = image
+                .crop_imm(x, y, tile_w, tile_h)
+                .write_to(&mut png, image::ImageFormat::Png)
+            {
+                log::warn!("Unable to encode basemap tile {col},{row}: {e}");
+                continue;
+            }
+
+            // Place the tile over its share of the floorplan
+            let lo = vec2(x as f32 / width as f32, y as f32 / height as f32);
+            let size = vec2(tile_w as f32 / width as f32, tile_h as f32 / height as f32);
+
+            let tile_center = origin + (lo + size / 2.0).component_mul(&scale);
+            let tile_scale = size.component_mul(&scale);
+
+            let floor = vec3(tile_center.x, domain.scene_y_offset, tile_center.y);
+
+            let transform = glm::scale(
+                &glm::translate(&Mat4::identity(), &floor),
+                &vec3(tile_scale.x, 1.0, tile_scale.y),
+            );
+
+            tiles.push(make_textured_plane(
+                state,
+                png.into_inner(),
+                transform,
+                &format!("Basemap {col},{row}"),
+            ));
+        }
+    }
+
+    tiles
+}
+
+/// Warns if the floorplan corners are out of order, or if the floorplan does
+/// not overlap the data at all. Either usually means the floorplan uses a
+/// different coordinate system than the data.
+fn check_floorplan_bounds(fp: &Floorplan, domain: &Domain) {
+    if fp.ll_x >= fp.ur_x || fp.ll_y >= fp.ur_y {
+        log::warn!(
+            "Floorplan lower left corner ({}, {}) is not below and left of its upper right corner ({}, {}); the image may be placed wrongly",
+            fp.ll_x,
+            fp.ll_y,
+            fp.ur_x,
+            fp.ur_y
+        );
     }
 
+    let overlaps = |a: f64, b: f64, range: glm::DVec2| a.min(b) <= range.y && a.max(b) >= range.x;
+
+    if !overlaps(fp.ll_x, fp.ur_x, domain.data_x) || !overlaps(fp.ll_y, fp.ur_y, domain.data_y) {
+        log::warn!(
+            "Floorplan X {}..{}, Y {}..{} does not overlap the data X {}..{}, Y {}..{}; check that both use the same coordinate system, or use --fit-basemap",
+            fp.ll_x,
+            fp.ur_x,
+            fp.ll_y,
+            fp.ur_y,
+            domain.data_x.x,
+            domain.data_x.y,
+            domain.data_y.x,
+            domain.data_y.y
+        );
+    }
+}
+
+/// Mirrors an encoded image top to bottom, re-encoding it as PNG.
+fn flip_vertical(data: &[u8]) -> Result<Vec<u8>, image::ImageError> {
+    let mut png = Cursor::new(Vec::<u8>::new());
+
+    image::load_from_memory(data)?
+        .flipv()
+        .write_to(&mut png, image::ImageFormat::Png)?;
+
+    Ok(png.into_inner())
+}
+
+/// Creates a plane entity textured with an encoded image.
+fn make_textured_plane(
+    state: &mut ServerState,
+    data: Vec<u8>,
+    transform: Mat4,
+    name: &str,
+) -> EntityReference {
     // Create a buffer from the image bytes
     let buf = state
         .buffers
-        .new_component(BufferState::new_from_bytes(fp.data.clone()));
+        .new_component(BufferState::new_from_bytes(data));
 
     // Create a full buffer view
     let view = state
@@ -47,20 +219,20 @@ pub fn make_basemap(
 
     // Create image from view
     let image = state.images.new_component(ServerImageState {
-        name: Some("Basemap Image".into()),
+        name: Some(format!("{name} Image")),
         source: ServerImageStateSource::new_buffer(view),
     });
 
     // Create texture referencing the image
     let tex = state.textures.new_component(ServerTextureState {
-        name: Some("Basemap Texture".into()),
+        name: Some(format!("{name} Texture")),
         image,
         sampler: None,
     });
 
     // Create a material that uses the texture
     let mat = state.materials.new_component(ServerMaterialState {
-        name: Some("Basemap Material".into()),
+        name: Some(format!("{name} Material")),
         mutable: ServerMaterialStateUpdatable {
             pbr_info: Some(ServerPBRInfo {
                 base_color: [1.0, 1.0, 1.0, 1.0], // White base color (fully unlit)
@@ -81,8 +253,8 @@ pub fn make_basemap(
     let geom = make_plane(state, transform, mat);
 
     // Register the geometry as a renderable entity in the scene
-    Some(state.entities.new_component(ServerEntityState {
-        name: Some("Basemap".into()),
+    state.entities.new_component(ServerEntityState {
+        name: Some(name.into()),
         mutable: ServerEntityStateUpdatable {
             representation: Some(ServerEntityRepresentation::new_render(
                 ServerRenderRepresentation {
@@ -92,5 +264,5 @@ pub fn make_basemap(
             )),
             ..Default::default()
         },
-    }))
+    })
 }
@@ -1,74 +1,348 @@
+use std::ops::{Range, RangeInclusive};
+
 use image::ExtendedColorType;
 use itertools::Itertools;
 use plotters::prelude::*;
 
-use crate::PowerSystem;
+use crate::{domain::Domain, instance::LineColoring, LineState, PowerSystem};
+
+/// Color scheme for generated charts.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum ChartTheme {
+    /// Dark text on a white background
+    #[default]
+    Light,
+    /// Light text on a dark background, for dark clients and passthrough
+    Dark,
+}
+
+impl ChartTheme {
+    fn background(self) -> RGBColor {
+        match self {
+            ChartTheme::Light => WHITE,
+            ChartTheme::Dark => RGBColor(32, 32, 36),
+        }
+    }
+
+    /// Color for text and axes
+    fn foreground(self) -> RGBColor {
+        match self {
+            ChartTheme::Light => BLACK,
+            ChartTheme::Dark => RGBColor(225, 225, 225),
+        }
+    }
+
+    /// Color for the overview voltage traces
+    fn overview_series(self) -> RGBColor {
+        match self {
+            ChartTheme::Light => RGBColor(120, 120, 255),
+            ChartTheme::Dark => RGBColor(140, 170, 255),
+        }
+    }
+
+    /// Colors for the power and voltage series of a line chart
+    fn detail_series(self) -> (RGBColor, RGBColor) {
+        match self {
+            ChartTheme::Light => (BLUE, RED),
+            ChartTheme::Dark => (RGBColor(90, 180, 255), RGBColor(255, 120, 100)),
+        }
+    }
+}
+
+/// How the time axis of a line chart is labeled and sampled.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TimeAxis {
+    /// Label shown for time step 0. Use the negative of an event step to
+    /// label time relative to that event
+    pub x_origin: i64,
+    /// Time step to mark with a labeled vertical line
+    pub event: Option<usize>,
+    /// Most points plotted per trace, see [`decimate`]. Every time step is
+    /// plotted if unset
+    pub max_points: Option<usize>,
+}
 
-/// Generates an overview time chart showing voltages over time for all lines.
+/// Thins a trace to about `max_points` points for plotting. Time steps are
+/// split into buckets, and the lowest and highest sample of each bucket are
+/// kept, in time order, so peaks survive. Points keep their time step.
+///
+/// Traces that already fit, or any trace without a limit, are kept whole.
+fn decimate(data: &[f32], max_points: Option<usize>) -> Vec<(usize, f32)> {
+    let buckets = match max_points {
+        Some(max) if data.len() > max => (max / 2).max(1),
+        _ => return data.iter().copied().enumerate().collect(),
+    };
+
+    let mut points = Vec::with_capacity(buckets * 2);
+
+    for bucket in 0..buckets {
+        let steps = bucket * data.len() / buckets..(bucket + 1) * data.len() / buckets;
+
+        match steps.minmax_by(|&a, &b| data[a].total_cmp(&data[b])) {
+            itertools::MinMaxResult::MinMax(lo, hi) => {
+                points.push((lo.min(hi), data[lo.min(hi)]));
+                points.push((lo.max(hi), data[lo.max(hi)]));
+            }
+            itertools::MinMaxResult::OneElement(t) => points.push((t, data[t])),
+            itertools::MinMaxResult::NoElements => {}
+        }
+    }
+
+    points
+}
+
+/// Horizontal reference levels drawn across the voltage axis of a line chart.
+#[derive(Debug, Clone, Copy)]
+pub struct VoltageReference {
+    /// Voltage (p.u.) of the nominal level, or `None` to leave it out
+    pub nominal: Option<f32>,
+    /// Lower and upper voltage limits (p.u.) to mark, if any
+    pub limits: Option<(f32, f32)>,
+}
+
+impl Default for VoltageReference {
+    fn default() -> Self {
+        Self {
+            nominal: Some(1.0),
+            limits: None,
+        }
+    }
+}
+
+impl VoltageReference {
+    /// Every level to draw, each flagged with whether it is the nominal.
+    fn levels(&self) -> impl Iterator<Item = (f32, bool)> {
+        let limits = self.limits.into_iter().flat_map(|(lo, hi)| [lo, hi]);
+
+        self.nominal
+            .map(|v| (v, true))
+            .into_iter()
+            .chain(limits.map(|v| (v, false)))
+    }
+}
+
+/// Line quantity plotted on the overview chart. All metrics use phase A.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SummaryMetric {
+    /// End voltage, in p.u.
+    #[default]
+    Voltage,
+    /// End real power
+    RealPower,
+    /// End reactive power
+    ReactivePower,
+    /// Line load, as a fraction of the rating
+    LineLoad,
+}
+
+impl SummaryMetric {
+    /// Parses a metric name, ignoring case.
+    pub fn parse(text: &str) -> Option<Self> {
+        match text.to_lowercase().as_str() {
+            "voltage" => Some(Self::Voltage),
+            "real" => Some(Self::RealPower),
+            "reactive" => Some(Self::ReactivePower),
+            "load" => Some(Self::LineLoad),
+            _ => None,
+        }
+    }
+
+    fn value(self, l: &LineState) -> f32 {
+        match self {
+            Self::Voltage => l.voltage.ea,
+            Self::RealPower => l.real_power.ea,
+            Self::ReactivePower => l.reactive_power.ea,
+            Self::LineLoad => l.line_load.a,
+        }
+    }
+
+    /// Axis description
+    fn desc(self) -> &'static str {
+        match self {
+            Self::Voltage => "volts",
+            Self::RealPower => "kW",
+            Self::ReactivePower => "kVAR",
+            Self::LineLoad => "load",
+        }
+    }
+
+    /// Vertical range to plot. Voltage uses a fixed range around 1.0 p.u.;
+    /// the others fit the data.
+    fn range(self, system: &PowerSystem) -> Range<f64> {
+        if self == Self::Voltage {
+            return 0.5..1.5;
+        }
+
+        let values = system.lines.iter().flatten().map(|l| self.value(l));
+
+        match values.minmax() {
+            // Identical values, such as no reactive power at all, are padded
+            // like a single value, so the axis is not empty
+            itertools::MinMaxResult::MinMax(lo, hi) if lo < hi => lo as f64..hi as f64,
+            itertools::MinMaxResult::MinMax(v, _) | itertools::MinMaxResult::OneElement(v) => {
+                v as f64 - 1.0..v as f64 + 1.0
+            }
+            itertools::MinMaxResult::NoElements => 0.0..1.0,
+        }
+    }
+}
+
+/// A rendered time chart, along with where its data is plotted.
+pub struct TimeChart {
+    /// PNG image contents
+    pub png: Vec<u8>,
+    /// Horizontal pixel range of the plotting area, excluding margins and labels
+    pub plot_x: Range<i32>,
+}
+
+/// Default size of probe and overview charts, in pixels.
+pub const CHART_SIZE: (u32, u32) = (1024, 768);
+
+/// Width of a chart plane in the scene, given its width at [`CHART_SIZE`].
+///
+/// Planes keep their height, and their width follows the aspect ratio of the
+/// image, so a chart of any size is shown without stretching.
+pub fn chart_plane_width(default_width: f32, size: (u32, u32)) -> f32 {
+    let aspect = |(w, h): (u32, u32)| w as f32 / h as f32;
+
+    default_width * aspect(size) / aspect(CHART_SIZE)
+}
+
+/// Accepted chart widths and heights, in pixels before supersampling.
+pub const CHART_SIDE_RANGE: RangeInclusive<u32> = 128..=4096;
+
+/// Most pixels rendered for one chart, after supersampling. Each costs three
+/// bytes while drawing.
+pub const MAX_CHART_RENDER_PIXELS: u64 = 64 * 1024 * 1024;
+
+/// Generates an overview time chart showing a metric over time for all lines.
 ///
 /// # Arguments
 /// * `system` - Reference to the loaded `PowerSystem`
+/// * `metric` - Line quantity to plot
 /// * `width` - Width of the generated image in pixels
 /// * `height` - Height of the generated image in pixels
+/// * `supersample` - Render at this multiple of the output size, then downscale
+/// * `theme` - Color scheme to draw with
+/// * `max_points` - Most points plotted per line, see [`decimate`]
 ///
 /// # Returns
-/// * A PNG image as a byte vector
-pub fn generate_time_chart(system: &PowerSystem, width: u32, height: u32) -> Vec<u8> {
+/// * A `TimeChart` with the PNG image and the pixel span of the plot area
+pub fn generate_time_chart(
+    system: &PowerSystem,
+    metric: SummaryMetric,
+    width: u32,
+    height: u32,
+    supersample: u32,
+    theme: ChartTheme,
+    max_points: Option<usize>,
+) -> TimeChart {
+    let s = supersample.max(1);
+    let (render_w, render_h) = (width * s, height * s);
+
     // Pre-allocate RGB buffer (3 bytes per pixel)
-    let mut buff = vec![0; (width * height * 3) as usize];
+    let mut buff = vec![0; (render_w * render_h * 3) as usize];
 
-    {
+    let plot_x = {
         // Create the root drawing area
-        let root = BitMapBackend::with_buffer(&mut buff, (width, height)).into_drawing_area();
+        let root = BitMapBackend::with_buffer(&mut buff, (render_w, render_h)).into_drawing_area();
+
+        root.fill(&theme.background()).unwrap();
 
-        root.fill(&WHITE).unwrap();
+        let fg = theme.foreground();
 
         // lines are [time][line_i]
 
         let line_count = system.lines.first().map(|l| l.len()).unwrap_or(1);
-        let time_count = system.lines.len();
+
+        // The axis ends on the last step, so the indicator can reach the edge
+        let last_step = system.lines.len().saturating_sub(1).max(1);
 
         // Set up the chart with margin and labels
         let mut chart = ChartBuilder::on(&root)
-            .margin(10)
-            .caption(format!("{}: Details", system.title), ("sans-serif", 40))
-            .set_label_area_size(LabelAreaPosition::Left, 60)
-            .set_label_area_size(LabelAreaPosition::Right, 60)
-            .set_label_area_size(LabelAreaPosition::Bottom, 60)
-            .build_cartesian_2d(0..time_count, 0.5..1.5)
+            .margin(10 * s)
+            .caption(
+                format!("{}: Details", system.title),
+                ("sans-serif", 40 * s).into_font().color(&fg),
+            )
+            .set_label_area_size(LabelAreaPosition::Left, 60 * s)
+            .set_label_area_size(LabelAreaPosition::Right, 60 * s)
+            .set_label_area_size(LabelAreaPosition::Bottom, 60 * s)
+            .build_cartesian_2d(0..last_step, metric.range(system))
             .unwrap();
 
-        chart
-            .configure_mesh()
-            .disable_x_mesh()
+        let mut mesh = chart.configure_mesh();
+
+        mesh.disable_x_mesh()
             .disable_y_mesh()
             .x_labels(15)
             .max_light_lines(4)
-            .x_label_style(("arial", 24))
-            .y_label_style(("arial", 24))
+            .x_label_style(("arial", 24 * s).into_font().color(&fg))
+            .y_label_style(("arial", 24 * s).into_font().color(&fg))
             .x_desc("Sample")
-            .y_desc("volts")
-            .draw()
-            .unwrap();
+            .y_desc(metric.desc());
+
+        if theme != ChartTheme::Light {
+            mesh.axis_style(fg)
+                .axis_desc_style(("sans-serif", 24 * s).into_font().color(&fg));
+        }
+
+        mesh.draw().unwrap();
 
-        // Plot each line's voltage trace over time
+        // Plot each line's trace over time
         for line_i in 0..line_count {
-            let data: Vec<_> = system.lines.iter().map(|l| l[line_i].voltage.ea).collect();
+            let data: Vec<_> = system
+                .lines
+                .iter()
+                .filter_map(|l| l.get(line_i))
+                .map(|l| metric.value(l))
+                .collect();
 
             chart
                 .draw_series(LineSeries::new(
-                    data.iter()
-                        .enumerate()
-                        .map(|(time, &value)| (time, value as f64)),
-                    &RGBColor(120, 120, 255),
+                    decimate(&data, max_points)
+                        .into_iter()
+                        .map(|(time, value)| (time, value as f64)),
+                    theme.overview_series().stroke_width(s),
                 ))
                 .unwrap();
         }
 
         root.present().unwrap();
+
+        // Ask plotters where the data actually landed, so the time indicator
+        // can line up with it
+        chart.plotting_area().get_pixel_range().0
+    };
+
+    // Bring the plot area back into output pixels
+    let s = s as i32;
+    let plot_x = (plot_x.start / s)..(plot_x.end / s);
+
+    TimeChart {
+        png: downsample_to_png(buff, width, height, supersample),
+        plot_x,
     }
+}
+
+/// Downscales a supersampled RGB buffer to the output size and encodes it as
+/// a PNG.
+///
+/// A `supersample` of 1 (or 0) encodes the buffer as-is.
+fn downsample_to_png(source: Vec<u8>, width: u32, height: u32, supersample: u32) -> Vec<u8> {
+    let s = supersample.max(1);
 
-    buffer_to_png(&buff, width, height)
+    if s == 1 {
+        return buffer_to_png(&source, width, height);
+    }
+
+    let large = image::RgbImage::from_raw(width * s, height * s, source)
+        .expect("supersampled buffer size mismatch");
+
+    let small =
+        image::imageops::resize(&large, width, height, image::imageops::FilterType::Triangle);
+
+    buffer_to_png(small.as_raw(), width, height)
 }
 
 /// Converts an in-memory RGB buffer into a PNG image.
@@ -103,19 +377,54 @@ fn buffer_to_png(source: &[u8], width: u32, height: u32) -> Vec<u8> {
 /// # Arguments
 /// * `line_i` - Index of the line to chart
 /// * `system` - Reference to the loaded `PowerSystem`
+/// * `size` - Width and height of the generated image in pixels
+/// * `supersample` - Render at this multiple of the output size, then downscale
+/// * `show_kv` - Label voltage in kV, if the system has a nominal voltage
+/// * `show_rating` - Add the line rating, if known, to the caption
+/// * `theme` - Color scheme to draw with
+/// * `axis` - Time axis labeling and event marker
+/// * `reference` - Dashed voltage levels to draw behind the voltage trace
 ///
 /// # Returns
 /// * A PNG image as a byte vector
-pub fn generate_chart_for(line_i: usize, system: &PowerSystem) -> Vec<u8> {
+#[allow(clippy::too_many_arguments)]
+pub fn generate_chart_for(
+    line_i: usize,
+    system: &PowerSystem,
+    size: (u32, u32),
+    supersample: u32,
+    show_kv: bool,
+    show_rating: bool,
+    theme: ChartTheme,
+    axis: TimeAxis,
+    reference: VoltageReference,
+) -> Vec<u8> {
     // Extract real power and voltage data for the selected line
     let data_power: Vec<_> = system
         .lines
         .iter()
         //.map(|l| l[line_i].voltage.average_a())
-        .map(|l| l[line_i].real_power.average())
+        .filter_map(|l| l.get(line_i))
+        .map(|l| l.real_power.average())
+        .collect();
+
+    // Voltage is stored in p.u.; optionally convert to kV for display
+    let (voltage_scale, voltage_desc) = match system.nominal_voltage_kv {
+        Some(kv) if show_kv => (kv, "kV"),
+        _ => (1.0, "p.u."),
+    };
+
+    let data_voltage: Vec<_> = system
+        .lines
+        .iter()
+        .filter_map(|l| l.get(line_i))
+        .map(|l| l.voltage.ea * voltage_scale)
         .collect();
 
-    let data_voltage: Vec<_> = system.lines.iter().map(|l| l[line_i].voltage.ea).collect();
+    let references: Vec<_> = reference
+        .levels()
+        .map(|(v, nominal)| (v * voltage_scale, nominal))
+        .collect();
 
     // Calculate min and max for scaling axes
     let power_minmax = match data_power.iter().minmax() {
@@ -123,71 +432,506 @@ pub fn generate_chart_for(line_i: usize, system: &PowerSystem) -> Vec<u8> {
         _ => (0.0, 1.0),
     };
 
-    let voltage_minmax = match data_voltage.iter().minmax() {
+    // The voltage axis always spans the reference levels, so they stay visible
+    let voltage_minmax = match data_voltage
+        .iter()
+        .chain(references.iter().map(|(v, _)| v))
+        .minmax()
+    {
         itertools::MinMaxResult::MinMax(&a, &b) => (a, b),
         _ => (0.0, 1.0),
     };
 
-    let size = (1024u32, 768u32);
-    let mut buff = vec![0; (size.0 * size.1 * 3) as usize];
+    let s = supersample.max(1);
+    let render_size = (size.0 * s, size.1 * s);
 
-    let name = &system.line_meta[line_i];
+    let mut buff = vec![0; (render_size.0 * render_size.1 * 3) as usize];
+
+    let name = system
+        .line_meta
+        .get(line_i)
+        .map(String::as_str)
+        .unwrap_or("Unknown");
+
+    let caption = match system.line_ratings.get(line_i).copied().flatten() {
+        Some(rating) if show_rating => format!("{name}: Details (rated {rating} A)"),
+        _ => format!("{name}: Details"),
+    };
 
     {
-        let root = BitMapBackend::with_buffer(&mut buff, size).into_drawing_area();
+        let root = BitMapBackend::with_buffer(&mut buff, render_size).into_drawing_area();
+
+        root.fill(&theme.background()).unwrap();
 
-        root.fill(&WHITE).unwrap();
+        let fg = theme.foreground();
+        let (power_color, voltage_color) = theme.detail_series();
 
         let mut chart = ChartBuilder::on(&root)
-            .margin(10)
-            .caption(format!("{name}: Details"), ("sans-serif", 40))
-            .set_label_area_size(LabelAreaPosition::Left, 60)
-            .set_label_area_size(LabelAreaPosition::Right, 60)
-            .set_label_area_size(LabelAreaPosition::Bottom, 40)
+            .margin(10 * s)
+            .caption(caption, ("sans-serif", 40 * s).into_font().color(&fg))
+            .set_label_area_size(LabelAreaPosition::Left, 60 * s)
+            .set_label_area_size(LabelAreaPosition::Right, 60 * s)
+            .set_label_area_size(LabelAreaPosition::Bottom, 40 * s)
             .build_cartesian_2d(0..data_power.len(), power_minmax.0..power_minmax.1)
             .unwrap()
             .set_secondary_coord(0..data_voltage.len(), voltage_minmax.0..voltage_minmax.1);
 
+        // Light keeps the plotters default styling
+        let themed = theme != ChartTheme::Light;
+        let label_font = ("sans-serif", 16 * s).into_font().color(&fg);
+
+        let x_label = |x: &usize| format!("{}", *x as i64 + axis.x_origin);
+
         // Draw primary (power) axis and series
-        chart
-            .configure_mesh()
-            .disable_x_mesh()
+        let mut mesh = chart.configure_mesh();
+
+        mesh.disable_x_mesh()
             .disable_y_mesh()
             .x_labels(30)
+            .x_label_formatter(&x_label)
             .max_light_lines(4)
-            .y_desc("kW")
-            .draw()
-            .unwrap();
+            .y_desc("kW");
+
+        if themed {
+            mesh.axis_style(fg)
+                .label_style(label_font.clone())
+                .axis_desc_style(label_font.clone());
+        }
+
+        mesh.draw().unwrap();
 
         // Draw secondary (voltage) axis and series
-        chart
-            .configure_secondary_axes()
-            .y_desc("volts")
-            .draw()
-            .unwrap();
+        let mut secondary = chart.configure_secondary_axes();
+
+        secondary.y_desc(voltage_desc);
+
+        if themed {
+            secondary
+                .axis_style(fg)
+                .label_style(label_font.clone())
+                .axis_desc_style(label_font);
+        }
+
+        secondary.draw().unwrap();
+
+        // Reference levels are dashed and drawn first, so the data stays on top
+        for &(level, nominal) in &references {
+            let style = if nominal {
+                voltage_color.mix(0.6).stroke_width(s)
+            } else {
+                fg.mix(0.4).stroke_width(s)
+            };
+
+            chart
+                .draw_secondary_series(DashedLineSeries::new(
+                    [(0, level), (data_voltage.len(), level)],
+                    12 * s,
+                    8 * s,
+                    style,
+                ))
+                .unwrap();
+        }
 
         chart
             .draw_series(LineSeries::new(
-                data_power
-                    .iter()
-                    .enumerate()
-                    .map(|(time, &value)| (time, value)),
-                &BLUE,
+                decimate(&data_power, axis.max_points),
+                power_color.stroke_width(s),
             ))
             .unwrap();
 
         chart
             .draw_secondary_series(LineSeries::new(
-                data_voltage
-                    .iter()
-                    .enumerate()
-                    .map(|(time, &value)| (time, value)),
-                &RED,
+                decimate(&data_voltage, axis.max_points),
+                voltage_color.stroke_width(s),
             ))
             .unwrap();
 
+        // Mark the event with a labeled vertical line across the plot
+        if let Some(event) = axis.event.filter(|&e| e < data_power.len()) {
+            chart
+                .draw_series(LineSeries::new(
+                    [(event, power_minmax.0), (event, power_minmax.1)],
+                    fg.stroke_width(s),
+                ))
+                .unwrap();
+
+            chart
+                .draw_series(std::iter::once(Text::new(
+                    "Event",
+                    (event, power_minmax.1),
+                    ("sans-serif", 20 * s).into_font().color(&fg),
+                )))
+                .unwrap();
+        }
+
         root.present().unwrap();
     }
 
-    buffer_to_png(&buff, size.0, size.1)
+    downsample_to_png(buff, size.0, size.1, supersample)
+}
+
+/// Size of the title banner image, in pixels.
+pub const TITLE_SIZE: (u32, u32) = (1024, 128);
+
+/// Draws the dataset title, with a line summarizing its size, for the
+/// on-scene banner.
+pub fn generate_title_banner(system: &PowerSystem, supersample: u32, theme: ChartTheme) -> Vec<u8> {
+    let size = TITLE_SIZE;
+
+    let s = supersample.max(1);
+    let render_size = (size.0 * s, size.1 * s);
+
+    let mut buff = vec![0; (render_size.0 * render_size.1 * 3) as usize];
+
+    let description = format!(
+        "{} lines, {} transformers, {} generators, {} time steps",
+        system.line_meta.len(),
+        system.tfs.first().map_or(0, |t| t.len()),
+        system.pvs.first().map_or(0, |g| g.len()),
+        system.lines.len(),
+    );
+
+    {
+        let root = BitMapBackend::with_buffer(&mut buff, render_size).into_drawing_area();
+
+        root.fill(&theme.background()).unwrap();
+
+        let s = s as i32;
+        let fg = theme.foreground();
+        let title_font = ("sans-serif", 56 * s).into_font().color(&fg);
+        let font = ("sans-serif", 28 * s).into_font().color(&fg);
+
+        root.draw(&Text::new(
+            system.title.as_str(),
+            (24 * s, 12 * s),
+            title_font,
+        ))
+        .unwrap();
+
+        root.draw(&Text::new(description, (24 * s, 84 * s), font))
+            .unwrap();
+
+        root.present().unwrap();
+    }
+
+    downsample_to_png(buff, size.0, size.1, supersample)
+}
+
+/// Generates a legend explaining how transformers are drawn.
+///
+/// Each phase is drawn as a colored tube spanning the heights of its two
+/// side voltages, and the tap range is taken from the data.
+///
+/// # Arguments
+/// * `system` - Reference to the loaded `PowerSystem`
+/// * `supersample` - Render at this multiple of the output size, then downscale
+/// * `theme` - Color scheme to draw with
+///
+/// # Returns
+/// * A PNG image as a byte vector
+pub fn generate_tf_legend(system: &PowerSystem, supersample: u32, theme: ChartTheme) -> Vec<u8> {
+    let size = (512u32, 512u32);
+
+    let s = supersample.max(1);
+    let render_size = (size.0 * s, size.1 * s);
+
+    let mut buff = vec![0; (render_size.0 * render_size.1 * 3) as usize];
+
+    // Tap range over all phases and time steps
+    let taps = system
+        .tfs
+        .iter()
+        .flatten()
+        .flat_map(|t| [t.tap.a, t.tap.b, t.tap.c]);
+
+    let tap_text = match taps.minmax() {
+        itertools::MinMaxResult::NoElements => "Taps: no transformers".to_string(),
+        itertools::MinMaxResult::OneElement(t) => format!("Taps: {t}"),
+        itertools::MinMaxResult::MinMax(lo, hi) => format!("Taps: {lo} to {hi}"),
+    };
+
+    // Approximations of the phase bands in the HSV texture
+    let phases = [
+        ("A", RGBColor(255, 102, 102)),
+        ("B", RGBColor(102, 255, 102)),
+        ("C", RGBColor(102, 102, 255)),
+    ];
+
+    {
+        let root = BitMapBackend::with_buffer(&mut buff, render_size).into_drawing_area();
+
+        root.fill(&theme.background()).unwrap();
+
+        let s = s as i32;
+        let fg = theme.foreground();
+        let title_font = ("sans-serif", 48 * s).into_font().color(&fg);
+        let font = ("sans-serif", 28 * s).into_font().color(&fg);
+
+        root.draw(&Text::new("Transformers", (24 * s, 24 * s), title_font))
+            .unwrap();
+
+        // One tube per phase, with the high and low ends labeled
+        for (i, (name, color)) in phases.iter().enumerate() {
+            let x = (60 + i as i32 * 90) * s;
+
+            root.draw(&Rectangle::new(
+                [(x, 120 * s), (x + 40 * s, 330 * s)],
+                color.filled(),
+            ))
+            .unwrap();
+
+            root.draw(&Text::new(*name, (x + 8 * s, 345 * s), font.clone()))
+                .unwrap();
+        }
+
+        // Explain the tube ends
+        for (i, line) in ["Tube ends sit", "at the from and", "to side voltage"]
+            .iter()
+            .enumerate()
+        {
+            let y = (180 + i as i32 * 35) * s;
+
+            root.draw(&Text::new(*line, (320 * s, y), font.clone()))
+                .unwrap();
+        }
+
+        root.draw(&Text::new(
+            "Height maps voltage, as on the ruler",
+            (24 * s, 410 * s),
+            font.clone(),
+        ))
+        .unwrap();
+
+        root.draw(&Text::new(tap_text, (24 * s, 455 * s), font))
+            .unwrap();
+
+        root.present().unwrap();
+    }
+
+    downsample_to_png(buff, size.0, size.1, supersample)
+}
+
+/// Generates a legend explaining line colors for a coloring source.
+///
+/// Each swatch approximates phase A's color band at one saturation level.
+///
+/// # Arguments
+/// * `coloring` - The active line coloring source
+/// * `domain` - Supplies the safe voltage limits
+/// * `supersample` - Render at this multiple of the output size, then downscale
+/// * `theme` - Color scheme to draw with
+///
+/// # Returns
+/// * A PNG image as a byte vector
+pub fn generate_color_legend(
+    coloring: LineColoring,
+    domain: &Domain,
+    supersample: u32,
+    theme: ChartTheme,
+) -> Vec<u8> {
+    let size = (512u32, 512u32);
+
+    let s = supersample.max(1);
+    let render_size = (size.0 * s, size.1 * s);
+
+    let mut buff = vec![0; (render_size.0 * render_size.1 * 3) as usize];
+
+    // Saturation levels, matching the line coloring in `instance`
+    let (title, entries) = match coloring {
+        LineColoring::VoltageSafety => (
+            "Line Voltage",
+            [
+                (0.2, format!("Low (< {})", domain.safe_min)),
+                (0.5, "Safe".to_string()),
+                (0.8, format!("High (> {})", domain.safe_max)),
+            ],
+        ),
+        LineColoring::LineLoad => (
+            "Line Load",
+            [
+                (0.2, "Unloaded".to_string()),
+                (0.5, "50% of rating".to_string()),
+                (0.8, "100%+ of rating".to_string()),
+            ],
+        ),
+    };
+
+    {
+        let root = BitMapBackend::with_buffer(&mut buff, render_size).into_drawing_area();
+
+        root.fill(&theme.background()).unwrap();
+
+        let s = s as i32;
+        let fg = theme.foreground();
+        let title_font = ("sans-serif", 48 * s).into_font().color(&fg);
+        let font = ("sans-serif", 32 * s).into_font().color(&fg);
+
+        root.draw(&Text::new(title, (24 * s, 24 * s), title_font))
+            .unwrap();
+
+        for (i, (saturation, label)) in entries.iter().enumerate() {
+            let y = (120 + i as i32 * 120) * s;
+
+            // Red hue at full value, as sampled from the HSV texture
+            let other = (255.0 * (1.0 - saturation)) as u8;
+
+            root.draw(&Rectangle::new(
+                [(24 * s, y), (124 * s, y + 80 * s)],
+                RGBColor(255, other, other).filled(),
+            ))
+            .unwrap();
+
+            root.draw(&Text::new(
+                label.as_str(),
+                (150 * s, y + 22 * s),
+                font.clone(),
+            ))
+            .unwrap();
+        }
+
+        root.present().unwrap();
+    }
+
+    downsample_to_png(buff, size.0, size.1, supersample)
+}
+
+/// Describes the scale printed on a ruler.
+pub struct RulerScale {
+    /// Heading printed along the ruler
+    pub title: String,
+    /// Values at the bottom and top edges of the ruler
+    pub range: Range<f32>,
+    /// Values to shade, such as the safe voltage band
+    pub band: Option<Range<f32>>,
+    /// Formats a tick value
+    pub label: fn(f32) -> String,
+}
+
+/// Number of labeled intervals on a ruler.
+const RULER_DIVISIONS: usize = 10;
+
+/// Draws a ruler texture with labeled ticks, spanning `scale.range` from the
+/// bottom edge of the image to the top.
+pub fn generate_ruler(scale: &RulerScale, supersample: u32, theme: ChartTheme) -> Vec<u8> {
+    let size = (384u32, 1024u32);
+
+    let s = supersample.max(1);
+    let render_size = (size.0 * s, size.1 * s);
+
+    let mut buff = vec![0; (render_size.0 * render_size.1 * 3) as usize];
+
+    {
+        let root = BitMapBackend::with_buffer(&mut buff, render_size).into_drawing_area();
+
+        root.fill(&theme.background()).unwrap();
+
+        let s = s as i32;
+        let fg = theme.foreground();
+        let height = render_size.1 as i32;
+        let span = scale.range.end - scale.range.start;
+
+        // Pixel row of a value. The top row is the end of the range
+        let row = |v: f32| ((1.0 - (v - scale.range.start) / span) * height as f32) as i32;
+
+        if let Some(band) = &scale.band {
+            root.draw(&Rectangle::new(
+                [(0, row(band.end)), (96 * s, row(band.start))],
+                GREEN.mix(0.3).filled(),
+            ))
+            .unwrap();
+        }
+
+        // Spine along the left edge
+        root.draw(&Rectangle::new([(0, 0), (6 * s, height)], fg.filled()))
+            .unwrap();
+
+        let font = ("sans-serif", 36 * s).into_font().color(&fg);
+
+        for i in 0..=RULER_DIVISIONS * 2 {
+            let v = scale.range.start + span * i as f32 / (RULER_DIVISIONS * 2) as f32;
+            let y = row(v);
+
+            // Labeled ticks alternate with shorter, unlabeled ones
+            if i % 2 == 1 {
+                root.draw(&Rectangle::new([(0, y - s), (48 * s, y + s)], fg.filled()))
+                    .unwrap();
+                continue;
+            }
+
+            root.draw(&Rectangle::new(
+                [(0, y - 2 * s), (96 * s, y + 2 * s)],
+                fg.filled(),
+            ))
+            .unwrap();
+
+            // Keep the labels at either end inside the image
+            let text_y = (y - 18 * s).clamp(0, height - 40 * s);
+
+            root.draw(&Text::new(
+                (scale.label)(v),
+                (112 * s, text_y),
+                font.clone(),
+            ))
+            .unwrap();
+        }
+
+        let title_font = ("sans-serif", 44 * s)
+            .into_font()
+            .transform(FontTransform::Rotate270)
+            .color(&fg);
+
+        root.draw(&Text::new(
+            scale.title.as_str(),
+            (size.0 as i32 * s - 64 * s, height / 2),
+            title_font,
+        ))
+        .unwrap();
+
+        root.present().unwrap();
+    }
+
+    downsample_to_png(buff, size.0, size.1, supersample)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decimate_keeps_short_and_empty_traces_whole() {
+        let data = [3.0, 1.0, 2.0];
+
+        assert_eq!(decimate(&data, None).len(), 3);
+        assert_eq!(decimate(&data, Some(3)).len(), 3);
+        // More points allowed than the trace has
+        assert_eq!(decimate(&data, Some(100)), [(0, 3.0), (1, 1.0), (2, 2.0)]);
+
+        assert!(decimate(&[], Some(4)).is_empty());
+        assert!(decimate(&[], None).is_empty());
+    }
+
+    #[test]
+    fn decimate_keeps_bucket_extremes_in_time_order() {
+        let data = [0.0, 5.0, 1.0, 2.0, -3.0, 2.5, 1.0, 1.0];
+
+        // Two buckets of four steps
+        assert_eq!(
+            decimate(&data, Some(4)),
+            [(0, 0.0), (1, 5.0), (4, -3.0), (5, 2.5)]
+        );
+
+        // Fewer than two points still keeps one bucket
+        assert_eq!(decimate(&data, Some(1)), [(1, 5.0), (4, -3.0)]);
+        assert_eq!(decimate(&data, Some(0)), [(1, 5.0), (4, -3.0)]);
+    }
+
+    #[test]
+    fn chart_plane_width_follows_aspect_ratio() {
+        assert_eq!(chart_plane_width(0.5, CHART_SIZE), 0.5);
+        assert_eq!(chart_plane_width(0.5, (2048, 1536)), 0.5);
+
+        // Twice as wide for the same height
+        assert!((chart_plane_width(0.5, (2048, 768)) - 1.0).abs() < 1e-6);
+        assert!((chart_plane_width(0.5, (512, 768)) - 0.25).abs() < 1e-6);
+    }
 }
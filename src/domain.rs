@@ -9,11 +9,33 @@ pub enum VoltageSafety {
     High,
 }
 
+/// An extra voltage limit that hazard cells are generated for.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HazardPlane {
+    /// Voltage (p.u.) of the limit.
+    pub voltage: f32,
+    /// Severity of crossing the limit. The safe range limits are severity 0.
+    pub severity: u8,
+}
+
+/// How data bounds are fitted into the scene. Both center the data at the
+/// origin, with its longer side spanning 2 units.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum DomainFit {
+    /// The data keeps its aspect ratio
+    #[default]
+    Square,
+    /// The shorter side is scaled as if the data covered the same area as a
+    /// 2 unit square, so long and thin data sets are widened rather than
+    /// drawn as a sliver. The longer side still fits in 2 units
+    Area,
+}
+
 /// Describes how to translate voltage and power values into visual dimensions (lengths, heights, and widths).
 ///
 /// This struct holds calibration parameters and scaling information
 /// for visualizing electrical properties like voltage and wattage.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 #[allow(dead_code)]
 pub struct Domain {
     /// Data domain minimum and maximum along X axis.
@@ -25,6 +47,8 @@ pub struct Domain {
     pub x_bounds: glm::DVec2,
     /// Visual bounds along Y axis after normalization.
     pub y_bounds: glm::DVec2,
+    /// How `x_bounds` and `y_bounds` were fitted to the data.
+    pub fit: DomainFit,
 
     /// Minimum height (visual) corresponding to minimum voltage.
     pub volt_height_min: f32,
@@ -36,6 +60,15 @@ pub struct Domain {
     /// Maximum "safe" voltage value.
     pub volt_max: f32,
 
+    /// Lowest voltage (p.u.) that is not flagged as a violation.
+    pub safe_min: f32,
+    /// Highest voltage (p.u.) that is not flagged as a violation.
+    pub safe_max: f32,
+
+    /// Extra voltage limits tested for hazard cells, on top of `safe_min`
+    /// and `safe_max`.
+    pub hazard_planes: Vec<HazardPlane>,
+
     /// Minimum tube width for visualization.
     pub tube_min: f32,
     /// Maximum tube width for visualization.
@@ -43,6 +76,45 @@ pub struct Domain {
 
     /// Maximum real or reactive power used for normalization.
     pub watt_bounds: f32,
+    /// Let real power tubes grow past `tube_max` for flows above
+    /// `watt_bounds`, instead of clamping.
+    pub open_tube_max: bool,
+
+    /// Maximum generator real power used for normalization.
+    pub gen_watt_bounds: f32,
+    /// Multiplier applied to generator glyph size.
+    pub gen_size_scale: f32,
+
+    /// Line load mapped to the maximum height. Line load is a fraction of
+    /// the line rating, where 1.0 is 100%.
+    pub line_load_max: f32,
+
+    /// Vertical exaggeration multiplier applied to all data-driven heights.
+    pub exaggeration: f32,
+
+    /// Horizontal spacing between phases, so that overlapping phase tubes
+    /// can be told apart.
+    pub phase_offset: f32,
+    /// Distance of each phase of a transformer from its location. The three
+    /// phases are spread evenly around it, so their tubes do not overlap.
+    pub tf_phase_radius: f32,
+
+    /// Height of the floor. Everything in the scene is raised by this much.
+    pub scene_y_offset: f32,
+
+    /// Map increasing data Y to decreasing scene Z, as for north-up maps
+    /// viewed from the south. Turn off for data with the opposite convention.
+    pub y_flip: bool,
+
+    /// Length of each dash in ground lines, or `None` for solid lines.
+    pub ground_dash: Option<f32>,
+    /// Gap between dashes in ground lines.
+    pub ground_gap: f32,
+
+    /// Position of a probe's chart handle, relative to the probe.
+    pub probe_handle_offset: glm::Vec3,
+    /// Position of a probe's chart, relative to its handle.
+    pub probe_chart_offset: glm::Vec3,
 }
 
 impl Default for Domain {
@@ -52,22 +124,49 @@ impl Default for Domain {
             data_y: Default::default(),
             x_bounds: Default::default(),
             y_bounds: Default::default(),
+            fit: DomainFit::Square,
             volt_height_min: 0.0,
             volt_height_max: 1.5,
             volt_min: 0.9,
             volt_max: 1.1,
+            safe_min: 0.95,
+            safe_max: 1.05,
+            hazard_planes: Vec::new(),
             tube_min: 0.001,
             tube_max: 0.03,
             watt_bounds: 1700.0,
+            open_tube_max: false,
+            gen_watt_bounds: 1700.0,
+            gen_size_scale: 2.0,
+            line_load_max: 2.0,
+            exaggeration: 1.0,
+            phase_offset: 0.001,
+            tf_phase_radius: 0.02,
+            scene_y_offset: 0.0,
+            y_flip: true,
+            ground_dash: None,
+            ground_gap: 0.01,
+            probe_handle_offset: glm::vec3(0.25, 1.0, 0.0),
+            probe_chart_offset: glm::vec3(0.0, 0.25, 0.0),
         }
     }
 }
 
 impl Domain {
-    /// Create a `Domain` from raw data bounds, setting up normalized visual bounds.
-    pub fn new(bound_min: glm::DVec2, bound_max: glm::DVec2) -> Self {
+    /// Create a `Domain` from raw data bounds, setting up normalized visual
+    /// bounds fitted to the scene as given by `fit`.
+    pub fn new(bound_min: glm::DVec2, bound_max: glm::DVec2, fit: DomainFit) -> Self {
         let range = bound_max - bound_min;
-        let max_dim = glm::DVec2::repeat(range.max() / 2.0);
+
+        // Half the data extent that maps onto 2 scene units, per axis
+        let max_dim = match fit {
+            DomainFit::Area if range.min() > 0.0 => {
+                // Fit by area, but never let the longer side leave the box
+                let area_side = (range.x * range.y).sqrt();
+                glm::max(&range, area_side) / 2.0
+            }
+            _ => glm::DVec2::repeat(range.max() / 2.0),
+        };
         let center = (bound_min + bound_max) / 2.0;
 
         // Create a normalized square centered around the data center
@@ -79,34 +178,109 @@ impl Domain {
             data_y: glm::DVec2::new(bound_min.y, bound_max.y),
             x_bounds: glm::DVec2::new(nl.x, nh.x),
             y_bounds: glm::DVec2::new(nl.y, nh.y),
+            fit,
             ..Default::default()
         }
     }
 
+    /// Create a copy of this `Domain` with new data bounds, keeping all other
+    /// calibration parameters.
+    pub fn with_bounds(&self, bound_min: glm::DVec2, bound_max: glm::DVec2) -> Self {
+        let bounded = Self::new(bound_min, bound_max, self.fit);
+
+        Self {
+            data_x: bounded.data_x,
+            data_y: bounded.data_y,
+            x_bounds: bounded.x_bounds,
+            y_bounds: bounded.y_bounds,
+            ..self.clone()
+        }
+    }
+
+    /// Scales a base height by the vertical exaggeration factor.
+    #[inline]
+    pub fn exaggerate(&self, h: f32) -> f32 {
+        h * self.exaggeration
+    }
+
+    /// Offset between successive phases (A/B/C). Phase B is placed one
+    /// offset from phase A, and phase C two.
+    #[inline]
+    pub fn phase_offset(&self) -> glm::Vec3 {
+        glm::vec3(self.phase_offset, 0.0, -self.phase_offset)
+    }
+
+    /// Offset of one phase's conductor on a line running from `a` to `b`
+    /// (ground coordinates). Phases are fanned out perpendicular to the
+    /// line, `phase_offset` apart, so they read as parallel conductors
+    /// whatever the line direction. Phase A sits on the line itself.
+    pub fn phase_fan(&self, a: glm::Vec2, b: glm::Vec2, phase: usize) -> glm::Vec3 {
+        let dir = b - a;
+
+        if dir.norm_squared() < f32::EPSILON {
+            return self.phase_offset() * phase as f32;
+        }
+
+        let dir = dir.normalize();
+
+        glm::vec3(-dir.y, 0.0, dir.x) * (self.phase_offset * phase as f32)
+    }
+
+    /// Offset of one phase of a transformer from its location. Phases sit
+    /// a third of a turn apart on a circle of `tf_phase_radius`, with phase
+    /// A towards -Z, so the three vertical tubes form a triangle.
+    pub fn tf_phase_offset(&self, phase: usize) -> glm::Vec3 {
+        let angle = phase as f32 * std::f32::consts::TAU / 3.0;
+
+        glm::vec3(angle.sin(), 0.0, -angle.cos()) * self.tf_phase_radius
+    }
+
+    /// Offset that lifts the scene to the floor height.
+    #[inline]
+    pub fn scene_offset(&self) -> glm::Vec3 {
+        glm::vec3(0.0, self.scene_y_offset, 0.0)
+    }
+
+    /// Every voltage limit tested for hazard cells: the safe range limits at
+    /// severity 0, followed by any extra planes.
+    pub fn all_hazard_planes(&self) -> impl Iterator<Item = HazardPlane> + '_ {
+        [self.safe_min, self.safe_max]
+            .into_iter()
+            .map(|voltage| HazardPlane {
+                voltage,
+                severity: 0,
+            })
+            .chain(self.hazard_planes.iter().copied())
+    }
+
     /// Maps a voltage value to a visual height, using clamped linear interpolation.
     #[inline]
     pub fn voltage_to_height(&self, v: f32) -> f32 {
-        v.abs().clamped_lerp(
+        self.exaggerate(v.abs().clamped_lerp(
             self.volt_min,
             self.volt_max,
             self.volt_height_min,
             self.volt_height_max,
-        )
+        ))
     }
 
-    /// Maps a line load (normalized) to a visual height.
+    /// Maps a line load (fraction of rating) to a visual height.
     #[inline]
     pub fn line_load_to_height(&self, v: f32) -> f32 {
-        v.abs()
-            .clamped_lerp(0.0, 2.0, self.volt_height_min, self.volt_height_max)
+        self.exaggerate(v.abs().clamped_lerp(
+            0.0,
+            self.line_load_max,
+            self.volt_height_min,
+            self.volt_height_max,
+        ))
     }
 
     /// Determines if a given voltage is within a safe range.
     #[inline]
     pub fn voltage_safety(&self, v: f32) -> VoltageSafety {
-        if v < 0.95 {
+        if v < self.safe_min {
             VoltageSafety::Low
-        } else if v > 1.05 {
+        } else if v > self.safe_max {
             VoltageSafety::High
         } else {
             VoltageSafety::Safe
@@ -114,10 +288,17 @@ impl Domain {
     }
 
     /// Maps real power (watts) to a visual width.
+    ///
+    /// With `open_tube_max`, over-limit flows exceed `tube_max`.
     #[inline]
     pub fn real_power_to_width(&self, v: f32) -> f32 {
-        v.abs()
-            .clamped_lerp(0.0, self.watt_bounds, self.tube_min, self.tube_max)
+        if self.open_tube_max {
+            v.abs()
+                .clamped_lerp_low(0.0, self.watt_bounds, self.tube_min, self.tube_max)
+        } else {
+            v.abs()
+                .clamped_lerp(0.0, self.watt_bounds, self.tube_min, self.tube_max)
+        }
     }
 
     /// Maps reactive power (VARs) to a visual width.
@@ -127,6 +308,14 @@ impl Domain {
             .clamped_lerp(0.0, self.watt_bounds, self.tube_min, self.tube_max)
     }
 
+    /// Maps generator real power (watts) to a glyph size.
+    #[inline]
+    pub fn generator_size(&self, v: f32) -> f32 {
+        v.abs()
+            .clamped_lerp(0.0, self.gen_watt_bounds, self.tube_min, self.tube_max)
+            * self.gen_size_scale
+    }
+
     /// Maps a normalized X coordinate [-1, 1] back into real-world bounds.
     #[inline]
     pub fn lerp_x(&self, v: f32) -> f32 {
@@ -135,14 +324,163 @@ impl Domain {
 
     /// Maps a normalized Y coordinate [-1, 1] back into real-world bounds.
     ///
-    /// Note: Y-axis is flipped (positive is downward visually).
+    /// Note: with `y_flip`, the Y-axis is flipped (positive is downward
+    /// visually).
     #[inline]
     pub fn lerp_y(&self, v: f32) -> f32 {
-        (v as f64).lerp(
-            self.y_bounds.x,
-            self.y_bounds.y,
-            1.0_f64, // Flip for now
-            -1.0_f64,
-        ) as f32
+        let (lo, hi) = if self.y_flip {
+            (1.0_f64, -1.0_f64)
+        } else {
+            (-1.0_f64, 1.0_f64)
+        };
+
+        (v as f64).lerp(self.y_bounds.x, self.y_bounds.y, lo, hi) as f32
+    }
+
+    /// Size of the data in the scene, along X and Z. The larger of the two
+    /// is 2.
+    pub fn scene_extent(&self) -> glm::Vec2 {
+        let data = glm::vec2(
+            (self.data_x.y - self.data_x.x) as f32,
+            (self.data_y.y - self.data_y.x) as f32,
+        );
+        let bounds = glm::vec2(
+            (self.x_bounds.y - self.x_bounds.x) as f32,
+            (self.y_bounds.y - self.y_bounds.x) as f32,
+        );
+
+        (data * 2.0).component_div(&bounds)
+    }
+
+    /// Scene Z of the far edge of the data, whichever way Y is mapped.
+    /// Widgets such as the rulers stand along this edge.
+    #[inline]
+    pub fn far_edge_z(&self) -> f32 {
+        self.lerp_y(self.data_y.x as f32)
+            .min(self.lerp_y(self.data_y.y as f32))
+    }
+
+    /// Scene Z of the near edge of the data, opposite [`Self::far_edge_z`].
+    /// The title banner lies along this edge.
+    #[inline]
+    pub fn near_edge_z(&self) -> f32 {
+        self.lerp_y(self.data_y.x as f32)
+            .max(self.lerp_y(self.data_y.y as f32))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A 10 by 20 data set. Square fitting centers a 20 by 20 square on it.
+    fn domain(y_flip: bool) -> Domain {
+        Domain {
+            y_flip,
+            scene_y_offset: 0.5,
+            ..Domain::new(
+                glm::DVec2::new(0.0, 0.0),
+                glm::DVec2::new(10.0, 20.0),
+                DomainFit::Square,
+            )
+        }
+    }
+
+    fn assert_near(a: f32, b: f32) {
+        assert!((a - b).abs() < 1e-5, "{a} != {b}");
+    }
+
+    #[test]
+    fn lerp_x_known_points() {
+        for y_flip in [true, false] {
+            let d = domain(y_flip);
+
+            assert_near(d.lerp_x(-5.0), -1.0);
+            assert_near(d.lerp_x(5.0), 0.0);
+            assert_near(d.lerp_x(15.0), 1.0);
+            assert_near(d.lerp_x(0.0), -0.5);
+        }
+    }
+
+    #[test]
+    fn lerp_y_known_points() {
+        let flipped = domain(true);
+
+        assert_near(flipped.lerp_y(0.0), 1.0);
+        assert_near(flipped.lerp_y(10.0), 0.0);
+        assert_near(flipped.lerp_y(20.0), -1.0);
+
+        let unflipped = domain(false);
+
+        assert_near(unflipped.lerp_y(0.0), -1.0);
+        assert_near(unflipped.lerp_y(10.0), 0.0);
+        assert_near(unflipped.lerp_y(20.0), 1.0);
+    }
+
+    #[test]
+    fn lerp_round_trip() {
+        for y_flip in [true, false] {
+            let d = domain(y_flip);
+            let (lo, hi) = if y_flip { (1.0, -1.0) } else { (-1.0, 1.0) };
+
+            for v in [0.0f32, 2.5, 7.0, 10.0] {
+                let x = d.lerp_x(v) as f64;
+                let x = x.lerp(-1.0, 1.0, d.x_bounds.x, d.x_bounds.y);
+                assert_near(x as f32, v);
+
+                let y = d.lerp_y(v * 2.0) as f64;
+                let y = y.lerp(lo, hi, d.y_bounds.x, d.y_bounds.y);
+                assert_near(y as f32, v * 2.0);
+            }
+        }
+    }
+
+    #[test]
+    fn floor_offset_leaves_ground_plane_alone() {
+        let raised = domain(true);
+        let level = Domain {
+            scene_y_offset: 0.0,
+            ..raised.clone()
+        };
+
+        for v in [0.0f32, 5.0, 20.0] {
+            assert_eq!(raised.lerp_x(v), level.lerp_x(v));
+            assert_eq!(raised.lerp_y(v), level.lerp_y(v));
+        }
+
+        assert_eq!(raised.scene_offset(), glm::vec3(0.0, 0.5, 0.0));
+    }
+
+    #[test]
+    fn area_fit_keeps_elongated_data_in_the_box() {
+        // A 100 to 1 feeder
+        let d = Domain::new(
+            glm::DVec2::new(0.0, 0.0),
+            glm::DVec2::new(100.0, 1.0),
+            DomainFit::Area,
+        );
+
+        let extent = d.scene_extent();
+        assert_near(extent.x, 2.0);
+        assert!(extent.y <= 2.0);
+
+        // Ten times as wide as square fitting, by the square root of the
+        // aspect ratio
+        assert_near(extent.y, 0.2);
+
+        for (x, y) in [(0.0, 0.0), (100.0, 1.0)] {
+            assert!(d.lerp_x(x).abs() <= 1.0 + 1e-5);
+            assert!(d.lerp_y(y).abs() <= 1.0 + 1e-5);
+        }
+
+        // Square data fits the same either way
+        let square = Domain::new(
+            glm::DVec2::new(0.0, 0.0),
+            glm::DVec2::new(5.0, 5.0),
+            DomainFit::Area,
+        );
+
+        assert_near(square.scene_extent().x, 2.0);
+        assert_near(square.scene_extent().y, 2.0);
     }
 }
@@ -4,6 +4,8 @@ use std::{
     path::Path,
 };
 
+use nalgebra_glm::mix_scalar;
+
 /// A single 2D position
 #[derive(Debug, Clone, Copy)]
 pub struct Position {
@@ -11,6 +13,16 @@ pub struct Position {
     pub sy: f64,
 }
 
+impl Position {
+    pub fn lerp(&self, other: &Self, t: f32) -> Self {
+        let t = t as f64;
+        Self {
+            sx: mix_scalar(self.sx, other.sx, t),
+            sy: mix_scalar(self.sy, other.sy, t),
+        }
+    }
+}
+
 /// A pair of positions with a start and end point
 #[derive(Debug, Clone, Copy)]
 pub struct EndedPosition {
@@ -20,14 +32,51 @@ pub struct EndedPosition {
     pub ey: f64,
 }
 
+impl EndedPosition {
+    pub fn lerp(&self, other: &Self, t: f32) -> Self {
+        let t = t as f64;
+        Self {
+            sx: mix_scalar(self.sx, other.sx, t),
+            sy: mix_scalar(self.sy, other.sy, t),
+            ex: mix_scalar(self.ex, other.ex, t),
+            ey: mix_scalar(self.ey, other.ey, t),
+        }
+    }
+}
+
 /// A quantity that is split by phase
+#[derive(Debug, Clone, Copy)]
 pub struct Phased<T = f32> {
     pub a: T,
     pub b: T,
     pub c: T,
 }
 
+impl<T> Phased<T>
+where
+    T: Div<Output = T> + Copy,
+{
+    pub fn scaled(self, scale: T) -> Self {
+        Self {
+            a: self.a / scale,
+            b: self.b / scale,
+            c: self.c / scale,
+        }
+    }
+}
+
+impl Phased<f32> {
+    pub fn lerp(&self, other: &Self, t: f32) -> Self {
+        Self {
+            a: mix_scalar(self.a, other.a, t),
+            b: mix_scalar(self.b, other.b, t),
+            c: mix_scalar(self.c, other.c, t),
+        }
+    }
+}
+
 /// A quantity that is split by phase, and is different at start and end points
+#[derive(Debug, Clone, Copy)]
 pub struct EndPhased<T = f32> {
     pub sa: T,
     pub sb: T,
@@ -61,7 +110,21 @@ where
     }
 }
 
+impl EndPhased<f32> {
+    pub fn lerp(&self, other: &Self, t: f32) -> Self {
+        Self {
+            sa: mix_scalar(self.sa, other.sa, t),
+            sb: mix_scalar(self.sb, other.sb, t),
+            sc: mix_scalar(self.sc, other.sc, t),
+            ea: mix_scalar(self.ea, other.ea, t),
+            eb: mix_scalar(self.eb, other.eb, t),
+            ec: mix_scalar(self.ec, other.ec, t),
+        }
+    }
+}
+
 /// A timestep record of a line
+#[derive(Clone)]
 pub struct LineState {
     pub voltage: EndPhased,
     pub real_power: EndPhased,
@@ -70,7 +133,21 @@ pub struct LineState {
     pub line_load: Phased<f32>,
 }
 
+impl LineState {
+    /// Blends this state toward another by `t` (0.0 - 1.0).
+    pub fn lerp(&self, other: &Self, t: f32) -> Self {
+        Self {
+            voltage: self.voltage.lerp(&other.voltage, t),
+            real_power: self.real_power.lerp(&other.real_power, t),
+            reactive_power: self.reactive_power.lerp(&other.reactive_power, t),
+            loc: self.loc.lerp(&other.loc, t),
+            line_load: self.line_load.lerp(&other.line_load, t),
+        }
+    }
+}
+
 /// A timestep of a transformer
+#[derive(Clone)]
 pub struct TransformerState {
     pub voltage: EndPhased,
 
@@ -78,7 +155,25 @@ pub struct TransformerState {
 
     pub tap_changes: Phased<i32>,
 
-    pub loc: Position,
+    /// From and to bus locations. These are the same point for transformers
+    /// modeled at a single location
+    pub loc: EndedPosition,
+}
+
+impl TransformerState {
+    /// Blends this state toward another by `t` (0.0 - 1.0).
+    ///
+    /// Tap positions are discrete, and snap to the nearer state.
+    pub fn lerp(&self, other: &Self, t: f32) -> Self {
+        let nearest = if t < 0.5 { self } else { other };
+
+        Self {
+            voltage: self.voltage.lerp(&other.voltage, t),
+            tap: nearest.tap,
+            tap_changes: nearest.tap_changes,
+            loc: self.loc.lerp(&other.loc, t),
+        }
+    }
 }
 
 /// A known generator type
@@ -90,7 +185,7 @@ pub enum GeneratorType {
 }
 
 impl GeneratorType {
-    fn decode(text: &str) -> Self {
+    pub fn decode(text: &str) -> Self {
         match text {
             "pv" => GeneratorType::Solar,
             "battery" => GeneratorType::Battery,
@@ -101,6 +196,7 @@ impl GeneratorType {
 }
 
 /// A timestep of a generator (PV or battery)
+#[derive(Clone)]
 pub struct GeneratorState {
     pub voltage: Phased,
     pub angle: Phased,
@@ -108,6 +204,178 @@ pub struct GeneratorState {
     pub react: f32,
     pub loc: Position,
     pub ty: GeneratorType,
+    /// Nameplate real power, in the same units as `real`, if known
+    pub capacity: Option<f32>,
+}
+
+impl GeneratorState {
+    /// Blends this state toward another by `t` (0.0 - 1.0).
+    pub fn lerp(&self, other: &Self, t: f32) -> Self {
+        Self {
+            voltage: self.voltage.lerp(&other.voltage, t),
+            angle: self.angle.lerp(&other.angle, t),
+            real: mix_scalar(self.real, other.real, t),
+            react: mix_scalar(self.react, other.react, t),
+            loc: self.loc.lerp(&other.loc, t),
+            ty: self.ty,
+            capacity: self.capacity,
+        }
+    }
+}
+
+/// An element state that may hold non-finite values, such as those written by
+/// a power flow solver for steps that did not converge.
+trait Sanitize {
+    /// Whether every coordinate of the element's position is finite.
+    fn position_finite(&self) -> bool;
+
+    /// Calls `f` on every value that is not a position, always in the same
+    /// order.
+    fn for_each_value(&mut self, f: impl FnMut(&mut f32));
+}
+
+impl EndPhased<f32> {
+    fn for_each_value(&mut self, mut f: impl FnMut(&mut f32)) {
+        for v in [
+            &mut self.sa,
+            &mut self.sb,
+            &mut self.sc,
+            &mut self.ea,
+            &mut self.eb,
+            &mut self.ec,
+        ] {
+            f(v);
+        }
+    }
+}
+
+impl Phased<f32> {
+    fn for_each_value(&mut self, mut f: impl FnMut(&mut f32)) {
+        for v in [&mut self.a, &mut self.b, &mut self.c] {
+            f(v);
+        }
+    }
+}
+
+impl EndedPosition {
+    fn is_finite(&self) -> bool {
+        [self.sx, self.sy, self.ex, self.ey]
+            .iter()
+            .all(|v| v.is_finite())
+    }
+}
+
+impl Sanitize for LineState {
+    fn position_finite(&self) -> bool {
+        self.loc.is_finite()
+    }
+
+    fn for_each_value(&mut self, mut f: impl FnMut(&mut f32)) {
+        self.voltage.for_each_value(&mut f);
+        self.real_power.for_each_value(&mut f);
+        self.reactive_power.for_each_value(&mut f);
+        self.line_load.for_each_value(&mut f);
+    }
+}
+
+impl Sanitize for TransformerState {
+    fn position_finite(&self) -> bool {
+        self.loc.is_finite()
+    }
+
+    fn for_each_value(&mut self, f: impl FnMut(&mut f32)) {
+        self.voltage.for_each_value(f);
+    }
+}
+
+impl Sanitize for GeneratorState {
+    fn position_finite(&self) -> bool {
+        self.loc.sx.is_finite() && self.loc.sy.is_finite()
+    }
+
+    fn for_each_value(&mut self, mut f: impl FnMut(&mut f32)) {
+        self.voltage.for_each_value(&mut f);
+        self.angle.for_each_value(&mut f);
+        f(&mut self.real);
+        f(&mut self.react);
+
+        if let Some(capacity) = self.capacity.as_mut() {
+            f(capacity);
+        }
+    }
+}
+
+/// Sanitizes one kind of element, stored as `states[time][element]`.
+///
+/// Non-finite values are replaced with the last finite value of the same
+/// element, or the first one for leading values, so a failed step looks like
+/// its neighbors. Values that are never finite become zero. Elements with a
+/// non-finite position at any step cannot be placed, and are removed from
+/// every step.
+///
+/// Adds the number of values replaced at each step to `replaced`, and returns
+/// the indices of removed elements.
+fn sanitize_states<T: Sanitize>(states: &mut [Vec<T>], replaced: &mut [usize]) -> Vec<usize> {
+    let count = states.iter().map(Vec::len).max().unwrap_or(0);
+
+    let mut placeable = vec![true; count];
+
+    // Seed each value with its first finite sample
+    let mut held: Vec<Vec<Option<f32>>> = vec![Vec::new(); count];
+
+    for step in states.iter_mut() {
+        for (e_i, element) in step.iter_mut().enumerate() {
+            placeable[e_i] &= element.position_finite();
+
+            let held = &mut held[e_i];
+            let mut v_i = 0;
+
+            element.for_each_value(|v| {
+                if v_i == held.len() {
+                    held.push(None);
+                }
+
+                if held[v_i].is_none() && v.is_finite() {
+                    held[v_i] = Some(*v);
+                }
+
+                v_i += 1;
+            });
+        }
+    }
+
+    for (step, replaced) in states.iter_mut().zip(replaced.iter_mut()) {
+        for (e_i, element) in step.iter_mut().enumerate() {
+            let held = &mut held[e_i];
+            let mut v_i = 0;
+
+            element.for_each_value(|v| {
+                if v.is_finite() {
+                    held[v_i] = Some(*v);
+                } else {
+                    *v = held[v_i].unwrap_or(0.0);
+                    *replaced += 1;
+                }
+
+                v_i += 1;
+            });
+        }
+    }
+
+    let removed: Vec<usize> = (0..count).filter(|&e_i| !placeable[e_i]).collect();
+
+    if !removed.is_empty() {
+        for step in states.iter_mut() {
+            let mut e_i = 0;
+
+            step.retain(|_| {
+                e_i += 1;
+                placeable[e_i - 1]
+            });
+        }
+    }
+
+    removed
 }
 
 /// Options for a map to show power systems context
@@ -132,51 +400,257 @@ pub struct PowerSystem {
 
     pub line_meta: Vec<String>,
 
+    /// Ampacity of each line, if the dataset gives one. Line load for rated
+    /// lines was computed as current over this rating.
+    pub line_ratings: Vec<Option<f32>>,
+
     pub floor_plan: Option<Floorplan>,
+
+    /// Real-world voltage, in kV, that corresponds to 1.0 p.u. Used for
+    /// labels only.
+    pub nominal_voltage_kv: Option<f32>,
+}
+
+impl PowerSystem {
+    /// Replaces NaN and infinite values in every state, so they cannot reach
+    /// instance buffers. Each value holds the last finite sample of its
+    /// element. Elements whose position is not finite are removed, with a
+    /// warning naming them.
+    ///
+    /// Logs the number of values replaced in each affected time step, and
+    /// returns the total.
+    pub fn sanitize(&mut self) -> usize {
+        let steps = self.lines.len().max(self.tfs.len()).max(self.pvs.len());
+
+        let mut replaced = vec![0; steps];
+
+        let lines = sanitize_states(&mut self.lines, &mut replaced);
+        let tfs = sanitize_states(&mut self.tfs, &mut replaced);
+        let pvs = sanitize_states(&mut self.pvs, &mut replaced);
+
+        for &line_i in &lines {
+            let name = self.line_meta.get(line_i).map_or("", String::as_str);
+            log::warn!("Removed line {line_i} {name:?}, as its position is not finite");
+        }
+
+        for line_i in lines.into_iter().rev() {
+            if line_i < self.line_meta.len() {
+                self.line_meta.remove(line_i);
+            }
+
+            if line_i < self.line_ratings.len() {
+                self.line_ratings.remove(line_i);
+            }
+        }
+
+        for (kind, removed) in [("transformer", tfs), ("generator", pvs)] {
+            for i in removed {
+                log::warn!("Removed {kind} {i}, as its position is not finite");
+            }
+        }
+
+        for (step, &count) in replaced.iter().enumerate() {
+            if count > 0 {
+                log::warn!("Replaced {count} non-finite values at time step {step}");
+            }
+        }
+
+        replaced.iter().sum()
+    }
+
+    /// Checks that transformers and generators have as many time steps as
+    /// lines, since every kind is looked up with the same time step. A kind
+    /// with no elements at all has no time steps, and is fine.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error naming the first kind with a different count.
+    pub fn check_time_steps(&self) -> Result<(), anyhow::Error> {
+        let expected = self.lines.len();
+
+        for (kind, count) in [
+            ("transformers", self.tfs.len()),
+            ("generators", self.pvs.len()),
+        ] {
+            if count != 0 && count != expected {
+                anyhow::bail!(
+                    "Dataset has {expected} time steps for lines, but {count} for {kind}"
+                );
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Finds the number of time steps of one kind of element, as the length of
+/// its longest series. Shorter series are logged, as loaders hold their last
+/// sample.
+///
+/// # Errors
+///
+/// Returns an error naming the first element with no samples, as every
+/// element is looked up at every time step.
+pub fn series_length<N: std::fmt::Display>(
+    kind: &str,
+    series: impl IntoIterator<Item = (N, usize)>,
+) -> Result<usize, anyhow::Error> {
+    let series: Vec<_> = series.into_iter().collect();
+
+    let count = series.iter().map(|(_, len)| *len).max().unwrap_or(0);
+
+    for (name, len) in &series {
+        if *len == 0 {
+            anyhow::bail!("{kind} {name} has no samples");
+        }
+
+        if *len != count {
+            log::warn!("{kind} {name} has {len} samples, expected {count}");
+        }
+    }
+
+    Ok(count)
+}
+
+/// Options controlling how a dataset is loaded
+#[derive(Debug, Clone)]
+pub struct LoadOptions {
+    /// Memory-map the file rather than reading it into memory up front
+    pub use_mmap: bool,
+    /// Divisor applied to line load values, so that 1.0 means 100% of the
+    /// line rating. Use 100 for datasets that store percentages.
+    pub line_load_divisor: f32,
+    /// Disable the capnp traversal limit. Only use this for trusted files.
+    pub unlimited_traversal: bool,
+}
+
+impl Default for LoadOptions {
+    fn default() -> Self {
+        Self {
+            use_mmap: false,
+            line_load_divisor: 1.0,
+            unlimited_traversal: false,
+        }
+    }
+}
+
+/// Multiple of the file size, in words, that decoding may traverse. Decoding
+/// visits most data a small number of times, so this leaves plenty of room
+/// for honest files while bounding hostile ones.
+const TRAVERSAL_FILE_MULTIPLE: u64 = 8;
+
+/// Lower bound on the traversal limit, so small files are never starved.
+/// Matches the capnp default of 64 MiB.
+const MIN_TRAVERSAL_WORDS: u64 = 8 * 1024 * 1024;
+
+impl LoadOptions {
+    /// Builds capnp reader options for a file of `file_len` bytes.
+    ///
+    /// The traversal limit is sized from the file length, so a corrupt or
+    /// malicious pack fails to decode instead of exhausting memory.
+    fn reader_options(&self, file_len: u64) -> capnp::message::ReaderOptions {
+        let traversal_limit_in_words = if self.unlimited_traversal {
+            None
+        } else {
+            let words = (file_len / 8).saturating_mul(TRAVERSAL_FILE_MULTIPLE);
+            Some(words.max(MIN_TRAVERSAL_WORDS) as usize)
+        };
+
+        capnp::message::ReaderOptions {
+            traversal_limit_in_words,
+            ..Default::default()
+        }
+    }
 }
 
 /// Loads a `PowerSystem` from a Cap'n Proto file on disk.
 ///
 /// # Errors
 ///
-/// This function will return an error if the capnp file is incomplete or
-/// does not have sufficient timesteps for all elements.
-pub fn load_powersystem(path: &Path) -> Result<PowerSystem, anyhow::Error> {
+/// This function will return an error if the capnp file is incomplete, does
+/// not have sufficient timesteps for all elements, has a different number of
+/// timesteps for lines, transformers, and generators, or exceeds the
+/// traversal limit.
+pub fn load_powersystem(path: &Path, opts: &LoadOptions) -> Result<PowerSystem, anyhow::Error> {
+    if opts.use_mmap {
+        return load_powersystem_mmap(path, opts);
+    }
+
     // Open the file and deserialize the Cap'n Proto message
     let file = std::fs::File::open(path)?;
+    let file_len = file.metadata()?.len();
     let buff_reader = BufReader::new(&file);
-    let reader = capnp::serialize::read_message(
-        buff_reader,
-        capnp::message::ReaderOptions {
-            traversal_limit_in_words: None,
-            ..Default::default()
-        },
+    let reader = capnp::serialize::read_message(buff_reader, opts.reader_options(file_len))?;
+
+    let ds = reader.get_root::<crate::power_system_capnp::power_system_dataset::Reader>()?;
+
+    decode_powersystem(&ds, path, opts)
+}
+
+/// Loads a `PowerSystem` from a memory-mapped Cap'n Proto file.
+///
+/// Everything is copied out of the mapping during decoding, so the returned
+/// `PowerSystem` does not borrow from it.
+fn load_powersystem_mmap(path: &Path, opts: &LoadOptions) -> Result<PowerSystem, anyhow::Error> {
+    let file = std::fs::File::open(path)?;
+
+    // Safety: the mapping is read-only and only lives for the duration of
+    // this function. Modifying the pack on disk while loading is not
+    // supported.
+    let map = unsafe { memmap2::Mmap::map(&file)? };
+
+    let mut slice: &[u8] = &map;
+    let reader = capnp::serialize::read_message_from_flat_slice(
+        &mut slice,
+        opts.reader_options(map.len() as u64),
     )?;
 
     let ds = reader.get_root::<crate::power_system_capnp::power_system_dataset::Reader>()?;
 
+    decode_powersystem(&ds, path, opts)
+}
+
+/// Decodes a dataset root into an owned `PowerSystem`.
+fn decode_powersystem(
+    ds: &crate::power_system_capnp::power_system_dataset::Reader,
+    path: &Path,
+    opts: &LoadOptions,
+) -> Result<PowerSystem, anyhow::Error> {
     // Load components individually
-    let lines = load_lines(&ds)?;
-    let tfs = load_transformers(&ds)?;
-    let pvs = load_generators(&ds)?;
-    let title = figure_name(path);
-    let line_meta = load_line_metadata(&ds);
-    let floor_plan = load_floorplan(&ds);
+    let lines = load_lines(ds, opts)?;
+    let tfs = load_transformers(ds)?;
+    let pvs = load_generators(ds)?;
+    let title = figure_name(ds, path);
+    let line_meta = load_line_metadata(ds);
+    // Packs have no ratings; line load is taken as given
+    let line_ratings = vec![None; line_meta.len()];
+    let floor_plan = load_floorplan(ds);
 
     // Assemble final PowerSystem
-    Ok(PowerSystem {
+    let system = PowerSystem {
         title,
         lines,
         tfs,
         pvs,
         floor_plan,
         line_meta,
-    })
+        line_ratings,
+        nominal_voltage_kv: None,
+    };
+
+    system.check_time_steps()?;
+
+    Ok(system)
 }
 
 /// Loads line data, transposing it from (Lines -> Times) into (Times -> Lines).
+///
+/// The timestep count is taken from the longest line. Lines with fewer
+/// samples repeat their last sample, so every timestep has the same number of
+/// lines.
 fn load_lines(
     ds: &crate::power_system_capnp::power_system_dataset::Reader,
+    opts: &LoadOptions,
 ) -> Result<Vec<Vec<LineState>>, anyhow::Error> {
     let line_src = ds.get_lines()?;
     let mut lines = Vec::with_capacity(line_src.len() as usize);
@@ -203,16 +677,19 @@ fn load_lines(
         })
         .collect();
 
-    let mut iters: Vec<_> = datas.iter().map(|f| (f.0, f.1.iter(), f.2, f.3)).collect();
-    let time_step_count = line_src.get(0).get_data()?.len();
+    let time_step_count = series_length("Line", datas.iter().map(|d| (d.2, d.1.len() as usize)))?;
     log::debug!("Time steps {time_step_count}");
 
+    let mut iters: Vec<_> = datas.iter().map(|f| (f.0, f.1.iter(), f.2, f.3)).collect();
+
     // Build per-time-step slices
-    for _ in 0..time_step_count {
-        let mut per_time_step = vec![];
+    for t in 0..time_step_count {
+        let mut per_time_step = Vec::with_capacity(iters.len());
 
-        for iter in iters.iter_mut() {
+        for (line_i, iter) in iters.iter_mut().enumerate() {
             let Some(a) = iter.1.next() else {
+                // Out of samples; hold the last one
+                per_time_step.push(lines[t - 1][line_i].clone());
                 continue;
             };
             let (volt_div, watt_div, var_div) = iter.3;
@@ -249,7 +726,8 @@ fn load_lines(
                     a: a.get_line_load_real_a(),
                     b: a.get_line_load_real_b(),
                     c: a.get_line_load_real_c(),
-                },
+                }
+                .scaled(opts.line_load_divisor),
                 loc: iter.0,
             });
         }
@@ -260,6 +738,8 @@ fn load_lines(
 }
 
 /// Loads transformer data, organized by time step.
+///
+/// Like lines, transformers with fewer samples repeat their last sample.
 fn load_transformers(
     ds: &crate::power_system_capnp::power_system_dataset::Reader,
 ) -> Result<Vec<Vec<TransformerState>>, anyhow::Error> {
@@ -270,9 +750,12 @@ fn load_transformers(
         .iter()
         .map(|f| {
             (
-                Position {
+                // Packs store a single location for transformers
+                EndedPosition {
                     sx: f.get_position_x(),
                     sy: f.get_position_y(),
+                    ex: f.get_position_x(),
+                    ey: f.get_position_y(),
                 },
                 f.get_data().unwrap(),
                 (
@@ -280,19 +763,27 @@ fn load_transformers(
                     f.get_wattage_divisor(),
                     f.get_vars_divisor(),
                 ),
+                f.get_id().unwrap().to_str().unwrap(),
             )
         })
         .collect();
 
+    let time_step_count = series_length(
+        "Transformer",
+        datas.iter().map(|d| (d.3, d.1.len() as usize)),
+    )?;
+
     let mut iters: Vec<_> = datas.iter().map(|f| (f.0, f.1.iter(), f.2)).collect();
-    let time_step_count = data_src.get(0).get_data()?.len();
 
-    for _ in 0..time_step_count {
+    for t in 0..time_step_count {
         let mut per_time_step = vec![];
 
-        for iter in iters.iter_mut() {
+        for (tf_i, iter) in iters.iter_mut().enumerate() {
             let (volt_div, _, _) = iter.2;
-            let a = iter.1.next().unwrap();
+            let Some(a) = iter.1.next() else {
+                per_time_step.push(transformers[t - 1][tf_i].clone());
+                continue;
+            };
 
             per_time_step.push(TransformerState {
                 voltage: EndPhased {
@@ -323,6 +814,8 @@ fn load_transformers(
 }
 
 /// Loads generator (PV) data, organized by time step.
+///
+/// Like lines, generators with fewer samples repeat their last sample.
 fn load_generators(
     ds: &crate::power_system_capnp::power_system_dataset::Reader,
 ) -> Result<Vec<Vec<GeneratorState>>, anyhow::Error> {
@@ -347,19 +840,25 @@ fn load_generators(
                     .ok()
                     .and_then(|f| f.to_str().ok())
                     .unwrap_or(""),
+                f.get_id().unwrap().to_str().unwrap(),
             )
         })
         .collect();
 
+    let time_step_count =
+        series_length("Generator", datas.iter().map(|d| (d.4, d.1.len() as usize)))?;
+
     let mut iters: Vec<_> = datas.iter().map(|f| (f.0, f.1.iter(), f.2, f.3)).collect();
-    let time_step_count = data_src.get(0).get_data()?.len();
 
-    for _ in 0..time_step_count {
+    for t in 0..time_step_count {
         let mut per_time_step = vec![];
 
-        for iter in iters.iter_mut() {
+        for (gen_i, iter) in iters.iter_mut().enumerate() {
             let (volt_div, _, _) = iter.2;
-            let a = iter.1.next().unwrap();
+            let Some(a) = iter.1.next() else {
+                per_time_step.push(generators[t - 1][gen_i].clone());
+                continue;
+            };
 
             let ty_str = iter.3;
 
@@ -378,6 +877,8 @@ fn load_generators(
                 react: a.get_react(),
                 loc: iter.0,
                 ty: GeneratorType::decode(ty_str),
+                // Not part of the capnp schema yet
+                capacity: None,
             });
         }
         generators.push(per_time_step);
@@ -420,8 +921,11 @@ fn load_floorplan(
 }
 
 /// Attempt to determine the dataset name. If unable, the filename will be used.
-fn figure_name(path: &Path) -> String {
-    let res = extract_name(path);
+fn figure_name(
+    ds: &crate::power_system_capnp::power_system_dataset::Reader,
+    path: &Path,
+) -> String {
+    let res = extract_name(ds);
 
     if let Some(name) = res {
         return name;
@@ -433,23 +937,8 @@ fn figure_name(path: &Path) -> String {
         .to_string()
 }
 
-/// Inspect the capnp file for a title
-fn extract_name(path: &Path) -> Option<String> {
-    let file = std::fs::File::open(path).ok()?;
-    let buff_reader = BufReader::new(&file);
-    let reader = capnp::serialize::read_message(
-        buff_reader,
-        capnp::message::ReaderOptions {
-            traversal_limit_in_words: None,
-            ..Default::default()
-        },
-    )
-    .ok()?;
-
-    let ds = reader
-        .get_root::<crate::power_system_capnp::power_system_dataset::Reader>()
-        .ok()?;
-
+/// Inspect the capnp dataset for a title
+fn extract_name(ds: &crate::power_system_capnp::power_system_dataset::Reader) -> Option<String> {
     let string = ds.get_name().ok()?.to_string().ok()?;
 
     if string.is_empty() {
@@ -458,3 +947,277 @@ fn extract_name(path: &Path) -> Option<String> {
 
     Some(string)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::power_system_capnp::power_system_dataset;
+
+    /// Every element spans from the origin to (1, 1), whatever its values.
+    fn span() -> EndedPosition {
+        EndedPosition {
+            sx: 0.0,
+            sy: 0.0,
+            ex: 1.0,
+            ey: 1.0,
+        }
+    }
+
+    fn end_phased(v: f32) -> EndPhased {
+        EndPhased {
+            sa: v,
+            sb: v,
+            sc: v,
+            ea: v,
+            eb: v,
+            ec: v,
+        }
+    }
+
+    fn line(v: f32) -> LineState {
+        LineState {
+            voltage: end_phased(v),
+            real_power: end_phased(v),
+            reactive_power: end_phased(v),
+            loc: span(),
+            line_load: Phased { a: v, b: v, c: v },
+        }
+    }
+
+    fn transformer(v: f32) -> TransformerState {
+        TransformerState {
+            voltage: end_phased(v),
+            tap: Phased { a: 0, b: 0, c: 0 },
+            tap_changes: Phased { a: 0, b: 0, c: 0 },
+            loc: span(),
+        }
+    }
+
+    fn generator(v: f32) -> GeneratorState {
+        GeneratorState {
+            voltage: Phased { a: v, b: v, c: v },
+            angle: Phased { a: v, b: v, c: v },
+            real: v,
+            react: v,
+            loc: Position { sx: 0.0, sy: 0.0 },
+            ty: GeneratorType::Solar,
+            capacity: Some(v),
+        }
+    }
+
+    fn system(
+        lines: Vec<Vec<LineState>>,
+        tfs: Vec<Vec<TransformerState>>,
+        pvs: Vec<Vec<GeneratorState>>,
+    ) -> PowerSystem {
+        PowerSystem {
+            title: "Test".into(),
+            lines,
+            tfs,
+            pvs,
+            line_meta: Vec::new(),
+            line_ratings: Vec::new(),
+            floor_plan: None,
+            nominal_voltage_kv: None,
+        }
+    }
+
+    #[test]
+    fn sanitize_holds_last_finite_sample() {
+        let mut system = system(
+            [f32::NAN, 1.0, f32::INFINITY, 2.0]
+                .map(|v| vec![line(v), line(5.0)])
+                .into(),
+            [3.0, f32::NEG_INFINITY, f32::NAN, 4.0]
+                .map(|v| vec![transformer(v)])
+                .into(),
+            vec![vec![generator(f32::NAN)]; 4],
+        );
+
+        // 21 values per line, 6 per transformer, and 9 per generator
+        assert_eq!(system.sanitize(), 2 * 21 + 2 * 6 + 4 * 9);
+
+        let line_volts: Vec<_> = system.lines.iter().map(|s| s[0].voltage.sa).collect();
+        let tf_volts: Vec<_> = system.tfs.iter().map(|s| s[0].voltage.ec).collect();
+
+        // Leading values take the first finite sample
+        assert_eq!(line_volts, [1.0, 1.0, 1.0, 2.0]);
+        assert_eq!(tf_volts, [3.0, 3.0, 3.0, 4.0]);
+
+        // Values that are never finite have nothing to hold
+        assert!(system.pvs.iter().all(|s| s[0].real == 0.0));
+
+        // Finite values are kept
+        assert!(system.lines.iter().all(|s| s[1].voltage.sa == 5.0));
+        assert_eq!(system.sanitize(), 0);
+    }
+
+    #[test]
+    fn sanitize_removes_elements_without_a_position() {
+        let mut lines = vec![vec![line(1.0), line(2.0), line(3.0)]; 2];
+        lines[1][1].loc.ex = f64::NAN;
+
+        let mut tfs = vec![vec![transformer(1.0), transformer(2.0)]; 2];
+        tfs[0][0].loc.sy = f64::INFINITY;
+
+        let mut pvs = vec![vec![generator(1.0)]; 2];
+        pvs[1][0].loc.sx = f64::NAN;
+
+        let mut system = system(lines, tfs, pvs);
+        system.line_meta = vec!["a".into(), "b".into(), "c".into()];
+        system.line_ratings = vec![Some(1.0), Some(2.0), Some(3.0)];
+
+        assert_eq!(system.sanitize(), 0);
+
+        // Removed from every step, so indices still match across steps
+        for step in &system.lines {
+            let volts: Vec<_> = step.iter().map(|l| l.voltage.sa).collect();
+            assert_eq!(volts, [1.0, 3.0]);
+        }
+
+        assert_eq!(system.line_meta, ["a", "c"]);
+        assert_eq!(system.line_ratings, [Some(1.0), Some(3.0)]);
+
+        assert!(system
+            .tfs
+            .iter()
+            .all(|s| s.len() == 1 && s[0].voltage.sa == 2.0));
+        assert!(system.pvs.iter().all(Vec::is_empty));
+    }
+
+    #[test]
+    fn sanitized_lines_give_finite_instances() {
+        let mut bad = line(f32::NAN);
+        bad.voltage.ea = f32::INFINITY;
+        bad.real_power.sb = f32::NEG_INFINITY;
+
+        let mut system = system(
+            vec![
+                vec![bad.clone(), line(1.0)],
+                vec![line(1.02), line(0.98)],
+                vec![bad, line(1.0)],
+            ],
+            Vec::new(),
+            Vec::new(),
+        );
+
+        system.sanitize();
+
+        let domain = crate::domain::Domain::default();
+
+        for step in &system.lines {
+            let mut dest = vec![];
+
+            crate::instance::recompute_lines(
+                step,
+                |s| crate::instance::LineGetterResult {
+                    volt_start: s.voltage.sa,
+                    volt_end: s.voltage.ea,
+                    watt: s.real_power.sb,
+                    vars: s.reactive_power.sa,
+                    inductive: s.reactive_power.sa >= 0.0,
+                    line_load: s.line_load.a,
+                },
+                &domain,
+                nalgebra_glm::Vec3::zeros(),
+                0,
+                0.0,
+                &mut dest,
+                None,
+                false,
+                false,
+                Default::default(),
+                false,
+                |_| None,
+                |_, _| {},
+                true,
+            );
+
+            assert!(!dest.is_empty());
+
+            let floats: Vec<f32> = dest
+                .chunks_exact(4)
+                .map(|b| f32::from_le_bytes(b.try_into().unwrap()))
+                .collect();
+
+            assert!(floats.iter().all(|v| v.is_finite()), "{floats:?}");
+        }
+    }
+
+    #[test]
+    fn check_time_steps_accepts_matching_counts() {
+        let system = system(
+            vec![vec![line(1.0)]; 3],
+            vec![vec![transformer(1.0)]; 3],
+            vec![vec![generator(1.0)]; 3],
+        );
+
+        assert!(system.check_time_steps().is_ok());
+    }
+
+    #[test]
+    fn check_time_steps_accepts_absent_kinds() {
+        let system = system(vec![vec![line(1.0)]; 3], Vec::new(), Vec::new());
+
+        assert!(system.check_time_steps().is_ok());
+    }
+
+    #[test]
+    fn check_time_steps_rejects_mismatched_counts() {
+        let short_tfs = system(
+            vec![vec![line(1.0)]; 3],
+            vec![vec![transformer(1.0)]; 2],
+            vec![vec![generator(1.0)]; 3],
+        );
+
+        let err = short_tfs.check_time_steps().unwrap_err().to_string();
+        assert!(err.contains("transformers"), "{err}");
+
+        let long_pvs = system(
+            vec![vec![line(1.0)]; 3],
+            vec![vec![transformer(1.0)]; 3],
+            vec![vec![generator(1.0)]; 4],
+        );
+
+        let err = long_pvs.check_time_steps().unwrap_err().to_string();
+        assert!(err.contains("generators"), "{err}");
+    }
+
+    #[test]
+    fn load_lines_repeats_last_sample_of_short_lines() {
+        let mut message = capnp::message::Builder::new_default();
+
+        {
+            let ds = message.init_root::<power_system_dataset::Builder>();
+            let mut lines = ds.init_lines(2);
+
+            // The first line is the short one, so the count must not come
+            // from it
+            for (line_i, count) in [(0, 2), (1, 4)] {
+                let mut line = lines.reborrow().get(line_i);
+                line.set_id(format!("line {line_i}").as_str());
+
+                let mut data = line.init_data(count);
+
+                for t in 0..count {
+                    data.reborrow().get(t).set_volt_a_from(t as f32 + 1.0);
+                }
+            }
+        }
+
+        let ds = message
+            .get_root_as_reader::<power_system_dataset::Reader>()
+            .unwrap();
+
+        let lines = load_lines(&ds, &LoadOptions::default()).unwrap();
+
+        assert_eq!(lines.len(), 4);
+        assert!(lines.iter().all(|step| step.len() == 2));
+
+        let short: Vec<_> = lines.iter().map(|step| step[0].voltage.sa).collect();
+        let long: Vec<_> = lines.iter().map(|step| step[1].voltage.sa).collect();
+
+        assert_eq!(short, [1.0, 2.0, 2.0, 2.0]);
+        assert_eq!(long, [1.0, 2.0, 3.0, 4.0]);
+    }
+}
@@ -0,0 +1,305 @@
+use serde::{Serialize, Serializer};
+
+use crate::dots::{EndPhased, EndedPosition, GeneratorType, Phased, PowerSystem};
+
+/// Text format for snapshot exports.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SnapshotFormat {
+    /// One CSV table per element kind, separated by blank lines
+    Csv,
+    /// A single JSON object with an array per element kind
+    Json,
+}
+
+impl SnapshotFormat {
+    /// Parses a format name, ignoring case.
+    pub fn parse(text: &str) -> Option<Self> {
+        match text.to_lowercase().as_str() {
+            "csv" => Some(Self::Csv),
+            "json" => Some(Self::Json),
+            _ => None,
+        }
+    }
+}
+
+/// A single exported value.
+enum Cell {
+    Text(String),
+    Int(i64),
+    Num(f32),
+}
+
+impl std::fmt::Display for Cell {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Cell::Text(t) if t.contains([',', '"', '\n']) => {
+                write!(f, "\"{}\"", t.replace('"', "\"\""))
+            }
+            Cell::Text(t) => write!(f, "{t}"),
+            Cell::Int(i) => write!(f, "{i}"),
+            Cell::Num(v) => write!(f, "{v}"),
+        }
+    }
+}
+
+impl Serialize for Cell {
+    fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Cell::Text(t) => s.serialize_str(t),
+            Cell::Int(i) => s.serialize_i64(*i),
+            Cell::Num(v) => s.serialize_f32(*v),
+        }
+    }
+}
+
+/// Exported rows for one kind of element. Column names follow the JSON
+/// dataset schema where there is an equivalent.
+struct Table {
+    kind: &'static str,
+    columns: &'static [&'static str],
+    rows: Vec<Vec<Cell>>,
+}
+
+impl Table {
+    fn records(&self) -> Vec<Record<'_>> {
+        self.rows
+            .iter()
+            .map(|cells| Record {
+                columns: self.columns,
+                cells,
+            })
+            .collect()
+    }
+}
+
+/// A row of a table, serialized as an object keyed by column.
+struct Record<'a> {
+    columns: &'static [&'static str],
+    cells: &'a [Cell],
+}
+
+impl Serialize for Record<'_> {
+    fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        s.collect_map(self.columns.iter().zip(self.cells))
+    }
+}
+
+/// JSON layout of a snapshot.
+#[derive(Serialize)]
+struct Snapshot<'a> {
+    time_step: usize,
+    lines: Vec<Record<'a>>,
+    transformers: Vec<Record<'a>>,
+    generators: Vec<Record<'a>>,
+}
+
+const LINE_COLUMNS: &[&str] = &[
+    "index",
+    "name",
+    "position_start_x",
+    "position_start_y",
+    "position_end_x",
+    "position_end_y",
+    "volt_a_from",
+    "volt_b_from",
+    "volt_c_from",
+    "volt_a_to",
+    "volt_b_to",
+    "volt_c_to",
+    "real_a_from",
+    "real_b_from",
+    "real_c_from",
+    "real_a_to",
+    "real_b_to",
+    "real_c_to",
+    "react_a_from",
+    "react_b_from",
+    "react_c_from",
+    "react_a_to",
+    "react_b_to",
+    "react_c_to",
+    "line_load_real_a",
+    "line_load_real_b",
+    "line_load_real_c",
+];
+
+const TRANSFORMER_COLUMNS: &[&str] = &[
+    "index",
+    "position_x",
+    "position_y",
+    "position_end_x",
+    "position_end_y",
+    "volt_a_from",
+    "volt_b_from",
+    "volt_c_from",
+    "volt_a_to",
+    "volt_b_to",
+    "volt_c_to",
+    "tap_a",
+    "tap_b",
+    "tap_c",
+    "tap_changes_a",
+    "tap_changes_b",
+    "tap_changes_c",
+];
+
+const GENERATOR_COLUMNS: &[&str] = &[
+    "index",
+    "type",
+    "position_x",
+    "position_y",
+    "volt_a",
+    "volt_b",
+    "volt_c",
+    "angle_a",
+    "angle_b",
+    "angle_c",
+    "real",
+    "react",
+];
+
+/// Returns the states at a time step, or nothing if the step is missing.
+fn at<T>(steps: &[Vec<T>], time_step: usize) -> &[T] {
+    steps.get(time_step).map(Vec::as_slice).unwrap_or_default()
+}
+
+fn position_cells(p: &EndedPosition) -> impl Iterator<Item = Cell> {
+    [p.sx, p.sy, p.ex, p.ey]
+        .into_iter()
+        .map(|v| Cell::Num(v as f32))
+}
+
+fn end_phased_cells(v: &EndPhased) -> impl Iterator<Item = Cell> {
+    [v.sa, v.sb, v.sc, v.ea, v.eb, v.ec]
+        .into_iter()
+        .map(Cell::Num)
+}
+
+fn phased_cells(v: &Phased) -> impl Iterator<Item = Cell> {
+    [v.a, v.b, v.c].into_iter().map(Cell::Num)
+}
+
+/// Collects every line, transformer, and generator state at a time step.
+fn snapshot_tables(system: &PowerSystem, time_step: usize) -> [Table; 3] {
+    let lines = Table {
+        kind: "lines",
+        columns: LINE_COLUMNS,
+        rows: at(&system.lines, time_step)
+            .iter()
+            .enumerate()
+            .map(|(i, l)| {
+                let name = system.line_meta.get(i).cloned().unwrap_or_default();
+
+                [Cell::Int(i as i64), Cell::Text(name)]
+                    .into_iter()
+                    .chain(position_cells(&l.loc))
+                    .chain(end_phased_cells(&l.voltage))
+                    .chain(end_phased_cells(&l.real_power))
+                    .chain(end_phased_cells(&l.reactive_power))
+                    .chain(phased_cells(&l.line_load))
+                    .collect()
+            })
+            .collect(),
+    };
+
+    let transformers = Table {
+        kind: "transformers",
+        columns: TRANSFORMER_COLUMNS,
+        rows: at(&system.tfs, time_step)
+            .iter()
+            .enumerate()
+            .map(|(i, t)| {
+                let taps = [t.tap.a, t.tap.b, t.tap.c]
+                    .into_iter()
+                    .chain([t.tap_changes.a, t.tap_changes.b, t.tap_changes.c])
+                    .map(|v| Cell::Int(v as i64));
+
+                [Cell::Int(i as i64)]
+                    .into_iter()
+                    .chain(position_cells(&t.loc))
+                    .chain(end_phased_cells(&t.voltage))
+                    .chain(taps)
+                    .collect()
+            })
+            .collect(),
+    };
+
+    let generators = Table {
+        kind: "generators",
+        columns: GENERATOR_COLUMNS,
+        rows: at(&system.pvs, time_step)
+            .iter()
+            .enumerate()
+            .map(|(i, g)| {
+                let ty = match g.ty {
+                    GeneratorType::Unknown => "unknown",
+                    GeneratorType::Solar => "pv",
+                    GeneratorType::Battery => "battery",
+                };
+
+                [
+                    Cell::Int(i as i64),
+                    Cell::Text(ty.into()),
+                    Cell::Num(g.loc.sx as f32),
+                    Cell::Num(g.loc.sy as f32),
+                ]
+                .into_iter()
+                .chain(phased_cells(&g.voltage))
+                .chain(phased_cells(&g.angle))
+                .chain([Cell::Num(g.real), Cell::Num(g.react)])
+                .collect()
+            })
+            .collect(),
+    };
+
+    [lines, transformers, generators]
+}
+
+/// Writes the state of every element at a time step as text.
+///
+/// Lines are named from the dataset metadata; transformers and generators are
+/// identified by index.
+pub fn export_snapshot(
+    system: &PowerSystem,
+    time_step: usize,
+    format: SnapshotFormat,
+) -> Result<String, anyhow::Error> {
+    let tables = snapshot_tables(system, time_step);
+
+    match format {
+        SnapshotFormat::Csv => {
+            let mut out = String::new();
+
+            for table in &tables {
+                out += &format!("# {} at time step {time_step}\n", table.kind);
+                out += &table.columns.join(",");
+                out += "\n";
+
+                for row in &table.rows {
+                    out += &row
+                        .iter()
+                        .map(Cell::to_string)
+                        .collect::<Vec<_>>()
+                        .join(",");
+                    out += "\n";
+                }
+
+                out += "\n";
+            }
+
+            Ok(out)
+        }
+        SnapshotFormat::Json => {
+            let [lines, transformers, generators] = &tables;
+
+            let snapshot = Snapshot {
+                time_step,
+                lines: lines.records(),
+                transformers: transformers.records(),
+                generators: generators.records(),
+            };
+
+            Ok(serde_json::to_string_pretty(&snapshot)?)
+        }
+    }
+}
@@ -558,6 +558,31 @@ pub fn make_sphere(
     server_state: &mut ServerState,
     color: glm::Vec3,
     scale: f32,
+) -> GeometryReference {
+    // Create a material to go along with this sphere
+    let material = server_state.materials.new_component(ServerMaterialState {
+        name: None,
+        mutable: ServerMaterialStateUpdatable {
+            pbr_info: Some(ServerPBRInfo {
+                base_color: [color.x, color.y, color.z, 1.0],
+                metallic: Some(1.0),
+                roughness: Some(0.25),
+                ..Default::default()
+            }),
+            double_sided: Some(true),
+            ..Default::default()
+        },
+    });
+
+    make_sphere_with_material(server_state, scale, material)
+}
+
+/// Builds a sphere of radius `scale` like [`make_sphere`], drawn with an
+/// existing material.
+pub fn make_sphere_with_material(
+    server_state: &mut ServerState,
+    scale: f32,
+    material: MaterialReference,
 ) -> GeometryReference {
     // Normalize each vertex to unit length to get a true sphere,
     // then scale by user-provided scale factor.
@@ -584,21 +609,6 @@ pub fn make_sphere(
         index: index_list,
     };
 
-    // Create a material to go along with this cube
-    let material = server_state.materials.new_component(ServerMaterialState {
-        name: None,
-        mutable: ServerMaterialStateUpdatable {
-            pbr_info: Some(ServerPBRInfo {
-                base_color: [color.x, color.y, color.z, 1.0],
-                metallic: Some(1.0),
-                roughness: Some(0.25),
-                ..Default::default()
-            }),
-            double_sided: Some(true),
-            ..Default::default()
-        },
-    });
-
     let pack = test_source.pack_bytes().unwrap();
 
     // Return a new mesh with this geometry/material
@@ -10,8 +10,8 @@ pub fn make_hazard_planes(
     domain: &Domain,
 ) -> (EntityReference, EntityReference) {
     // set up hazard planes
-    let lower_hazard_coord = glm::vec3(0.0, domain.voltage_to_height(0.95), 0.0);
-    let upper_hazard_coord = glm::vec3(0.0, domain.voltage_to_height(1.05), 0.0);
+    let lower_hazard_coord = glm::vec3(0.0, domain.voltage_to_height(domain.safe_min), 0.0);
+    let upper_hazard_coord = glm::vec3(0.0, domain.voltage_to_height(domain.safe_max), 0.0);
 
     let hazard_mat = state.materials.new_component(ServerMaterialState {
         name: None,
@@ -14,12 +14,33 @@ pub type ObjectList = Vec<(EntityReference, GeometryReference)>;
 
 /// Import a wavefront OBJ file
 pub fn import_file<R: std::io::BufRead>(
+    from: R,
+    state: &mut ServerState,
+    transform: Option<glm::Mat4>,
+    parent: Option<EntityReference>,
+    material_override: Option<MaterialReference>,
+) -> Result<ObjectList> {
+    import_file_with_mtl(from, None::<R>, state, transform, parent, material_override)
+}
+
+/// Import a wavefront OBJ file, along with an optional MTL material library.
+///
+/// Each object is split into one submesh per material it uses. If a material
+/// override is given, or no library is provided, every submesh gets the same
+/// material, as in [`import_file`].
+pub fn import_file_with_mtl<R: std::io::BufRead, M: std::io::BufRead>(
     mut from: R,
+    mtl: Option<M>,
     state: &mut ServerState,
     transform: Option<glm::Mat4>,
     parent: Option<EntityReference>,
     material_override: Option<MaterialReference>,
 ) -> Result<ObjectList> {
+    let library = mtl.map(parse_mtl).unwrap_or_default();
+
+    // Materials created from the library, shared between submeshes
+    let mut library_materials = HashMap::<String, MaterialReference>::new();
+
     let mut line = String::new();
 
     let mut wfobj = WFObjectState::new();
@@ -50,8 +71,18 @@ pub fn import_file<R: std::io::BufRead>(
 
         let bytes = source.pack_bytes().context("Packing bytes")?;
 
+        let library_entry = sub_obj
+            .material
+            .as_ref()
+            .and_then(|name| library.get_key_value(name));
+
         let material = if let Some(m) = &material_override {
             m.clone()
+        } else if let Some((name, mtl)) = library_entry {
+            library_materials
+                .entry(name.clone())
+                .or_insert_with(|| mtl.build(name, state))
+                .clone()
         } else {
             state.materials.new_component(ServerMaterialState {
                 name: None,
@@ -242,6 +273,13 @@ fn handle_o(obj: &mut WFObjectState, mut line: SplitWhitespace) -> Option<()> {
     Some(())
 }
 
+fn handle_usemtl(obj: &mut WFObjectState, mut line: SplitWhitespace) -> Option<()> {
+    // Faces so far belong to the previous material
+    obj.push_object();
+    obj.last_material = line.next().map(|f| f.to_string());
+    Some(())
+}
+
 struct WFObjectState {
     fn_map: HashMap<String, WFFunc>,
 
@@ -249,8 +287,9 @@ struct WFObjectState {
     normal_list: Vec<[f32; 3]>,
     tex_list: Vec<[f32; 3]>,
 
-    obj_face_list: HashMap<String, Vec<FaceMarker>>,
+    obj_face_list: HashMap<(String, Option<String>), Vec<FaceMarker>>,
     last_name: String,
+    last_material: Option<String>,
     last_face_list: Vec<FaceMarker>,
 }
 
@@ -263,6 +302,7 @@ impl WFObjectState {
         fn_map.insert("vt".to_string(), handle_vt);
         fn_map.insert("f".to_string(), handle_f);
         fn_map.insert("o".to_string(), handle_o);
+        fn_map.insert("usemtl".to_string(), handle_usemtl);
 
         Self {
             fn_map,
@@ -271,6 +311,7 @@ impl WFObjectState {
             tex_list: Default::default(),
             obj_face_list: Default::default(),
             last_name: Default::default(),
+            last_material: Default::default(),
             last_face_list: Default::default(),
         }
     }
@@ -296,7 +337,10 @@ impl WFObjectState {
 
         let local_vec = take(&mut self.last_face_list);
 
-        self.obj_face_list.insert(name.to_string(), local_vec);
+        self.obj_face_list
+            .entry((name.to_string(), self.last_material.clone()))
+            .or_default()
+            .extend(local_vec);
     }
 }
 
@@ -359,6 +403,7 @@ fn compute_quad(indicies: &[u32], vs: &[VertexTexture]) -> ([u32; 3], [u32; 3])
 
 struct PackedObj {
     name: String,
+    material: Option<String>,
     verts: Vec<VertexTexture>,
     faces: Vec<[u32; 3]>,
 }
@@ -380,7 +425,7 @@ fn pack_wf_state(mut obj: WFObjectState, transform: Option<glm::Mat4>) -> Vec<Pa
 
     let mut ret = Vec::<PackedObj>::new();
 
-    for (name, this_obj_faces) in take(&mut obj.obj_face_list) {
+    for ((name, material), this_obj_faces) in take(&mut obj.obj_face_list) {
         this_face_cache.clear();
         counter = 0;
         vert_list.clear();
@@ -415,6 +460,7 @@ fn pack_wf_state(mut obj: WFObjectState, transform: Option<glm::Mat4>) -> Vec<Pa
 
         ret.push(PackedObj {
             name,
+            material,
             verts: take(&mut vert_list),
             faces: take(&mut faces),
         })
@@ -429,3 +475,94 @@ fn pack_wf_state(mut obj: WFObjectState, transform: Option<glm::Mat4>) -> Vec<Pa
 
     ret
 }
+
+/// A material parsed from an MTL library
+#[derive(Debug, Clone)]
+struct MtlMaterial {
+    color: [f32; 4],
+    metallic: Option<f32>,
+    roughness: Option<f32>,
+}
+
+impl Default for MtlMaterial {
+    fn default() -> Self {
+        Self {
+            color: [1.0, 1.0, 1.0, 1.0],
+            metallic: None,
+            roughness: None,
+        }
+    }
+}
+
+impl MtlMaterial {
+    /// Registers this material with the server.
+    fn build(&self, name: &str, state: &mut ServerState) -> MaterialReference {
+        state.materials.new_component(ServerMaterialState {
+            name: Some(name.to_string()),
+            mutable: ServerMaterialStateUpdatable {
+                pbr_info: Some(PBRInfo {
+                    base_color: self.color,
+                    metallic: Some(self.metallic.unwrap_or(0.0)),
+                    roughness: Some(self.roughness.unwrap_or(1.0)),
+                    ..Default::default()
+                }),
+                use_alpha: Some(self.color[3] < 1.0),
+                ..Default::default()
+            },
+        })
+    }
+}
+
+/// Parse a wavefront MTL library.
+///
+/// Only diffuse color (`Kd`), opacity (`d` / `Tr`) and the PBR extension
+/// metallic / roughness (`Pm` / `Pr`) are understood.
+fn parse_mtl<R: std::io::BufRead>(from: R) -> HashMap<String, MtlMaterial> {
+    let mut ret = HashMap::<String, MtlMaterial>::new();
+    let mut current: Option<(String, MtlMaterial)> = None;
+
+    fn parse_f32(v: Option<&str>) -> f32 {
+        v.unwrap_or_default().parse().unwrap_or_default()
+    }
+
+    for line in from.lines().map_while(|f| f.ok()) {
+        let mut iter = line.split_whitespace();
+
+        let Some(directive) = iter.next() else {
+            continue;
+        };
+
+        if directive == "newmtl" {
+            if let Some((name, mtl)) = current.take() {
+                ret.insert(name, mtl);
+            }
+
+            let name = iter.next().unwrap_or("Unknown").to_string();
+            current = Some((name, MtlMaterial::default()));
+            continue;
+        }
+
+        let Some((_, mtl)) = current.as_mut() else {
+            continue;
+        };
+
+        match directive {
+            "Kd" => {
+                mtl.color[0] = parse_f32(iter.next());
+                mtl.color[1] = parse_f32(iter.next());
+                mtl.color[2] = parse_f32(iter.next());
+            }
+            "d" => mtl.color[3] = parse_f32(iter.next()),
+            "Tr" => mtl.color[3] = 1.0 - parse_f32(iter.next()),
+            "Pm" => mtl.metallic = Some(parse_f32(iter.next())),
+            "Pr" => mtl.roughness = Some(parse_f32(iter.next())),
+            _ => (),
+        }
+    }
+
+    if let Some((name, mtl)) = current.take() {
+        ret.insert(name, mtl);
+    }
+
+    ret
+}
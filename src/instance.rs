@@ -1,9 +1,9 @@
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet, VecDeque};
 
 use crate::{
     domain::{Domain, VoltageSafety},
     dots::GeneratorType,
-    utility::roll_free_rotation,
+    utility::{roll_free_rotation, LerpTrait},
     GeneratorState, LineState, TransformerState,
 };
 
@@ -15,9 +15,50 @@ pub struct LineGetterResult {
     pub volt_end: f32,
     pub watt: f32,
     pub vars: f32,
+    /// Reactive power is zero or positive, read before any `abs()`
+    pub inductive: bool,
     pub line_load: f32,
 }
 
+impl LineGetterResult {
+    /// Reads one phase of a line, 0 for A through 2 for C. Powers, like line
+    /// load, are read at the from end of every phase.
+    pub fn phase(s: &LineState, phase: usize) -> Self {
+        let (volt_start, volt_end, watt, vars, line_load) = match phase {
+            0 => (
+                s.voltage.sa,
+                s.voltage.ea,
+                s.real_power.sa,
+                s.reactive_power.sa,
+                s.line_load.a,
+            ),
+            1 => (
+                s.voltage.sb,
+                s.voltage.eb,
+                s.real_power.sb,
+                s.reactive_power.sb,
+                s.line_load.b,
+            ),
+            _ => (
+                s.voltage.sc,
+                s.voltage.ec,
+                s.real_power.sc,
+                s.reactive_power.sc,
+                s.line_load.c,
+            ),
+        };
+
+        Self {
+            volt_start,
+            volt_end,
+            watt,
+            vars,
+            inductive: vars >= 0.0,
+            line_load,
+        }
+    }
+}
+
 #[allow(dead_code)]
 pub struct TfGetterResult {
     pub volt_start: f32,
@@ -33,12 +74,63 @@ pub struct GeneratorGetterResult {
     pub real: f32,
     pub react: f32,
     pub ty: GeneratorType,
+    pub capacity: Option<f32>,
+}
+
+/// Per-instance data for an instanced mesh.
+///
+/// Encoded as 16 floats, in order: position (with an unused fourth value),
+/// texture coordinates and color, rotation quaternion (x, y, z, w), and scale
+/// (with an unused fourth value).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct InstanceMatrix {
+    pub center: glm::Vec3,
+    pub tex: glm::Vec4,
+    pub rot: glm::Quat,
+    pub scale: glm::Vec3,
+}
+
+impl InstanceMatrix {
+    /// Size in bytes of one encoded instance.
+    pub const STRIDE: usize = std::mem::size_of::<[f32; 16]>();
+
+    /// Lays out the instance as the 16 floats clients expect.
+    pub fn to_array(self) -> [f32; 16] {
+        let rot = self.rot.as_vector();
+
+        [
+            self.center.x,
+            self.center.y,
+            self.center.z,
+            0.0, // 3
+            self.tex.x,
+            self.tex.y,
+            self.tex.z,
+            self.tex.w, // 7
+            rot.x,
+            rot.y,
+            rot.z,
+            rot.w, // 11
+            self.scale.x,
+            self.scale.y,
+            self.scale.z,
+            0.0, // 15
+        ]
+    }
+
+    /// Appends the encoded instance to an instance buffer.
+    pub fn push_to(self, dest: &mut Vec<u8>) {
+        dest.extend_from_slice(bytemuck::cast_slice(&self.to_array()));
+    }
 }
 
 /// Recomputes bus instance transforms and encodes them into a GPU-friendly buffer.
 ///
 /// Each bus is a vertical element placed at a line endpoint, lifted by voltage
 /// or line load and encoded with transform, color, and scale data.
+/// With `reactive_sign`, buses are tinted by the sign of their reactive power,
+/// see [`element_band`].
+#[allow(clippy::too_many_arguments)]
 pub fn recompute_buses<F>(
     src: &[LineState],
     getter: F,
@@ -47,19 +139,23 @@ pub fn recompute_buses<F>(
     color_band: f32,
     dest: &mut Vec<u8>,
     use_line_load: bool,
+    reactive_sign: bool,
+    style: BusStyle,
 ) where
     F: Fn(&LineState) -> LineGetterResult,
 {
     log::debug!("Recompute buses {}", src.len());
 
     for state in src {
+        let result = getter(state);
         let LineGetterResult {
             volt_start,
             volt_end,
             watt,
             vars,
             line_load,
-        } = getter(state);
+            ..
+        } = result;
 
         // Determine the vertical height of each endpoint, based on either voltage or line load
         let (height_a, height_b) = if use_line_load {
@@ -88,7 +184,6 @@ pub fn recompute_buses<F>(
 
         let v = p_b - p_a;
         let rot = roll_free_rotation(v.normalize());
-        let rot_vec = rot.as_vector();
 
         let center = p_a;
 
@@ -99,24 +194,39 @@ pub fn recompute_buses<F>(
         let saturation = safety_to_saturation(safety);
 
         // Assign texture coords using a "color band" and safety-based saturation
-        let texture = glm::vec2(color_band, saturation);
-
-        // large tube to show tf bounds
-        let mat = [
-            center.x, center.y, center.z, 0.0, //
-            texture.x, texture.y, 1.0, 1.0, //
-            rot_vec.x, rot_vec.y, rot_vec.z, rot_vec.w, //
-            width, height, width, 0.0, //
-        ];
+        let texture = glm::vec2(element_band(color_band, &result, reactive_sign), saturation);
+
+        let (rot, scale) = match style {
+            // large tube to show tf bounds
+            BusStyle::Tube => (rot, glm::vec3(width, height, width)),
+            BusStyle::Sphere => {
+                let size = (volt_start - 1.0).abs().clamped_lerp(
+                    0.0,
+                    SPHERE_DEVIATION_MAX,
+                    d.tube_min,
+                    d.tube_max,
+                ) * 2.0;
+
+                (glm::quat_identity(), glm::vec3(size, size, size))
+            }
+        };
 
-        dest.extend_from_slice(bytemuck::cast_slice(&mat));
+        InstanceMatrix {
+            center,
+            tex: glm::vec4(texture.x, texture.y, 1.0, 1.0),
+            rot,
+            scale,
+        }
+        .push_to(dest);
     }
 }
 
-/// Converts a line state into a 4x4 matrix with color and orientation metadata.
+/// Converts a line state into an instance with color and orientation metadata.
 ///
 /// This is used for generating line flow or voltage/power bar representations.
+/// The line is moved sideways by its `phase` index, see [`Domain::phase_fan`].
 #[inline]
+#[allow(clippy::too_many_arguments)]
 fn state_to_line<F, T, C>(
     state: &LineState,
     getter: &F,
@@ -124,8 +234,9 @@ fn state_to_line<F, T, C>(
     mut callback: C,
     d: &Domain,
     offset: glm::Vec3,
+    phase: usize,
     use_line_load: bool,
-) -> Option<[f32; 16]>
+) -> Option<InstanceMatrix>
 where
     F: Fn(&LineState) -> LineGetterResult,
     T: Fn(&LineGetterResult, f32) -> glm::Vec4,
@@ -138,6 +249,7 @@ where
         watt,
         vars,
         line_load,
+        ..
     } = result;
 
     let (height_a, height_b) = if use_line_load {
@@ -152,17 +264,13 @@ where
         )
     };
 
-    let p_a = glm::vec3(
-        d.lerp_x(state.loc.sx as f32),
-        height_a,
-        d.lerp_y(state.loc.sy as f32),
-    ) + offset;
+    let ground_a = glm::vec2(d.lerp_x(state.loc.sx as f32), d.lerp_y(state.loc.sy as f32));
+    let ground_b = glm::vec2(d.lerp_x(state.loc.ex as f32), d.lerp_y(state.loc.ey as f32));
+
+    let offset = offset + d.phase_fan(ground_a, ground_b, phase);
 
-    let p_b = glm::vec3(
-        d.lerp_x(state.loc.ex as f32),
-        height_b,
-        d.lerp_y(state.loc.ey as f32),
-    ) + offset;
+    let p_a = glm::vec3(ground_a.x, height_a, ground_a.y) + offset;
+    let p_b = glm::vec3(ground_b.x, height_b, ground_b.y) + offset;
 
     callback(&result, p_a, p_b);
 
@@ -179,33 +287,20 @@ where
 
     let watt_size = d.real_power_to_width(watt);
     let vars_size = d.reactive_power_to_width(vars);
-    let rot_vec = rot.as_vector();
 
-    if p_a.y < 0.000001 || p_b.y < 0.000001 {
+    if height_a < 0.000001 || height_b < 0.000001 {
         return None;
     }
 
     let texture = texture(&result, v.magnitude());
 
     // Construct instance matrix with position, texture info, rotation, and scale
-    Some([
-        center.x,
-        center.y,
-        center.z,
-        0.0, // 3
-        texture.x,
-        texture.y,
-        texture.z,
-        texture.w, // 7
-        rot_vec.x,
-        rot_vec.y,
-        rot_vec.z,
-        rot_vec.w, // 11
-        watt_size,
-        vars_size,
-        v.magnitude(),
-        0.0, // 15
-    ])
+    Some(InstanceMatrix {
+        center,
+        tex: texture,
+        rot,
+        scale: glm::vec3(watt_size, vars_size, v.magnitude()),
+    })
 }
 
 fn safety_to_saturation(v: VoltageSafety) -> f32 {
@@ -216,13 +311,125 @@ fn safety_to_saturation(v: VoltageSafety) -> f32 {
     }
 }
 
+/// Hue for elements carrying inductive (positive) reactive power, when tinting
+/// by reactive sign. A warm orange.
+const BAND_INDUCTIVE: f32 = 0.08;
+
+/// Hue for elements carrying capacitive (negative) reactive power, when
+/// tinting by reactive sign. A cool cyan.
+const BAND_CAPACITIVE: f32 = 0.55;
+
+/// Picks the hue for a line or bus. With `reactive_sign`, the phase band is
+/// replaced by a warm or cool hue depending on the sign of reactive power.
+fn element_band(color_band: f32, result: &LineGetterResult, reactive_sign: bool) -> f32 {
+    match (reactive_sign, result.inductive) {
+        (false, _) => color_band,
+        (true, true) => BAND_INDUCTIVE,
+        (true, false) => BAND_CAPACITIVE,
+    }
+}
+
+/// Maps a line load (fraction of rating) onto saturation. Anything at or
+/// over the rating is fully saturated.
+fn line_load_to_saturation(line_load: f32) -> f32 {
+    line_load.abs().clamped_lerp(0.0, 1.0, 0.2, 0.8)
+}
+
+/// Shape used to draw buses.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum BusStyle {
+    /// Rounded tubes, sized by real and reactive power
+    #[default]
+    Tube,
+    /// Spheres, sized by how far the voltage is from 1.0 p.u.
+    Sphere,
+}
+
+/// Voltage deviation from 1.0 p.u. at which bus spheres reach full size
+const SPHERE_DEVIATION_MAX: f32 = 0.1;
+
+/// Data used to pick line colors.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LineColoring {
+    /// Saturation shows whether voltage is low, safe, or high
+    #[default]
+    VoltageSafety,
+    /// Saturation shows line load relative to the line rating
+    LineLoad,
+}
+
+/// Thins an instance buffer down to at most `max` instances, keeping evenly
+/// spaced instances so the result is the same every time. Each instance is
+/// `stride` bytes.
+///
+/// Returns the number of instances dropped.
+pub fn subsample_instances(buffer: &mut Vec<u8>, stride: usize, max: usize) -> usize {
+    let count = buffer.len() / stride;
+
+    if count <= max {
+        return 0;
+    }
+
+    *buffer = (0..max)
+        .flat_map(|i| {
+            let src = i * count / max;
+            buffer[src * stride..(src + 1) * stride].iter().copied()
+        })
+        .collect();
+
+    count - max
+}
+
+/// Number of hazard cells produced by a recompute, split by voltage band.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct HazardStats {
+    /// Cells crossing a limit below the safe range midpoint
+    pub low: usize,
+    /// Cells crossing a limit above the safe range midpoint
+    pub high: usize,
+}
+
+impl HazardStats {
+    pub fn total(&self) -> usize {
+        self.low + self.high
+    }
+}
+
+impl std::ops::AddAssign for HazardStats {
+    fn add_assign(&mut self, rhs: Self) {
+        self.low += rhs.low;
+        self.high += rhs.high;
+    }
+}
+
+/// Hazard cell colors, indexed by severity. Higher severities use the last.
+const HAZARD_COLORS: [[f32; 4]; 3] = [
+    [0.0, 0.0, 1.0, 1.0],
+    [1.0, 0.6, 0.0, 1.0],
+    [1.0, 0.0, 0.0, 1.0],
+];
+
+/// A voltage limit as a horizontal plane in the scene.
+struct CheckPlane {
+    height: f32,
+    severity: u8,
+    /// Below the middle of the safe range
+    low: bool,
+}
+
+/// Colors of persistent hazard cells crossed in few, and in all, of the
+/// steps in the window
+const HAZARD_RARE_COLOR: [f32; 4] = [1.0, 0.9, 0.2, 0.4];
+const HAZARD_CHRONIC_COLOR: [f32; 4] = [1.0, 0.0, 0.0, 1.0];
+
 /// Detects hazard line intersections with horizontal voltage bands
 ///
 /// This discretizes intersections and stores them for later instance creation.
-struct HazardCheck {
+/// Cells are keyed by grid position and the index of the plane they cross, so
+/// phases crossing the same cell share one instance.
+pub struct HazardCheck {
     snap: f32,
-    v_min_height: f32,
-    v_max_height: f32,
+    planes: Vec<CheckPlane>,
     map_intersect: HashSet<(i32, i32, i32)>,
 }
 
@@ -250,49 +457,171 @@ fn line_plane_intersection(a: glm::Vec3, b: glm::Vec3, plane_h: f32) -> Option<g
 }
 
 impl HazardCheck {
-    fn new(d: &Domain) -> Self {
-        // we are scaling the data to a 2 meter square. we want X cells
+    pub fn new(d: &Domain) -> Self {
+        // the data spans about 2 meters along its longest side. we want X
+        // cells along that side
+
+        let extent = d.scene_extent().max();
+        let extent = if extent.is_finite() && extent > 0.0 {
+            extent
+        } else {
+            2.0
+        };
+
+        let middle = (d.safe_min + d.safe_max) / 2.0;
 
         Self {
-            snap: 2.0 / 20.0,
-            v_min_height: d.voltage_to_height(0.95),
-            v_max_height: d.voltage_to_height(1.05),
+            snap: extent / 20.0,
+            planes: d
+                .all_hazard_planes()
+                .map(|p| CheckPlane {
+                    height: d.voltage_to_height(p.voltage) + d.scene_y_offset,
+                    severity: p.severity,
+                    low: p.voltage < middle,
+                })
+                .collect(),
             map_intersect: Default::default(),
         }
     }
 
     fn check(&mut self, a: glm::Vec3, b: glm::Vec3) {
-        // Snap point to grid and record whether it intersects upper or lower band
+        // Snap point to grid and record which plane it intersects
 
-        if let Some(point) = line_plane_intersection(a, b, self.v_min_height) {
-            let point: glm::IVec3 = glm::round(&(point / self.snap)).try_cast().unwrap();
-            self.map_intersect.insert((point.x, point.z, 0));
+        for (level, plane) in self.planes.iter().enumerate() {
+            if let Some(point) = line_plane_intersection(a, b, plane.height) {
+                let point: glm::IVec3 = glm::round(&(point / self.snap)).try_cast().unwrap();
+                self.map_intersect.insert((point.x, point.z, level as i32));
+            }
         }
+    }
 
-        if let Some(point) = line_plane_intersection(a, b, self.v_max_height) {
-            let point: glm::IVec3 = glm::round(&(point / self.snap)).try_cast().unwrap();
-            self.map_intersect.insert((point.x, point.z, 1));
+    pub fn stats(&self) -> HazardStats {
+        let low = self
+            .map_intersect
+            .iter()
+            .filter(|&&(_, _, level)| self.planes[level as usize].low)
+            .count();
+
+        HazardStats {
+            low,
+            high: self.map_intersect.len() - low,
         }
     }
 
+    /// Writes one instance per cell. Cells are emitted in sorted order, so
+    /// the buffer is the same from run to run.
     fn create_matrices(&self, dest: &mut Vec<u8>) {
-        for &(x, y, level) in &self.map_intersect {
-            let scale = glm::vec3(self.snap, 1.0, self.snap);
+        let mut cells: Vec<_> = self.map_intersect.iter().copied().collect();
+        cells.sort_unstable();
 
-            let point = glm::vec3(
-                x as f32 * self.snap,
-                glm::mix_scalar(self.v_min_height, self.v_max_height, level as f32),
-                y as f32 * self.snap,
-            );
+        for (x, y, level) in cells {
+            let plane = &self.planes[level as usize];
 
-            let mat = [
-                point.x, point.y, point.z, 0.0, //
-                0.0, 0.0, 1.0, 1.0, //
-                0.0, 0.0, 0.0, 1.0, //
-                scale.x, scale.y, scale.z, 0.0, //
-            ];
+            let color = HAZARD_COLORS[(plane.severity as usize).min(HAZARD_COLORS.len() - 1)];
 
-            dest.extend_from_slice(bytemuck::cast_slice(&mat));
+            self.cell_matrix(x, y, plane, color.into()).push_to(dest);
+        }
+    }
+
+    /// Places a cell on its plane.
+    fn cell_matrix(&self, x: i32, y: i32, plane: &CheckPlane, color: glm::Vec4) -> InstanceMatrix {
+        InstanceMatrix {
+            center: glm::vec3(x as f32 * self.snap, plane.height, y as f32 * self.snap),
+            tex: color,
+            rot: glm::quat_identity(),
+            scale: glm::vec3(self.snap, 1.0, self.snap),
+        }
+    }
+}
+
+/// Hazard cells of recent time steps, so cells can be colored by how often
+/// lines crossed them rather than flickering from step to step.
+#[derive(Default)]
+pub struct HazardHistory {
+    /// Number of time steps counted. Zero draws only the current step
+    window: usize,
+    /// Checks of the most recent time steps, oldest first
+    steps: VecDeque<(usize, HazardCheck)>,
+}
+
+impl HazardHistory {
+    pub fn new(window: usize) -> Self {
+        Self {
+            window,
+            steps: VecDeque::new(),
+        }
+    }
+
+    pub fn window(&self) -> usize {
+        self.window
+    }
+
+    /// Changes the number of time steps counted, forgetting any that no
+    /// longer fit.
+    pub fn set_window(&mut self, window: usize) {
+        self.window = window;
+        self.trim();
+    }
+
+    /// Records the cells of a time step. A step recorded again, such as after
+    /// a settings change, replaces its earlier cells.
+    pub fn record(&mut self, time_step: usize, check: HazardCheck) {
+        if self.steps.back().is_some_and(|(t, _)| *t == time_step) {
+            self.steps.pop_back();
+        }
+
+        self.steps.push_back((time_step, check));
+        self.trim();
+    }
+
+    fn trim(&mut self) {
+        while self.steps.len() > self.window.max(1) {
+            self.steps.pop_front();
+        }
+    }
+
+    /// Writes hazard cell instances for the latest step.
+    ///
+    /// Without a window, cells are drawn in severity colors. Otherwise, every
+    /// cell crossed within the window is drawn, colored from faint yellow to
+    /// red by the fraction of steps it was crossed in, whatever the phase.
+    /// Steps checked on a different grid, before the domain changed, are
+    /// left out.
+    pub fn create_matrices(&self, dest: &mut Vec<u8>) {
+        let Some((_, latest)) = self.steps.back() else {
+            return;
+        };
+
+        if self.window == 0 {
+            latest.create_matrices(dest);
+            return;
+        }
+
+        let mut counts = HashMap::<(i32, i32, i32), usize>::new();
+
+        for (_, check) in self.steps.iter().filter(|(_, c)| c.snap == latest.snap) {
+            for &cell in &check.map_intersect {
+                *counts.entry(cell).or_default() += 1;
+            }
+        }
+
+        let mut cells: Vec<_> = counts.into_iter().collect();
+        cells.sort_unstable();
+
+        for ((x, y, level), count) in cells {
+            let Some(plane) = latest.planes.get(level as usize) else {
+                continue;
+            };
+
+            let frequency = count as f32 / self.window as f32;
+
+            let color = glm::lerp(
+                &HAZARD_RARE_COLOR.into(),
+                &HAZARD_CHRONIC_COLOR.into(),
+                frequency,
+            );
+
+            latest.cell_matrix(x, y, plane, color).push_to(dest);
         }
     }
 }
@@ -300,68 +629,193 @@ impl HazardCheck {
 /// Builds per-instance transforms for all power lines and detects hazard zones.
 ///
 /// Outputs both instance matrices and, if applicable, intersection hazard boxes.
+/// Lines are colored according to `coloring`, tinted by reactive sign with
+/// `reactive_sign`, and fanned out by `phase`. A hue returned by `highlight`,
+/// given a line's position in `src`, overrides all of that.
+/// With `violations_only`, lines with a safe average voltage are skipped.
+/// Lines are tested against the voltage limits into `hazards`, if given.
+/// After each instance, `extra` may append attributes for the line at that
+/// position in `src`. With `gradient`, the saturations at the start and end of
+/// the line then follow, as two floats, so clients can blend along the tube.
 #[allow(clippy::too_many_arguments)]
-pub fn recompute_lines<F>(
+pub fn recompute_lines<F, H, X>(
     src: &[LineState],
     getter: F,
     d: &Domain,
     offset: glm::Vec3,
+    phase: usize,
     color_band: f32,
     dest: &mut Vec<u8>,
-    hazard_parts: &mut Vec<u8>,
+    mut hazards: Option<&mut HazardCheck>,
     line_load: bool,
+    violations_only: bool,
+    coloring: LineColoring,
+    reactive_sign: bool,
+    highlight: H,
+    extra: X,
+    gradient: bool,
 ) where
     F: Fn(&LineState) -> LineGetterResult,
+    H: Fn(usize) -> Option<f32>,
+    X: Fn(usize, &mut Vec<u8>),
 {
     log::debug!("Recompute line {}", src.len());
 
-    let mut checker = HazardCheck::new(d);
-
-    for state in src.iter() {
+    for (i, state) in src.iter().enumerate() {
         // Process each line, converting to instance data and checking for hazards
 
+        if violations_only {
+            let st = getter(state);
+            let safety = d.voltage_safety((st.volt_start + st.volt_end) / 2.0);
+
+            if matches!(safety, VoltageSafety::Safe) {
+                continue;
+            }
+        }
+
+        let highlight = highlight(i);
+
         let Some(matrix) = state_to_line(
             state,
             &getter,
             |st, _len| {
-                let safety = d.voltage_safety((st.volt_start + st.volt_end) / 2.0);
-
-                glm::vec4(color_band, safety_to_saturation(safety), 1.0, 1.0)
+                // Highlighted lines are fully saturated in their own hue
+                if let Some(hue) = highlight {
+                    return glm::vec4(hue, 1.0, 1.0, 1.0);
+                }
+
+                let saturation = match coloring {
+                    LineColoring::VoltageSafety => {
+                        safety_to_saturation(d.voltage_safety((st.volt_start + st.volt_end) / 2.0))
+                    }
+                    LineColoring::LineLoad => line_load_to_saturation(st.line_load),
+                };
+
+                glm::vec4(
+                    element_band(color_band, st, reactive_sign),
+                    saturation,
+                    1.0,
+                    1.0,
+                )
             },
             |_, a, b| {
-                checker.check(a, b);
+                if let Some(checker) = hazards.as_deref_mut() {
+                    checker.check(a, b);
+                }
             },
             d,
             offset,
+            phase,
             line_load,
         ) else {
             continue;
         };
 
-        dest.extend_from_slice(bytemuck::cast_slice(&matrix));
+        matrix.push_to(dest);
+        extra(i, dest);
+
+        if gradient {
+            let st = getter(state);
+            let (start, end) = match (highlight, coloring) {
+                (Some(_), _) => (1.0, 1.0),
+                (None, LineColoring::VoltageSafety) => (
+                    safety_to_saturation(d.voltage_safety(st.volt_start)),
+                    safety_to_saturation(d.voltage_safety(st.volt_end)),
+                ),
+                (None, LineColoring::LineLoad) => {
+                    let saturation = line_load_to_saturation(st.line_load);
+                    (saturation, saturation)
+                }
+            };
+
+            dest.extend_from_slice(&start.to_le_bytes());
+            dest.extend_from_slice(&end.to_le_bytes());
+        }
     }
+}
 
-    if !line_load {
-        // Generate hazard geometry for intersections with voltage limits
+/// Builds faded per-instance transforms for a previous time step's lines.
+///
+/// Lines are placed and colored as in [`recompute_lines`], with `alpha` written
+/// to the last color component. No hazard checks are made.
+#[allow(clippy::too_many_arguments)]
+pub fn recompute_line_trail<F>(
+    src: &[LineState],
+    getter: F,
+    d: &Domain,
+    offset: glm::Vec3,
+    phase: usize,
+    color_band: f32,
+    alpha: f32,
+    dest: &mut Vec<u8>,
+    line_load: bool,
+    coloring: LineColoring,
+    reactive_sign: bool,
+) where
+    F: Fn(&LineState) -> LineGetterResult,
+{
+    for state in src.iter() {
+        let Some(matrix) = state_to_line(
+            state,
+            &getter,
+            |st, _len| {
+                let saturation = match coloring {
+                    LineColoring::VoltageSafety => {
+                        safety_to_saturation(d.voltage_safety((st.volt_start + st.volt_end) / 2.0))
+                    }
+                    LineColoring::LineLoad => line_load_to_saturation(st.line_load),
+                };
+
+                glm::vec4(
+                    element_band(color_band, st, reactive_sign),
+                    saturation,
+                    1.0,
+                    alpha,
+                )
+            },
+            |_, _, _| {},
+            d,
+            offset,
+            phase,
+            line_load,
+        ) else {
+            continue;
+        };
 
-        checker.create_matrices(hazard_parts);
+        matrix.push_to(dest);
     }
 }
 
+/// Width of ground line tubes.
+const GROUND_LINE_WIDTH: f32 = 0.005;
+
+/// Color texture coordinates of ground lines.
+pub const GROUND_LINE_TEX: [f32; 4] = [0.1, 0.5, 1.0, 1.0];
+
 /// Creates low-lying "ground lines" that visually represent line topology on the ground.
-pub fn recompute_gound_lines(src: &[LineState], d: &Domain, dest: &mut Vec<u8>) {
+///
+/// With `ground_dash` set in the domain, each line is broken into dashes, so
+/// the projection reads differently from real conductors. This costs one
+/// instance per dash. As with [`recompute_lines`], `extra` may append
+/// attributes after each instance.
+pub fn recompute_gound_lines(
+    src: &[LineState],
+    d: &Domain,
+    dest: &mut Vec<u8>,
+    extra: impl Fn(usize, &mut Vec<u8>),
+) {
     log::debug!("Recompute ground line {}", src.len());
 
-    for state in src {
+    for (i, state) in src.iter().enumerate() {
         let p_a = glm::vec3(
             d.lerp_x(state.loc.sx as f32),
-            0.0,
+            d.scene_y_offset,
             d.lerp_y(state.loc.sy as f32),
         );
 
         let p_b = glm::vec3(
             d.lerp_x(state.loc.ex as f32),
-            0.0,
+            d.scene_y_offset,
             d.lerp_y(state.loc.ey as f32),
         );
 
@@ -372,42 +826,72 @@ pub fn recompute_gound_lines(src: &[LineState], d: &Domain, dest: &mut Vec<u8>)
         }
 
         let rot = roll_free_rotation(v.normalize());
-        let rot_vec = rot.as_vector();
 
-        let center = (p_a + p_b) / 2.0;
+        let len = v.magnitude();
+
+        let Some(dash) = d.ground_dash else {
+            // Basic blue line with uniform thin tube and neutral transform
+            InstanceMatrix {
+                center: (p_a + p_b) / 2.0,
+                tex: GROUND_LINE_TEX.into(),
+                rot,
+                scale: glm::vec3(GROUND_LINE_WIDTH, GROUND_LINE_WIDTH, len),
+            }
+            .push_to(dest);
+            extra(i, dest);
+            continue;
+        };
 
-        // Basic blue line with uniform thin tube and neutral transform
-        let matrix: [f32; 16] = [
-            center.x,
-            center.y,
-            center.z,
-            0.0, // 3
-            0.1,
-            0.5,
-            1.0,
-            1.0, // 7
-            rot_vec.x,
-            rot_vec.y,
-            rot_vec.z,
-            rot_vec.w, // 11
-            0.005,
-            0.005,
-            v.magnitude(),
-            0.0, // 15
-        ];
+        let dir = (p_b - p_a) / len;
+        let period = dash + d.ground_gap;
+
+        // Lay dashes from the start, cutting the last one short at the end
+        let mut start = 0.0;
+
+        while start < len {
+            let end = (start + dash).min(len);
+
+            InstanceMatrix {
+                center: p_a + dir * ((start + end) / 2.0),
+                tex: GROUND_LINE_TEX.into(),
+                rot,
+                scale: glm::vec3(GROUND_LINE_WIDTH, GROUND_LINE_WIDTH, end - start),
+            }
+            .push_to(dest);
+            extra(i, dest);
 
-        dest.extend_from_slice(bytemuck::cast_slice(&matrix));
+            start += period;
+        }
     }
 }
 
+/// Chevron repeats per unit of line length at full flow.
+const FLOW_REPEAT: f32 = 30.0;
+
+/// Fraction of full chevron density shown for near-zero flow. Keeps trickles
+/// visible and avoids a degenerate texture scale.
+const FLOW_MIN_DENSITY: f32 = 0.1;
+
+/// Computes the chevron repeat count along a line, so that heavier flows
+/// (relative to `watt_bounds`) show denser arrows.
+fn flow_repeat(watt: f32, len: f32, d: &Domain) -> f32 {
+    let density = watt
+        .abs()
+        .clamped_lerp(0.0, d.watt_bounds, FLOW_MIN_DENSITY, 1.0);
+
+    FLOW_REPEAT * len * density
+}
+
 /// Generates flowing visual instances based on power or voltage.
 ///
-/// Encodes flow rate into texture scale and slight geometry padding for effect.
+/// Encodes flow rate into chevron density and slight geometry padding for effect.
+/// Flows follow the conductor of `phase`, placed as in [`recompute_lines`].
 pub fn recompute_line_flows<F>(
     src: &[LineState],
     getter: F,
     domain: &Domain,
     offset: glm::Vec3,
+    phase: usize,
     dest: &mut Vec<u8>,
     use_line_load: bool,
 ) where
@@ -419,26 +903,31 @@ pub fn recompute_line_flows<F>(
         let Some(mut matrix) = state_to_line(
             state,
             &getter,
-            |_, len| glm::vec4(0.0, 0.0, 30.0 * len, 1.0),
+            |r, len| glm::vec4(0.0, 0.0, flow_repeat(r.watt, len, domain), 1.0),
             |_, _, _| {},
             domain,
             offset,
+            phase,
             use_line_load,
         ) else {
             continue;
         };
 
         // Nudge tube width/height slightly for visual separation
-        matrix[12] += 0.002;
-        matrix[13] += 0.002;
+        matrix.scale.x += 0.002;
+        matrix.scale.y += 0.002;
 
-        dest.extend_from_slice(bytemuck::cast_slice(&matrix));
+        matrix.push_to(dest);
     }
 }
 
 /// Builds transformer visual elements using height-based scaling.
 ///
-/// Includes both the main transformer and a "link" tube to the baseline.
+/// Transformers at a single location are drawn as a vertical tube between
+/// their from and to voltages. Those with distinct from and to buses are
+/// drawn as a tube between the two. Both include a "link" tube to the
+/// baseline. Phases are kept apart by `offset`, see
+/// [`Domain::tf_phase_offset`].
 pub fn recompute_tfs<F>(
     src: &[TransformerState],
     getter: F,
@@ -458,79 +947,85 @@ pub fn recompute_tfs<F>(
             tap_change: _,
         } = getter(state);
 
+        let height_a = d.voltage_to_height(volt_start);
+        let height_b = d.voltage_to_height(volt_end);
+
         let p_a = glm::vec3(
             d.lerp_x(state.loc.sx as f32),
-            d.voltage_to_height(volt_start),
+            height_a,
             d.lerp_y(state.loc.sy as f32),
         ) + offset;
 
         let p_b = glm::vec3(
-            d.lerp_x(state.loc.sx as f32),
-            d.voltage_to_height(volt_end),
-            d.lerp_y(state.loc.sy as f32),
+            d.lerp_x(state.loc.ex as f32),
+            height_b,
+            d.lerp_y(state.loc.ey as f32),
         ) + offset;
 
+        let single_point = state.loc.sx == state.loc.ex && state.loc.sy == state.loc.ey;
+
         // log::debug!(
         //     "Recompute: {volt_start} {volt_end} {} {} {p_a} {p_b}",
         //     state.loc.sx,
         //     state.loc.sy
         // );
 
-        //let v = p_b - p_a;
-
-        // reverse?
-
-        //let rot = roll_free_rotation(v.normalize());
-
         let center = (p_a + p_b) / 2.0;
 
         let height = (p_b.y - p_a.y).abs();
 
-        if p_a.y < 0.000001 || p_b.y < 0.000001 {
+        if height_a < 0.000001 || height_b < 0.000001 {
             //log::debug!("SKIP TF");
             continue;
         }
 
         let texture = glm::vec2(color_band, 0.6);
 
-        // First tube: transformer height bounds
-        let mat = [
-            center.x, center.y, center.z, 0.0, //
-            texture.x, texture.y, 1.0, 1.0, //
-            0.0, 0.0, 0.0, 1.0, //
-            d.tube_max, height, d.tube_max, 0.0, //
-        ];
+        // First tube: transformer height bounds, or a span between buses
+        let (rot, scale) = if single_point {
+            (
+                glm::quat_identity(),
+                glm::vec3(d.tube_max, height, d.tube_max),
+            )
+        } else {
+            let v = p_b - p_a;
+
+            (
+                roll_free_rotation(v.normalize()),
+                glm::vec3(d.tube_max, d.tube_max, v.magnitude()),
+            )
+        };
 
-        dest.extend_from_slice(bytemuck::cast_slice(&mat));
+        InstanceMatrix {
+            center,
+            tex: glm::vec4(texture.x, texture.y, 1.0, 1.0),
+            rot,
+            scale,
+        }
+        .push_to(dest);
 
         // Second tube: thin baseline connection to floor
-        let hx = d.volt_height_max - d.volt_height_min;
+        let hx = d.exaggerate(d.volt_height_max - d.volt_height_min);
 
         // thinner tube to show tf to map
-        let mat = [
-            center.x,
-            hx / 2.0,
-            center.z,
-            0.0, //
-            texture.x,
-            texture.y,
-            1.0,
-            1.0, //
-            0.0,
-            0.0,
-            0.0,
-            1.0, //
-            d.tube_min,
-            hx,
-            d.tube_min,
-            0.0, //
-        ];
-
-        dest.extend_from_slice(bytemuck::cast_slice(&mat));
+        InstanceMatrix {
+            center: glm::vec3(center.x, offset.y + hx / 2.0, center.z),
+            tex: glm::vec4(texture.x, texture.y, 1.0, 1.0),
+            rot: glm::quat_identity(),
+            scale: glm::vec3(d.tube_min, hx, d.tube_min),
+        }
+        .push_to(dest);
     }
 }
 
+/// Brightness of an idle generator when sized by capacity
+const GEN_IDLE_VALUE: f32 = 0.25;
+
 /// Builds generator instance transforms with voltage-aware height and width.
+///
+/// With `use_capacity`, generators that have a nameplate capacity are sized
+/// by it, and brightened by their output. Others are sized by output.
+#[allow(clippy::too_many_arguments)]
 pub fn recompute_gens<F>(
     src: &[GeneratorState],
     getter: F,
@@ -538,6 +1033,8 @@ pub fn recompute_gens<F>(
     offset: glm::Vec3,
     dest: &mut Vec<u8>,
     use_line_load: bool,
+    show_angle: bool,
+    use_capacity: bool,
 ) where
     F: Fn(&GeneratorState) -> GeneratorGetterResult,
 {
@@ -545,10 +1042,11 @@ pub fn recompute_gens<F>(
     for state in src {
         let GeneratorGetterResult {
             voltage,
-            angle: _,
+            angle,
             real,
             react,
             ty,
+            capacity,
         } = getter(state);
 
         let height = if use_line_load {
@@ -563,7 +1061,15 @@ pub fn recompute_gens<F>(
             d.lerp_y(state.loc.sy as f32),
         ) + offset;
 
-        let width = d.real_power_to_width(real.abs()) * 2.0;
+        // With a known capacity, size shows the plant and brightness how
+        // much of it is in use
+        let (width, value) = match capacity.filter(|c| use_capacity && *c > 0.0) {
+            Some(capacity) => (
+                d.generator_size(capacity),
+                (real.abs() / capacity).clamped_lerp(0.0, 1.0, GEN_IDLE_VALUE, 1.0),
+            ),
+            None => (d.generator_size(real), 1.0),
+        };
         //let height = d.reactive_power_to_width(react.abs()) * 2.0;
         let height = width;
 
@@ -578,15 +1084,237 @@ pub fn recompute_gens<F>(
             _ => 0.5,
         };
 
+        // Lean the glyph's stalk by the phase angle (degrees), like a dial
+        let rot = if show_angle {
+            glm::quat_angle_axis(-angle.to_radians(), &glm::Vec3::z())
+        } else {
+            glm::quat_identity()
+        };
+
         log::debug!("GEN {p_a:?} {real} {width} | {react} {height} | {hue} {sat}");
 
-        let mat = [
-            p_a.x, p_a.y, p_a.z, 0.0, //
-            hue, sat, 1.0, 1.0, //
-            0.0, 0.0, 0.0, 1.0, //
-            width, height, width, 0.0, //
+        InstanceMatrix {
+            center: p_a,
+            tex: glm::vec4(hue, sat, value, 1.0),
+            rot,
+            scale: glm::vec3(width, height, width),
+        }
+        .push_to(dest);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dots::{EndPhased, EndedPosition, Phased};
+
+    #[test]
+    fn instance_matrix_layout() {
+        let rot = glm::quat(0.1, 0.2, 0.3, 0.9);
+
+        let matrix = InstanceMatrix {
+            center: glm::vec3(1.0, 2.0, 3.0),
+            tex: glm::vec4(4.0, 5.0, 6.0, 7.0),
+            rot,
+            scale: glm::vec3(8.0, 9.0, 10.0),
+        };
+
+        // Laid out as the matrices were built inline before
+        let rot_vec = rot.as_vector();
+        let expected = [
+            1.0, 2.0, 3.0, 0.0, //
+            4.0, 5.0, 6.0, 7.0, //
+            rot_vec.x, rot_vec.y, rot_vec.z, rot_vec.w, //
+            8.0, 9.0, 10.0, 0.0, //
         ];
 
-        dest.extend_from_slice(bytemuck::cast_slice(&mat));
+        assert_eq!(matrix.to_array(), expected);
+
+        let mut dest = vec![0xAA];
+        matrix.push_to(&mut dest);
+
+        assert_eq!(dest.len(), 1 + InstanceMatrix::STRIDE);
+        assert_eq!(&dest[1..], bytemuck::cast_slice::<f32, u8>(&expected));
+    }
+
+    #[test]
+    fn subsample_keeps_evenly_spaced_instances() {
+        let mut buffer: Vec<u8> = (0..10).collect();
+
+        // Five instances of two bytes, thinned to two
+        assert_eq!(subsample_instances(&mut buffer, 2, 2), 3);
+        assert_eq!(buffer, [0, 1, 4, 5]);
+
+        let mut buffer: Vec<u8> = (0..10).collect();
+
+        assert_eq!(subsample_instances(&mut buffer, 2, 0), 5);
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn subsample_leaves_small_buffers_alone() {
+        let mut empty = vec![];
+        assert_eq!(subsample_instances(&mut empty, 4, 0), 0);
+        assert!(empty.is_empty());
+
+        // Already within the limit
+        let mut buffer: Vec<u8> = (0..8).collect();
+        assert_eq!(subsample_instances(&mut buffer, 4, 2), 0);
+        assert_eq!(buffer.len(), 8);
+
+        // A stride longer than the buffer holds no whole instance
+        assert_eq!(subsample_instances(&mut buffer, 16, 0), 0);
+        assert_eq!(buffer.len(), 8);
+    }
+
+    #[test]
+    fn phase_c_reads_power_at_the_from_end() {
+        let end = |s: f32, e: f32| EndPhased {
+            sa: s,
+            sb: s + 1.0,
+            sc: s + 2.0,
+            ea: e,
+            eb: e + 1.0,
+            ec: e + 2.0,
+        };
+
+        let state = LineState {
+            voltage: end(1.0, 11.0),
+            real_power: end(20.0, 30.0),
+            // Capacitive at the from end, inductive at the to end
+            reactive_power: end(-42.0, 50.0),
+            loc: EndedPosition {
+                sx: 0.0,
+                sy: 0.0,
+                ex: 1.0,
+                ey: 1.0,
+            },
+            line_load: Phased {
+                a: 0.1,
+                b: 0.2,
+                c: 0.3,
+            },
+        };
+
+        for phase in 0..3 {
+            let r = LineGetterResult::phase(&state, phase);
+            let offset = phase as f32;
+
+            assert_eq!(r.volt_start, 1.0 + offset);
+            assert_eq!(r.volt_end, 11.0 + offset);
+            assert_eq!(r.watt, 20.0 + offset);
+            assert_eq!(r.vars, -42.0 + offset);
+            assert!(!r.inductive);
+        }
+
+        assert_eq!(LineGetterResult::phase(&state, 2).line_load, 0.3);
+    }
+
+    /// A check on a grid of unit cells, with a low plane at 0 and a high
+    /// plane at 2.
+    fn unit_check() -> HazardCheck {
+        HazardCheck {
+            snap: 1.0,
+            planes: vec![
+                CheckPlane {
+                    height: 0.0,
+                    severity: 1,
+                    low: true,
+                },
+                CheckPlane {
+                    height: 2.0,
+                    severity: 1,
+                    low: false,
+                },
+            ],
+            map_intersect: Default::default(),
+        }
+    }
+
+    /// Crosses the low plane in the cell at `x`.
+    fn cross_low(check: &mut HazardCheck, x: f32) {
+        check.check(glm::vec3(x, -1.0, 0.0), glm::vec3(x, 1.0, 0.0));
+    }
+
+    /// Colors of the instances written to a buffer.
+    fn instance_colors(bytes: &[u8]) -> Vec<[f32; 4]> {
+        bytes
+            .chunks_exact(InstanceMatrix::STRIDE)
+            .map(|m| {
+                std::array::from_fn(|i| {
+                    let at = (4 + i) * 4;
+                    f32::from_le_bytes(m[at..at + 4].try_into().unwrap())
+                })
+            })
+            .collect()
+    }
+
+    #[test]
+    fn phases_share_a_cell() {
+        let mut check = unit_check();
+
+        // Three phases crossing the same cell
+        for _ in 0..3 {
+            cross_low(&mut check, 0.1);
+        }
+
+        let stats = check.stats();
+        assert_eq!((stats.low, stats.high), (1, 0));
+
+        let mut dest = vec![];
+        check.create_matrices(&mut dest);
+        assert_eq!(dest.len(), InstanceMatrix::STRIDE);
+    }
+
+    #[test]
+    fn zero_window_draws_only_the_latest_step() {
+        let mut history = HazardHistory::new(0);
+
+        let mut first = unit_check();
+        cross_low(&mut first, 0.0);
+        history.record(0, first);
+
+        let mut second = unit_check();
+        cross_low(&mut second, 3.0);
+        cross_low(&mut second, 3.0);
+
+        let mut expected = vec![];
+        second.create_matrices(&mut expected);
+        history.record(1, second);
+
+        let mut dest = vec![];
+        history.create_matrices(&mut dest);
+
+        assert_eq!(dest, expected);
+        assert_eq!(instance_colors(&dest).len(), 1);
+    }
+
+    #[test]
+    fn window_colors_cells_by_frequency() {
+        let mut history = HazardHistory::new(2);
+
+        // Falls out of the window once two more steps are recorded
+        let mut stale = unit_check();
+        cross_low(&mut stale, 5.0);
+        history.record(0, stale);
+
+        let mut first = unit_check();
+        cross_low(&mut first, 0.0);
+        history.record(1, first);
+
+        let mut second = unit_check();
+        cross_low(&mut second, 0.0);
+        cross_low(&mut second, 0.0);
+        cross_low(&mut second, 3.0);
+        history.record(2, second);
+
+        let mut dest = vec![];
+        history.create_matrices(&mut dest);
+
+        let half: [f32; 4] =
+            glm::lerp(&HAZARD_RARE_COLOR.into(), &HAZARD_CHRONIC_COLOR.into(), 0.5).into();
+
+        // Cells are sorted, so the chronic cell at 0 comes first
+        assert_eq!(instance_colors(&dest), vec![HAZARD_CHRONIC_COLOR, half]);
     }
 }
@@ -2,23 +2,44 @@ use colabrodo_server::{server::*, server_messages::*};
 
 use nalgebra_glm as glm;
 
-use crate::geometry::{make_bus, make_cube, make_cyl};
+use crate::geometry::{make_bus, make_cube, make_cyl, make_sphere_with_material};
+use crate::instance::{BusStyle, InstanceMatrix};
 
 /// Represents a template for instancing entities with geometry and per-instance data.
 ///
-/// The `buffer` is used to store transform data (empty initially).
+/// The `buffer` is used to store transform data (empty initially). Each
+/// instance is an [`InstanceMatrix`], followed by `extra_floats` attributes.
 pub struct InstancedItem {
     pub entity: EntityReference,
     pub geometry: GeometryReference,
     pub buffer: Vec<u8>,
+    /// Instance data of the last upload, or `None` if nothing has been sent
+    pub sent: Option<Vec<u8>>,
+    /// Attribute floats appended to each instance after the standard layout
+    pub extra_floats: usize,
+}
+
+impl InstancedItem {
+    /// Size in bytes of one instance, including any extra attributes.
+    pub fn stride(&self) -> usize {
+        InstanceMatrix::STRIDE + self.extra_floats * std::mem::size_of::<f32>()
+    }
 }
 
 /// Creates an instanced bus element.
 ///
-/// This generates a basic bus geometry and an entity ready for instancing.
-pub fn make_bus_element(state: &mut ServerState, material: MaterialReference) -> InstancedItem {
+/// This generates a basic bus geometry, or a unit sphere, and an entity ready
+/// for instancing.
+pub fn make_bus_element(
+    state: &mut ServerState,
+    material: MaterialReference,
+    style: BusStyle,
+) -> InstancedItem {
     // Create geometry for the buses
-    let geometry = make_bus(state, glm::identity(), material);
+    let geometry = match style {
+        BusStyle::Tube => make_bus(state, glm::identity(), material),
+        BusStyle::Sphere => make_sphere_with_material(state, 0.5, material),
+    };
 
     // Create an entity to render the buses
     let entity = state.entities.new_component(ServerEntityState {
@@ -40,6 +61,8 @@ pub fn make_bus_element(state: &mut ServerState, material: MaterialReference) ->
         entity,
         geometry,
         buffer: vec![],
+        sent: None,
+        extra_floats: 0,
     }
 }
 
@@ -70,19 +93,53 @@ pub fn make_line_element(state: &mut ServerState, material: MaterialReference) -
         entity,
         geometry,
         buffer: vec![],
+        sent: None,
+        extra_floats: 0,
+    }
+}
+
+/// Creates an instanced element for the faded trail of previous line states.
+///
+/// Uses the same cube geometry as [`make_line_element`].
+pub fn make_line_trail_element(
+    state: &mut ServerState,
+    material: MaterialReference,
+) -> InstancedItem {
+    let geometry = make_cube(state, glm::identity(), material);
+
+    let entity = state.entities.new_component(ServerEntityState {
+        name: Some("Line Trail".to_string()),
+        mutable: ServerEntityStateUpdatable {
+            parent: None,
+            transform: None,
+            representation: Some(ServerEntityRepresentation::new_render(
+                ServerRenderRepresentation {
+                    mesh: geometry.clone(),
+                    instances: None,
+                },
+            )),
+            ..Default::default()
+        },
+    });
+
+    InstancedItem {
+        entity,
+        geometry,
+        buffer: vec![],
+        sent: None,
+        extra_floats: 0,
     }
 }
 
 /// Creates an instanced line element with flow texture mapping.
 ///
-/// Loads a pre-textured cube mesh from an embedded OBJ file.
+/// Loads a pre-textured cube mesh from OBJ source.
 pub fn make_line_flow_element(
     state: &mut ServerState,
     material: MaterialReference,
+    obj: &str,
 ) -> InstancedItem {
-    const TEX_CUBE: &str = include_str!("../assets/tex_cube.obj");
-
-    let contents = std::io::BufReader::new(std::io::Cursor::new(TEX_CUBE));
+    let contents = std::io::BufReader::new(std::io::Cursor::new(obj));
 
     let (cube_ent, cube_geom) =
         crate::import_obj::import_file(contents, state, None, None, Some(material))
@@ -95,6 +152,8 @@ pub fn make_line_flow_element(
         entity: cube_ent,
         geometry: cube_geom,
         buffer: vec![],
+        sent: None,
+        extra_floats: 0,
     }
 }
 
@@ -128,16 +187,20 @@ pub fn make_transformer_element(
         entity,
         geometry,
         buffer: vec![],
+        sent: None,
+        extra_floats: 0,
     }
 }
 
 /// Creates an instanced generator element.
 ///
-/// Loads a generator model from an embedded OBJ file.
-pub fn make_generator_element(state: &mut ServerState, texture: TextureReference) -> InstancedItem {
-    let contents = include_str!("../assets/generator.obj");
-
-    let contents = std::io::BufReader::new(std::io::Cursor::new(contents));
+/// Loads a generator model from OBJ source.
+pub fn make_generator_element(
+    state: &mut ServerState,
+    texture: TextureReference,
+    obj: &str,
+) -> InstancedItem {
+    let contents = std::io::BufReader::new(std::io::Cursor::new(obj));
 
     let material = state.materials.new_component(ServerMaterialState {
         name: None,
@@ -169,16 +232,20 @@ pub fn make_generator_element(state: &mut ServerState, texture: TextureReference
         entity,
         geometry,
         buffer: vec![],
+        sent: None,
+        extra_floats: 0,
     }
 }
 
 /// Creates an instanced hazard marker element.
 ///
-/// Loads a rounded rectangle mesh from an embedded OBJ file.
-pub fn make_hazard_element(state: &mut ServerState, material: MaterialReference) -> InstancedItem {
-    let contents = include_str!("../assets/rounded_rect.obj");
-
-    let contents = std::io::BufReader::new(std::io::Cursor::new(contents));
+/// Loads a rounded rectangle mesh from OBJ source.
+pub fn make_hazard_element(
+    state: &mut ServerState,
+    material: MaterialReference,
+    obj: &str,
+) -> InstancedItem {
+    let contents = std::io::BufReader::new(std::io::Cursor::new(obj));
 
     let (entity, geometry) =
         crate::import_obj::import_file(contents, state, None, None, Some(material))
@@ -191,5 +258,7 @@ pub fn make_hazard_element(state: &mut ServerState, material: MaterialReference)
         entity,
         geometry,
         buffer: vec![],
+        sent: None,
+        extra_floats: 0,
     }
 }
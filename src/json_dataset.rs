@@ -0,0 +1,424 @@
+use std::{io::BufReader, path::Path};
+
+use anyhow::Context;
+use serde::Deserialize;
+
+use crate::dots::*;
+
+/// Default divisor used when a dataset does not specify one
+fn unit_divisor() -> f64 {
+    1.0
+}
+
+/// Top level of a JSON dataset. Mirrors the `PowerSystemsData` capnp schema.
+#[derive(Debug, Deserialize)]
+struct JsonDataset {
+    #[serde(default)]
+    name: String,
+    #[serde(default)]
+    lines: Vec<JsonLine>,
+    #[serde(default)]
+    transformers: Vec<JsonTransformer>,
+    #[serde(default)]
+    generators: Vec<JsonGenerator>,
+    #[serde(default)]
+    floorplan: Option<JsonFloorplan>,
+    #[serde(default)]
+    nominal_voltage_kv: Option<f32>,
+}
+
+/// A line, with one data record per timestep
+#[derive(Debug, Deserialize)]
+struct JsonLine {
+    #[serde(default)]
+    name: Option<String>,
+    position_start_x: f64,
+    position_start_y: f64,
+    position_end_x: f64,
+    position_end_y: f64,
+    #[serde(default = "unit_divisor")]
+    voltage_divisor: f64,
+    #[serde(default = "unit_divisor")]
+    wattage_divisor: f64,
+    #[serde(default = "unit_divisor")]
+    vars_divisor: f64,
+    /// Ampacity. If given, line load records are currents in amperes
+    #[serde(default)]
+    rating: Option<f32>,
+    data: Vec<JsonLineState>,
+}
+
+#[derive(Debug, Deserialize)]
+struct JsonLineState {
+    volt_a_from: f32,
+    volt_b_from: f32,
+    volt_c_from: f32,
+    volt_a_to: f32,
+    volt_b_to: f32,
+    volt_c_to: f32,
+    real_a_from: f32,
+    real_b_from: f32,
+    real_c_from: f32,
+    real_a_to: f32,
+    real_b_to: f32,
+    real_c_to: f32,
+    react_a_from: f32,
+    react_b_from: f32,
+    react_c_from: f32,
+    react_a_to: f32,
+    react_b_to: f32,
+    react_c_to: f32,
+    #[serde(default)]
+    line_load_real_a: f32,
+    #[serde(default)]
+    line_load_real_b: f32,
+    #[serde(default)]
+    line_load_real_c: f32,
+}
+
+/// A transformer, with one data record per timestep
+#[derive(Debug, Deserialize)]
+struct JsonTransformer {
+    position_x: f64,
+    position_y: f64,
+    /// To bus location, if it differs from the from bus at `position_x/y`
+    #[serde(default)]
+    position_end_x: Option<f64>,
+    #[serde(default)]
+    position_end_y: Option<f64>,
+    #[serde(default = "unit_divisor")]
+    voltage_divisor: f64,
+    data: Vec<JsonTransformerState>,
+}
+
+#[derive(Debug, Deserialize)]
+struct JsonTransformerState {
+    volt_a_from: f32,
+    volt_b_from: f32,
+    volt_c_from: f32,
+    volt_a_to: f32,
+    volt_b_to: f32,
+    volt_c_to: f32,
+    #[serde(default)]
+    tap_a: i32,
+    #[serde(default)]
+    tap_b: i32,
+    #[serde(default)]
+    tap_c: i32,
+    #[serde(default)]
+    tap_changes_a: i32,
+    #[serde(default)]
+    tap_changes_b: i32,
+    #[serde(default)]
+    tap_changes_c: i32,
+}
+
+/// A generator, with one data record per timestep
+#[derive(Debug, Deserialize)]
+struct JsonGenerator {
+    position_x: f64,
+    position_y: f64,
+    #[serde(default = "unit_divisor")]
+    voltage_divisor: f64,
+    #[serde(default, rename = "type")]
+    ty: String,
+    /// Nameplate real power, in the same units as `real`
+    #[serde(default)]
+    capacity: Option<f32>,
+    data: Vec<JsonGeneratorState>,
+}
+
+#[derive(Debug, Deserialize)]
+struct JsonGeneratorState {
+    volt_a: f32,
+    volt_b: f32,
+    volt_c: f32,
+    #[serde(default)]
+    angle_a: f32,
+    #[serde(default)]
+    angle_b: f32,
+    #[serde(default)]
+    angle_c: f32,
+    real: f32,
+    react: f32,
+}
+
+/// Floorplan placement. The image is loaded from a path relative to the
+/// dataset file.
+#[derive(Debug, Deserialize)]
+struct JsonFloorplan {
+    lower_left_x: f64,
+    lower_left_y: f64,
+    upper_right_x: f64,
+    upper_right_y: f64,
+    image_path: String,
+}
+
+/// Loads a `PowerSystem` from a JSON file on disk.
+///
+/// The same divisor scaling and (Elements -> Times) to (Times -> Elements)
+/// transposition as the capnp loader is applied.
+///
+/// # Errors
+///
+/// This function will return an error if the file cannot be read, does not
+/// match the expected schema, or has a different number of samples for
+/// lines, transformers, and generators.
+pub fn load_powersystem_json(
+    path: &Path,
+    opts: &LoadOptions,
+) -> Result<PowerSystem, anyhow::Error> {
+    let file = std::fs::File::open(path)?;
+    let ds: JsonDataset = serde_json::from_reader(BufReader::new(file))?;
+
+    if let Some((i, l)) = ds
+        .lines
+        .iter()
+        .enumerate()
+        .find(|(_, l)| l.rating.is_some_and(|r| r <= 0.0))
+    {
+        anyhow::bail!(
+            "Line {} has a rating that is not positive",
+            l.name.clone().unwrap_or_else(|| i.to_string())
+        );
+    }
+
+    let lines = transpose(
+        "Line",
+        &ds.lines,
+        |i, l| l.name.clone().unwrap_or_else(|| i.to_string()),
+        |l| &l.data,
+        |l, a| LineState {
+            voltage: EndPhased {
+                sa: a.volt_a_from,
+                sb: a.volt_b_from,
+                sc: a.volt_c_from,
+                ea: a.volt_a_to,
+                eb: a.volt_b_to,
+                ec: a.volt_c_to,
+            }
+            .scaled(l.voltage_divisor as f32),
+            real_power: EndPhased {
+                sa: a.real_a_from,
+                sb: a.real_b_from,
+                sc: a.real_c_from,
+                ea: a.real_a_to,
+                eb: a.real_b_to,
+                ec: a.real_c_to,
+            }
+            .scaled(l.wattage_divisor as f32),
+            reactive_power: EndPhased {
+                sa: a.react_a_from,
+                sb: a.react_b_from,
+                sc: a.react_c_from,
+                ea: a.react_a_to,
+                eb: a.react_b_to,
+                ec: a.react_c_to,
+            }
+            .scaled(l.vars_divisor as f32),
+            // Rated lines give current, which is normalized here
+            line_load: Phased {
+                a: a.line_load_real_a,
+                b: a.line_load_real_b,
+                c: a.line_load_real_c,
+            }
+            .scaled(l.rating.unwrap_or(opts.line_load_divisor)),
+            loc: EndedPosition {
+                sx: l.position_start_x,
+                sy: l.position_start_y,
+                ex: l.position_end_x,
+                ey: l.position_end_y,
+            },
+        },
+    )?;
+
+    let tfs = transpose(
+        "Transformer",
+        &ds.transformers,
+        |i, _| i.to_string(),
+        |t| &t.data,
+        |t, a| TransformerState {
+            voltage: EndPhased {
+                sa: a.volt_a_from,
+                sb: a.volt_b_from,
+                sc: a.volt_c_from,
+                ea: a.volt_a_to,
+                eb: a.volt_b_to,
+                ec: a.volt_c_to,
+            }
+            .scaled(t.voltage_divisor as f32),
+            tap: Phased {
+                a: a.tap_a,
+                b: a.tap_b,
+                c: a.tap_c,
+            },
+            tap_changes: Phased {
+                a: a.tap_changes_a,
+                b: a.tap_changes_b,
+                c: a.tap_changes_c,
+            },
+            loc: EndedPosition {
+                sx: t.position_x,
+                sy: t.position_y,
+                ex: t.position_end_x.unwrap_or(t.position_x),
+                ey: t.position_end_y.unwrap_or(t.position_y),
+            },
+        },
+    )?;
+
+    let pvs = transpose(
+        "Generator",
+        &ds.generators,
+        |i, _| i.to_string(),
+        |g| &g.data,
+        |g, a| {
+            let volt_div = g.voltage_divisor as f32;
+
+            GeneratorState {
+                voltage: Phased {
+                    a: a.volt_a / volt_div,
+                    b: a.volt_b / volt_div,
+                    c: a.volt_c / volt_div,
+                },
+                angle: Phased {
+                    a: a.angle_a,
+                    b: a.angle_b,
+                    c: a.angle_c,
+                },
+                real: a.real,
+                react: a.react,
+                loc: Position {
+                    sx: g.position_x,
+                    sy: g.position_y,
+                },
+                ty: GeneratorType::decode(&g.ty),
+                capacity: g.capacity.filter(|c| *c > 0.0),
+            }
+        },
+    )?;
+
+    let line_meta = ds
+        .lines
+        .iter()
+        .map(|l| l.name.clone().unwrap_or_else(|| "Unknown".into()))
+        .collect();
+
+    let line_ratings = ds.lines.iter().map(|l| l.rating).collect();
+
+    let floor_plan = ds
+        .floorplan
+        .map(|fp| load_floorplan(fp, path))
+        .transpose()?;
+
+    let title = if ds.name.is_empty() {
+        path.file_stem()
+            .and_then(|f| f.to_str())
+            .unwrap_or("Unknown")
+            .to_string()
+    } else {
+        ds.name
+    };
+
+    let system = PowerSystem {
+        title,
+        lines,
+        tfs,
+        pvs,
+        floor_plan,
+        line_meta,
+        line_ratings,
+        nominal_voltage_kv: ds.nominal_voltage_kv,
+    };
+
+    system.check_time_steps()?;
+
+    Ok(system)
+}
+
+/// Transposes per-element data (Elements -> Times) into (Times -> Elements).
+///
+/// The timestep count is taken from the longest element. Elements with fewer
+/// records repeat their last record.
+///
+/// # Errors
+///
+/// Returns an error naming the first element, by `name`, with no records,
+/// as the capnp loader does.
+fn transpose<E, D, S>(
+    kind: &str,
+    elements: &[E],
+    name: impl Fn(usize, &E) -> String,
+    data: impl Fn(&E) -> &Vec<D>,
+    make: impl Fn(&E, &D) -> S,
+) -> Result<Vec<Vec<S>>, anyhow::Error> {
+    let time_step_count = series_length(
+        kind,
+        elements
+            .iter()
+            .enumerate()
+            .map(|(i, e)| (name(i, e), data(e).len())),
+    )?;
+
+    Ok((0..time_step_count)
+        .map(|t| {
+            elements
+                .iter()
+                .map(|e| {
+                    let records = data(e);
+                    make(e, records.get(t).unwrap_or(&records[records.len() - 1]))
+                })
+                .collect()
+        })
+        .collect())
+}
+
+/// Reads the floorplan image referenced by a JSON dataset.
+fn load_floorplan(fp: JsonFloorplan, dataset_path: &Path) -> Result<Floorplan, anyhow::Error> {
+    let image_path = dataset_path
+        .parent()
+        .unwrap_or_else(|| Path::new("."))
+        .join(&fp.image_path);
+
+    let data = std::fs::read(&image_path)
+        .with_context(|| format!("Reading floorplan image {}", image_path.display()))?;
+
+    Ok(Floorplan {
+        ll_x: fp.lower_left_x,
+        ll_y: fp.lower_left_y,
+        ur_x: fp.upper_right_x,
+        ur_y: fp.upper_right_y,
+        data,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn transpose_repeats_last_record_of_short_elements() {
+        let elements = vec![vec![1, 2], vec![10, 20, 30, 40]];
+
+        let steps = transpose("Series", &elements, |i, _| i.to_string(), |e| e, |_, d| *d).unwrap();
+
+        assert_eq!(steps.len(), 4);
+        assert_eq!(steps, [[1, 10], [2, 20], [2, 30], [2, 40]]);
+    }
+
+    #[test]
+    fn transpose_rejects_elements_without_records() {
+        let elements = vec![vec![1, 2], vec![], vec![3]];
+
+        let err = transpose(
+            "Transformer",
+            &elements,
+            |i, _| i.to_string(),
+            |e| e,
+            |_, d| *d,
+        )
+        .unwrap_err()
+        .to_string();
+
+        assert_eq!(err, "Transformer 1 has no samples");
+    }
+}
@@ -1,13 +1,17 @@
+mod animation;
 mod arguments;
 mod basemap;
 mod chart;
 mod domain;
 mod dots;
+mod export;
 mod geometry;
 mod hazard;
 mod import_obj;
 mod instance;
 mod instanced_item;
+mod json_dataset;
+mod mesh_assets;
 mod methods;
 #[allow(clippy::all)]
 mod power_system_capnp;
@@ -29,14 +33,36 @@ use dots::*;
 /// Entry point for the noodles_grid application.
 ///
 /// Initializes logging, parses arguments, loads dataset, sets up server state,
-/// advertises via mDNS, and runs the main server loop.
+/// starts filling the scene, advertises via mDNS, and runs the main server
+/// loop until Ctrl-C. With
+/// `--export-animation`, writes the animation and exits instead.
 #[tokio::main]
 async fn main() {
-    env_logger::init();
-
     // Parse command-line arguments
     let args = Arguments::parse();
 
+    init_logging(&args);
+
+    args.validate();
+
+    // Load power system dataset from file
+    let data = load_data(&args);
+    let data_title = data.title.clone();
+    log::info!("Loaded dataset: {data_title}");
+
+    // Render the animation and exit, without starting a server
+    if let Some(path) = &args.export_animation {
+        animation::export_animation(
+            &data,
+            args.animation_metric,
+            args.chart_theme,
+            args.chart_points.map(|max| max as usize),
+            path,
+        )
+        .expect("exporting animation");
+        return;
+    }
+
     // Use specified port or fall back to default (50000)
     let port = args.port.unwrap_or(50000u16);
 
@@ -49,90 +75,247 @@ async fn main() {
     // Create a new blank server state
     let state = ServerState::new();
 
-    // Load power system dataset from file
-    let data = load_data(&args);
-    let data_title = data.title.clone();
-    log::info!("Loaded dataset: {data_title}");
-
-    let app_state = GridState::new(state.clone(), data);
+    let app_state = GridState::new(state.clone(), data, &args);
 
     GridState::post_setup(&state, &app_state);
 
-    // Perform initial work to populate instances
-    {
-        let mut lock = app_state.lock().unwrap();
-        recompute_all(&mut lock, &mut state.lock().unwrap());
+    // Populate instances and the overview chart in the background, so
+    // clients can connect right away
+    let warm_up_task = tokio::task::spawn_blocking({
+        let app_state = app_state.clone();
+        move || warm_up(&app_state)
+    });
+
+    if args.blocking_startup {
+        warm_up_task.await.expect("warming up the scene");
     }
 
     // Start mDNS service to advertise server on local network
-    let mdns = mdns_publish(opts.host.port().unwrap(), data_title);
+    let mdns = mdns_publish(opts.host.port().unwrap(), data_title, args.mdns_retries);
 
     // Enter server main loop (awaits incoming client connections)
-    server_main(opts, state).await;
+    tokio::select! {
+        _ = server_main(opts, state) => {}
+        _ = tokio::signal::ctrl_c() => {
+            log::info!("Interrupted, shutting down");
 
-    // Gracefully shut down mDNS service on exit
-    mdns.shutdown().unwrap();
+            // A second Ctrl-C skips the cleanup, in case it hangs
+            tokio::spawn(async {
+                if tokio::signal::ctrl_c().await.is_ok() {
+                    log::warn!("Interrupted again, exiting now");
+                    std::process::exit(130);
+                }
+            });
+
+            app_state.lock().unwrap().shutdown();
+        }
+    }
+
+    // Withdraw the advertisement, so the LAN does not see a dead server
+    mdns.shutdown();
+}
+
+/// Sets up the logger from `--verbose` and `--log-json`.
+///
+/// Without `-v`, only errors are logged. `RUST_LOG` overrides the level either
+/// way.
+fn init_logging(args: &Arguments) {
+    use std::io::Write;
+
+    let level = match args.verbose {
+        0 => "error",
+        1 => "info",
+        2 => "debug",
+        _ => "trace",
+    };
+
+    let mut builder =
+        env_logger::Builder::from_env(env_logger::Env::default().default_filter_or(level));
+
+    if args.log_json {
+        builder.format(|buf, record| {
+            let line = serde_json::json!({
+                "time": buf.timestamp().to_string(),
+                "level": record.level().as_str(),
+                "target": record.target(),
+                "message": record.args().to_string(),
+            });
+
+            writeln!(buf, "{line}")
+        });
+    }
+
+    builder.init();
 }
 
 /// Loads the power system dataset from the specified arguments.
 ///
+/// Files with a `.json` extension are loaded as JSON datasets, everything
+/// else is treated as a capnp pack.
+///
 /// Panics if loading fails.
 fn load_data(args: &Arguments) -> PowerSystem {
-    load_powersystem(&args.pack_path).expect("loading powersystem")
+    let is_json = args
+        .pack_path
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("json"));
+
+    let opts = LoadOptions {
+        use_mmap: args.mmap,
+        line_load_divisor: args.line_load_divisor.unwrap_or(1.0),
+        unlimited_traversal: args.unlimited_traversal,
+    };
+
+    let mut system = if is_json {
+        json_dataset::load_powersystem_json(&args.pack_path, &opts).expect("loading powersystem")
+    } else {
+        load_powersystem(&args.pack_path, &opts).expect("loading powersystem")
+    };
+
+    if let Some(kv) = args.nominal_voltage_kv {
+        system.nominal_voltage_kv = Some(kv);
+    }
+
+    let sanitized = system.sanitize();
+
+    if sanitized > 0 {
+        log::warn!(
+            "Dataset had {sanitized} NaN or infinite values, replaced with the last finite sample"
+        );
+    }
+
+    system
+}
+
+/// The mDNS daemon and the service it advertises.
+struct Advertisement {
+    daemon: mdns_sd::ServiceDaemon,
+    /// Full name of the registered service, once registration succeeds
+    fullname: std::sync::Arc<std::sync::Mutex<Option<String>>>,
+}
+
+impl Advertisement {
+    /// Unregisters the service, so browsers drop it right away rather than
+    /// when the record expires, and stops the daemon.
+    fn shutdown(self) {
+        if let Some(fullname) = self.fullname.lock().unwrap().take() {
+            match self.daemon.unregister(&fullname) {
+                // Give the goodbye packets a moment to go out
+                Ok(status) => {
+                    let _ = status.recv_timeout(std::time::Duration::from_secs(1));
+                }
+                Err(e) => log::warn!("unable to unregister MDNS: {e}"),
+            }
+        }
+
+        if let Err(e) = self.daemon.shutdown() {
+            log::warn!("unable to shut down MDNS: {e}");
+        }
+    }
 }
 
 /// Publishes the server via mDNS/Bonjour for easy local discovery.
 ///
-/// Registers the service under `_noodles._tcp.local.` with hostname and IP addresses.
-fn mdns_publish(port: u16, name: String) -> mdns_sd::ServiceDaemon {
+/// Registration runs in the background so a slow network does not hold up
+/// the server. Failed attempts are retried with backoff, up to `retries`
+/// times. Failing to register is not fatal; clients can still connect by
+/// address.
+fn mdns_publish(port: u16, name: String, retries: u32) -> Advertisement {
     let mdns = mdns_sd::ServiceDaemon::new().expect("unable to create mdns daemon");
 
+    let daemon = mdns.clone();
+    let fullname = std::sync::Arc::new(std::sync::Mutex::new(None));
+    let registered = fullname.clone();
+
+    tokio::spawn(async move {
+        let mut delay = std::time::Duration::from_millis(500);
+
+        for attempt in 0..=retries {
+            match mdns_register(&daemon, port, &name) {
+                Ok(service) => {
+                    *registered.lock().unwrap() = Some(service);
+                    return;
+                }
+                Err(e) => log::warn!(
+                    "unable to register MDNS (attempt {} of {}): {e}",
+                    attempt + 1,
+                    retries + 1
+                ),
+            }
+
+            if attempt < retries {
+                tokio::time::sleep(delay).await;
+                delay *= 2;
+            }
+        }
+
+        log::warn!("giving up on MDNS, clients will need to connect by address");
+    });
+
+    Advertisement {
+        daemon: mdns,
+        fullname,
+    }
+}
+
+/// Registers the service under `_noodles._tcp.local.` with hostname and IP
+/// addresses, returning the full service name.
+///
+/// Fails if the machine has no non-loopback IPv4 address yet, or the daemon
+/// rejects the service.
+fn mdns_register(
+    mdns: &mdns_sd::ServiceDaemon,
+    port: u16,
+    name: &str,
+) -> Result<String, anyhow::Error> {
     const SERVICE_TYPE: &str = "_noodles._tcp.local.";
 
     let instance_name = format!("grid: {name}");
 
     // Gather all non-loopback IPv4 addresses on the machine
-    if let Ok(nif) = local_ip_address::list_afinet_netifas() {
-        let ip_list: Vec<_> = nif
-            .iter()
-            .filter_map(|f| match f.1 {
-                std::net::IpAddr::V4(ipv4_addr) => Some(ipv4_addr),
-                _ => None,
-            })
-            .filter_map(|f| {
-                let str = f.to_string();
-                if str != "127.0.0.1" {
-                    Some(str)
-                } else {
-                    None
-                }
-            })
-            .collect();
+    let nif = local_ip_address::list_afinet_netifas()?;
 
-        let hname = hostname::get().unwrap_or_else(|_| ip_list.first().unwrap().into());
+    let ip_list: Vec<_> = nif
+        .iter()
+        .filter_map(|f| match f.1 {
+            std::net::IpAddr::V4(ipv4_addr) => Some(ipv4_addr),
+            _ => None,
+        })
+        .filter_map(|f| {
+            let str = f.to_string();
+            if str != "127.0.0.1" {
+                Some(str)
+            } else {
+                None
+            }
+        })
+        .collect();
 
-        let hname = hname.into_string().unwrap();
+    let Some(first_ip) = ip_list.first() else {
+        anyhow::bail!("no network interfaces to advertise on");
+    };
 
-        let host = format!("{}.local.", hname);
+    let hname = hostname::get().unwrap_or_else(|_| first_ip.into());
 
-        // Construct mDNS service record with IPs and port
-        let srv_info = mdns_sd::ServiceInfo::new(
-            SERVICE_TYPE,
-            &instance_name,
-            &host,
-            ip_list.as_slice(),
-            port,
-            None,
-        )
-        .expect("unable to build MDNS service information");
+    let hname = hname.into_string().unwrap();
 
-        log::info!("registering MDNS SD on {name} {ip_list:?}");
+    let host = format!("{}.local.", hname);
 
-        // Register service and log if it fails
-        if mdns.register(srv_info).is_err() {
-            log::warn!("unable to register MDNS!");
-        }
-    }
+    // Construct mDNS service record with IPs and port
+    let srv_info = mdns_sd::ServiceInfo::new(
+        SERVICE_TYPE,
+        &instance_name,
+        &host,
+        ip_list.as_slice(),
+        port,
+        None,
+    )?;
+
+    log::info!("registering MDNS SD on {name} {ip_list:?}");
+
+    let fullname = srv_info.get_fullname().to_string();
+
+    mdns.register(srv_info)?;
 
-    mdns
+    Ok(fullname)
 }
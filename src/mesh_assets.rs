@@ -0,0 +1,61 @@
+use std::path::Path;
+
+use anyhow::Context;
+
+use crate::arguments::Arguments;
+
+/// OBJ meshes used for glyphs and widgets.
+///
+/// Each mesh defaults to the copy embedded at compile time, but can be
+/// replaced by a file on disk at startup.
+#[derive(Debug)]
+pub struct MeshAssets {
+    pub generator: String,
+    pub hazard: String,
+    pub line_flow: String,
+    pub probe: String,
+    pub close: String,
+}
+
+impl MeshAssets {
+    /// Gathers meshes, reading any overrides given on the command line.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if an override file cannot be read.
+    pub fn load(args: &Arguments) -> Result<Self, anyhow::Error> {
+        Ok(Self {
+            generator: read_or(
+                args.generator_obj.as_deref(),
+                include_str!("../assets/generator.obj"),
+            )?,
+            hazard: read_or(
+                args.hazard_obj.as_deref(),
+                include_str!("../assets/rounded_rect.obj"),
+            )?,
+            line_flow: read_or(
+                args.line_flow_obj.as_deref(),
+                include_str!("../assets/tex_cube.obj"),
+            )?,
+            probe: read_or(
+                args.probe_obj.as_deref(),
+                include_str!("../assets/probe_icon.obj"),
+            )?,
+            close: read_or(
+                args.close_obj.as_deref(),
+                include_str!("../assets/close.obj"),
+            )?,
+        })
+    }
+}
+
+/// Reads an OBJ file if a path is given, otherwise uses the embedded default.
+fn read_or(path: Option<&Path>, default: &str) -> Result<String, anyhow::Error> {
+    let Some(path) = path else {
+        return Ok(default.to_string());
+    };
+
+    log::info!("Loading mesh from {}", path.display());
+
+    std::fs::read_to_string(path).with_context(|| format!("Reading mesh {}", path.display()))
+}
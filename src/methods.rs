@@ -7,10 +7,60 @@ use std::sync::Arc;
 use std::sync::Mutex;
 use std::time::Duration;
 
+use crate::chart::{generate_chart_for, SummaryMetric, CHART_SIDE_RANGE, MAX_CHART_RENDER_PIXELS};
+use crate::domain::Domain;
+use crate::export::{export_snapshot, SnapshotFormat};
+use crate::instance::{BusStyle, LineColoring};
+use crate::probe::load_probes;
+use crate::probe::pick_lines;
+use crate::probe::save_probes;
 use crate::probe::update_probes;
 use crate::probe::ClickResult;
 use crate::probe::Probe;
 use crate::state::*;
+use crate::summary::INDICATOR_COLOR;
+
+/// Builds a CBOR map with text keys, for returning structured method results.
+fn cbor_map(entries: Vec<(&str, Value)>) -> Value {
+    Value::Map(
+        entries
+            .into_iter()
+            .map(|(k, v)| (Value::Text(k.to_string()), v))
+            .collect(),
+    )
+}
+
+/// Wraps an unsigned count as a CBOR integer.
+fn cbor_count(v: usize) -> Value {
+    Value::Integer((v as u64).into())
+}
+
+/// NOODLES exception code for requests that cannot be handled.
+const INVALID_REQUEST: i32 = -32600;
+
+/// NOODLES exception code for invalid method parameters.
+const INVALID_PARAMETERS: i32 = -32602;
+
+/// NOODLES exception code for failures on the server.
+const INTERNAL_ERROR: i32 = -32603;
+
+/// Describes the current time as a method result, so clients can confirm
+/// where a time change landed without another request.
+fn time_result(app: &GridState) -> Value {
+    cbor_map(vec![
+        ("time_step", cbor_count(app.time_step)),
+        ("time_frac", Value::Float(app.time_frac() as f64)),
+    ])
+}
+
+/// Name of a line, or a placeholder if the dataset has no name for it.
+fn line_name(app: &GridState, line_i: usize) -> String {
+    app.system
+        .line_meta
+        .get(line_i)
+        .cloned()
+        .unwrap_or_else(|| format!("Line {line_i}"))
+}
 
 make_method_function!(set_time,
 GridState,
@@ -20,13 +70,16 @@ GridState,
 {
     //! Sets the current time step based on a floating-point input.
     //!
-    //! Clamps the input to valid range and triggers recomputation.
+    //! Clamps the input to valid range and triggers recomputation. Returns
+    //! the resulting time step and normalized time.
+    app.note_activity();
+
     let time : f32 = from_cbor(time).unwrap_or_default();
-    let time : usize = time as usize;
-    let time = time.clamp(0, app.max_time_step - 1);
+    let time = clamp_time_step(time as i64, app.max_time_step);
     app.time_step = time;
+    app.sub_step = 0.0;
     recompute_all(app, state);
-    Ok(None)
+    Ok(Some(time_result(app)))
 });
 
 // =============================================================================
@@ -39,35 +92,106 @@ GridState,
 {
     //! Steps the current time step by a signed integer amount.
     //!
-    //! Used for manual time navigation forward/backward.
+    //! Used for manual time navigation forward/backward. Returns the
+    //! resulting time step and normalized time.
+    app.note_activity();
 
     let time : i32 = from_cbor(time).unwrap_or_default();
-    let time = (app.time_step as i32 + time).clamp(0, app.max_time_step as i32 - 1);
+    let time = clamp_time_step(app.time_step as i64 + time as i64, app.max_time_step);
 
     log::debug!("Stepping time: {time}");
 
-    app.time_step = time as usize;
+    app.time_step = time;
+    app.sub_step = 0.0;
     recompute_all(app, state);
 
     log::debug!("All done");
-    Ok(None)
+    Ok(Some(time_result(app)))
 });
 
+/// Jumps to the nearest violating time step in a direction, returning the
+/// new time as from [`time_result`], or nothing if the data has no
+/// violations.
+fn jump_to_violation(
+    app: &mut GridState,
+    state: &mut ServerState,
+    direction: i32,
+) -> Option<Value> {
+    let time = app.find_violation(direction)?;
+
+    app.time_step = time;
+    app.sub_step = 0.0;
+
+    let time_frac = app.time_frac();
+    if let Some(summary) = &mut app.summary {
+        summary.set_time_normalized(time_frac);
+    }
+
+    recompute_all(app, state);
+
+    Some(time_result(app))
+}
+
+make_method_function!(
+    next_violation,
+    GridState,
+    "Next Violation",
+    "Jump to the next time step with a voltage violation",
+    {
+        //! Moves forward to the next time step where any line is outside
+        //! voltage limits, wrapping at the end. Returns the resulting time
+        //! step and normalized time.
+        app.note_activity();
+
+        Ok(jump_to_violation(app, state, 1))
+    }
+);
+
+make_method_function!(
+    prev_violation,
+    GridState,
+    "Previous Violation",
+    "Jump to the previous time step with a voltage violation",
+    {
+        //! Moves back to the previous time step where any line is outside
+        //! voltage limits, wrapping at the start. Returns the resulting time
+        //! step and normalized time.
+        app.note_activity();
+
+        Ok(jump_to_violation(app, state, -1))
+    }
+);
+
+/// Time between playback time steps.
+const STEP_INTERVAL: f32 = 0.25;
+
 /// Periodically signals a timer channel until cancelled.
 ///
-/// Runs in a background task to drive automatic time advancement.
+/// Runs in a background task to drive automatic time advancement. Ticks are
+/// scheduled on a fixed grid, so a slow receiver does not delay later ticks,
+/// and ticks missed while the channel is full are dropped rather than
+/// delivered in a burst.
 async fn advance_timer(
     send_back: tokio::sync::mpsc::Sender<bool>,
     mut to_stop: tokio::sync::oneshot::Receiver<bool>,
+    interval: Duration,
 ) {
+    let mut ticker = tokio::time::interval_at(tokio::time::Instant::now() + interval, interval);
+    ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+
     loop {
         log::debug!("Advancer");
         tokio::select! {
-            _ = tokio::time::sleep(Duration::from_secs_f32(0.25)) => {
-                log::debug!("Sleep done");
-                if send_back.send(true).await.is_err() {
-                    log::debug!("closing advance timer");
-                    return
+            _ = ticker.tick() => {
+                log::debug!("Tick");
+                match send_back.try_send(true) {
+                    Ok(()) => {}
+                    // The last tick has not been handled yet; skip this one
+                    Err(tokio::sync::mpsc::error::TrySendError::Full(_)) => {}
+                    Err(tokio::sync::mpsc::error::TrySendError::Closed(_)) => {
+                        log::debug!("closing advance timer");
+                        return
+                    }
                 }
             },
             _ = &mut to_stop => {
@@ -102,7 +226,14 @@ fn check_launch_timer(gs: &mut GridState, timer_direction: i32) {
         gs.active_timer = Some(os_tx);
         let send_back = gs.send_back.clone().unwrap();
 
-        tokio::spawn(advance_timer(send_back, os_rx));
+        // Interpolated playback ticks once per sub step
+        let interval = if gs.interpolate {
+            Duration::from_secs_f32(STEP_INTERVAL / INTERPOLATION_STEPS as f32)
+        } else {
+            Duration::from_secs_f32(STEP_INTERVAL)
+        };
+
+        tokio::spawn(advance_timer(send_back, os_rx, interval));
     } else {
         // timer not running and they want a stop. skip
     }
@@ -118,6 +249,7 @@ GridState,
     //! Starts or stops automatic time playback based on a step direction.
     //!
     //! -1: reverse, 0: stop, 1: forward
+    app.note_activity();
 
     let time : i32 = from_cbor(time).unwrap_or_default();
     let time = time.clamp(-1, 1);
@@ -128,30 +260,96 @@ GridState,
     Ok(None)
 });
 
+make_method_function!(set_loop_range,
+GridState,
+"Set Loop Range",
+"Restrict playback to a range of time steps",
+| start : Value : "First time step of the loop" |,
+| end : Value : "Last time step of the loop, inclusive" |,
+{
+    //! Sets the playback loop range.
+    //!
+    //! Use 0 and the last time step to restore full range playback.
+    app.note_activity();
+
+    let start : usize = from_cbor(start).unwrap_or_default();
+    let end : usize = from_cbor(end).unwrap_or(usize::MAX);
+
+    if !app.set_loop_range(start, end) {
+        log::warn!("Ignoring loop range with start {start} after end {end}");
+    }
+
+    Ok(None)
+});
+
+make_method_function!(
+    toggle_interpolation,
+    GridState,
+    "Toggle Interpolation",
+    "Toggle smooth blending between time steps during playback",
+    {
+        //! Toggles interpolated playback, restarting the timer if it is
+        //! running so the tick rate matches.
+        app.note_activity();
+
+        app.interpolate = !app.interpolate;
+        app.sub_step = 0.0;
+
+        let direction = app.time_step_direction;
+
+        if app.active_timer.is_some() {
+            check_launch_timer(app, 0);
+            check_launch_timer(app, direction);
+        }
+
+        recompute_all(app, state);
+        Ok(None)
+    }
+);
+
 /// Watches for timer signals and advances the visualization time step.
 ///
-/// Recomputes the entire scene each time the step updates.
+/// Recomputes the entire scene each time the step updates. Ticks that queue
+/// up while a recompute is running are coalesced, advancing by all of them at
+/// once, so playback keeps pace with real time on large datasets. Playback is
+/// paused once clients have been idle for the configured timeout.
 pub async fn advance_watcher(gs: GridStatePtr, mut rx: tokio::sync::mpsc::Receiver<bool>) {
     while rx.recv().await.is_some() {
+        let mut ticks = 1;
+
+        while rx.try_recv().is_ok() {
+            ticks += 1;
+        }
+
+        if ticks > 1 {
+            log::info!(
+                "skipping {} playback steps, recompute is slower than playback",
+                ticks - 1
+            );
+        }
+
         log::debug!("advancing time");
         let mut lock = gs.lock().unwrap();
 
-        let mut new_time =
-            (lock.time_step as i32 + lock.time_step_direction) % lock.max_time_step as i32;
-
-        // do a wrapping sub here
-        if new_time < 0 {
-            new_time += lock.max_time_step as i32
+        // Nobody is watching; stop until a client starts playback again
+        if lock.is_idle() {
+            log::info!("Pausing playback, no client activity");
+            check_launch_timer(&mut lock, 0);
+            continue;
         }
 
-        lock.time_step = new_time.try_into().unwrap();
+        for _ in 0..ticks {
+            lock.advance_time_step();
+        }
 
         let ss_arc = lock.state.clone();
         let mut ss_lock = ss_arc.lock().unwrap();
 
         {
             let time_frac = lock.time_frac();
-            lock.summary.set_time_normalized(time_frac);
+            if let Some(summary) = &mut lock.summary {
+                summary.set_time_normalized(time_frac);
+            }
             //lock.summary.set_time_normalized(0.0);
         }
 
@@ -173,9 +371,7 @@ fn make_probe(gs: &mut GridState, state: &mut ServerState, context: Option<Invok
             gs.probes.pop_front();
         }
 
-        const TEX_CUBE: &str = include_str!("../assets/probe_icon.obj");
-
-        let contents = std::io::BufReader::new(std::io::Cursor::new(TEX_CUBE));
+        let contents = std::io::BufReader::new(std::io::Cursor::new(gs.meshes.probe.as_str()));
 
         let hazard_mat = state.materials.new_component(ServerMaterialState {
             name: None,
@@ -216,6 +412,45 @@ fn make_probe(gs: &mut GridState, state: &mut ServerState, context: Option<Invok
     }
 }
 
+/// Recreates probes saved to the probes file, if one was given.
+///
+/// Probes attached to lines that do not exist in the loaded dataset are
+/// skipped. A missing file is not an error, as it is created on first save.
+pub fn restore_probes(gs: &mut GridState, state: &mut ServerState) {
+    let Some(path) = gs.probes_path.clone() else {
+        return;
+    };
+
+    if !path.exists() {
+        log::info!("No probes file at {}, starting fresh", path.display());
+        return;
+    }
+
+    let saved = match load_probes(&path) {
+        Ok(saved) => saved,
+        Err(e) => {
+            log::warn!("Unable to read probes from {}: {e}", path.display());
+            return;
+        }
+    };
+
+    let line_count = gs.system.line_meta.len();
+
+    for probe in saved {
+        if probe.line >= line_count {
+            log::warn!("Skipping probe on missing line {}", probe.line);
+            continue;
+        }
+
+        make_probe(gs, state, None);
+
+        // Seat the probe; the probe service will snap it and build a chart
+        if let Some(new_probe) = gs.probes.back_mut() {
+            new_probe.dirty = Some(probe.position.into());
+        }
+    }
+}
+
 make_method_function!(
     create_probe,
     GridState,
@@ -223,11 +458,263 @@ make_method_function!(
     "Add a probe to the visualization",
     {
         //! User-invoked method to create a new probe.
+        app.note_activity();
+
         make_probe(app, state, None);
         Ok(None)
     }
 );
 
+make_method_function!(create_probe_at,
+GridState,
+"Create Probe At",
+"Add a probe to the visualization at a point",
+| position : [f32;3] : "Where to place the probe, as vec3" |,
+{
+    //! Creates a probe and moves it to `position` in one call, for scripted
+    //! or menu driven placement. The probe snaps to the nearest line within
+    //! the snap radius, as if dragged there. The oldest probe is recycled at
+    //! the probe limit.
+    app.note_activity();
+
+    if position.iter().any(|v| !v.is_finite()) {
+        return Err(MethodException {
+            code: INVALID_PARAMETERS,
+            message: Some("Position must be finite".into()),
+            data: None,
+        });
+    }
+
+    make_probe(app, state, None);
+
+    // The probe service will snap it and build a chart
+    if let Some(new_probe) = app.probes.back_mut() {
+        new_probe.dirty = Some(position.into());
+    }
+
+    Ok(None)
+});
+
+make_method_function!(
+    clear_probes,
+    GridState,
+    "Clear Probes",
+    "Remove all probes",
+    {
+        //! Removes every probe along with its handle, chart, and delete
+        //! button. Charts still being generated for them are discarded.
+        //! Returns the number of probes removed.
+        app.note_activity();
+
+        let count = app.probes.len();
+
+        // Dropping a probe releases all of its entities
+        app.probes.clear();
+
+        Ok(Some(cbor_count(count)))
+    }
+);
+
+make_method_function!(set_snap_radius,
+GridState,
+"Set Snap Radius",
+"Set how close a probe must be to a line to attach to it",
+| radius : Value : "Positive float distance, in domain units" |,
+{
+    //! Sets the probe snap radius. Existing probes are re-seated, so probes
+    //! now out of reach drop their charts and probes now in reach attach.
+    app.note_activity();
+
+    let radius : f32 = from_cbor(radius).unwrap_or_default();
+
+    if !(radius > 0.0 && radius.is_finite()) {
+        return Err(MethodException {
+            code: INVALID_PARAMETERS,
+            message: Some(format!("Snap radius must be positive, got {radius}")),
+            data: None,
+        });
+    }
+
+    app.snap_radius = radius;
+
+    for probe in &mut app.probes {
+        probe.dirty = Some(probe.world_pos);
+    }
+
+    app.probe_move_request_signal.send(true).unwrap();
+    Ok(None)
+});
+
+make_method_function!(
+    save_probe_positions,
+    GridState,
+    "Save Probes",
+    "Save probe positions so they are restored on restart",
+    {
+        //! Writes probe positions to the file given by `--probes`.
+        app.note_activity();
+
+        let Some(path) = app.probes_path.clone() else {
+            return Err(MethodException {
+                code: INVALID_REQUEST,
+                message: Some("No probes file configured, start with --probes".into()),
+                data: None,
+            });
+        };
+
+        if let Err(e) = save_probes(app, &path) {
+            log::error!("Unable to save probes to {}: {e}", path.display());
+            return Err(MethodException {
+                code: INTERNAL_ERROR,
+                message: Some(format!("Unable to save probes: {e}")),
+                data: None,
+            });
+        }
+
+        Ok(None)
+    }
+);
+
+make_method_function!(export_probe_chart,
+GridState,
+"Export Probe Chart",
+"Get the chart image of a probe",
+| probe : Value : "Integer index of the probe, oldest first" |,
+{
+    //! Returns a map of the attached line's `index` and `name`, and `png`,
+    //! the chart image as drawn for the probe with the current display
+    //! settings. The image is returned rather than written on the server.
+    app.note_activity();
+
+    let probe_i : usize = from_cbor(probe).unwrap_or(usize::MAX);
+
+    let Some(probe) = app.probes.get(probe_i) else {
+        return Err(MethodException {
+            code: INVALID_PARAMETERS,
+            message: Some(format!("No probe {probe_i}, there are {}", app.probes.len())),
+            data: None,
+        });
+    };
+
+    let line_i = probe.line_i;
+
+    // Detached probes have no chart
+    if line_i == usize::MAX {
+        return Err(MethodException {
+            code: INVALID_REQUEST,
+            message: Some(format!("Probe {probe_i} is not attached to a line")),
+            data: None,
+        });
+    }
+
+    let png = generate_chart_for(
+        line_i,
+        &app.system,
+        app.chart_size,
+        app.chart_supersample,
+        app.show_kv,
+        app.show_line_load,
+        app.chart_theme,
+        app.time_axis,
+        app.voltage_reference(),
+    );
+
+    Ok(Some(cbor_map(vec![
+        ("index", cbor_count(line_i)),
+        ("name", Value::Text(line_name(app, line_i))),
+        ("png", Value::Bytes(png)),
+    ])))
+});
+
+make_method_function!(export_snapshot,
+GridState,
+"Export Snapshot",
+"Export the state of every element at the current time step",
+| format : Value : "Text, either \"csv\" or \"json\"" |,
+{
+    //! Returns the state of every line, transformer, and generator at the
+    //! current time step as CSV or JSON text. Does not change the scene.
+    app.note_activity();
+
+    let format : String = from_cbor(format).unwrap_or_default();
+
+    let Some(format) = SnapshotFormat::parse(&format) else {
+        return Err(MethodException {
+            code: INVALID_PARAMETERS,
+            message: Some(format!("Unknown snapshot format \"{format}\"")),
+            data: None,
+        });
+    };
+
+    match export_snapshot(&app.system, app.time_step, format) {
+        Ok(text) => Ok(Some(Value::Text(text))),
+        Err(e) => Err(MethodException {
+            code: INTERNAL_ERROR,
+            message: Some(format!("Unable to export snapshot: {e}")),
+            data: None,
+        }),
+    }
+});
+
+// =============================================================================
+
+make_method_function!(get_line_state,
+GridState,
+"Get Line State",
+"Get the values of one line at the current time step",
+| line : Value : "Integer index of the line" |,
+{
+    //! Returns a map of the line's `index`, `name`, and `time_step`, with a
+    //! map for each phase (`a`, `b`, `c`) of `volt_from`, `volt_to`,
+    //! `real_from`, `real_to`, `react_from`, `react_to`, and `line_load`.
+    //! Values are as stored for the time step, without blending.
+    app.note_activity();
+
+    let line_i : usize = from_cbor(line).unwrap_or(usize::MAX);
+
+    let Some(l) = app
+        .system
+        .lines
+        .get(app.time_step)
+        .and_then(|lines| lines.get(line_i))
+    else {
+        return Err(MethodException {
+            code: INVALID_PARAMETERS,
+            message: Some(format!(
+                "No line {line_i}, there are {}",
+                app.system.line_meta.len()
+            )),
+            data: None,
+        });
+    };
+
+    let phase = |volt: (f32, f32), real: (f32, f32), react: (f32, f32), load: f32| {
+        cbor_map(vec![
+            ("volt_from", Value::Float(volt.0 as f64)),
+            ("volt_to", Value::Float(volt.1 as f64)),
+            ("real_from", Value::Float(real.0 as f64)),
+            ("real_to", Value::Float(real.1 as f64)),
+            ("react_from", Value::Float(react.0 as f64)),
+            ("react_to", Value::Float(react.1 as f64)),
+            ("line_load", Value::Float(load as f64)),
+        ])
+    };
+
+    let (v, p, q) = (&l.voltage, &l.real_power, &l.reactive_power);
+
+    Ok(Some(cbor_map(vec![
+        ("index", cbor_count(line_i)),
+        (
+            "name",
+            Value::Text(app.system.line_meta.get(line_i).cloned().unwrap_or_default()),
+        ),
+        ("time_step", cbor_count(app.time_step)),
+        ("a", phase((v.sa, v.ea), (p.sa, p.ea), (q.sa, q.ea), l.line_load.a)),
+        ("b", phase((v.sb, v.eb), (p.sb, p.eb), (q.sb, q.eb), l.line_load.b)),
+        ("c", phase((v.sc, v.ec), (p.sc, p.ec), (q.sc, q.ec), l.line_load.c)),
+    ])))
+});
+
 make_method_function!(
     item_activate,
     GridState,
@@ -235,6 +722,8 @@ make_method_function!(
     "Activate",
     {
         //! Handles activation of an item, which can trigger probe creation.
+        app.note_activity();
+
         make_probe(app, state, context);
         Ok(None)
     }
@@ -323,6 +812,8 @@ make_method_function!(set_position,
     |position : [f32;3] : "New position of entity, as vec3"|,
     {
         //! Method to update an entity's world position.
+        app.note_activity();
+
         on_move(app, state, context, position);
         Ok(None)
     }
@@ -337,13 +828,1229 @@ make_method_function!(
     "Toggle visibility of line loading",
     {
         //! Toggles between normal line coloring and line load visualization.
+        //! Probe charts are rebuilt to show line ratings while line load is
+        //! shown.
+        app.note_activity();
 
         app.show_line_load = !app.show_line_load;
+
+        for probe in app.probes.iter_mut() {
+            probe.dirty = Some(probe.world_pos);
+        }
+
+        app.probe_move_request_signal.send(true).unwrap();
+
+        recompute_all(app, state);
+        Ok(None)
+    }
+);
+
+make_method_function!(
+    toggle_angle,
+    GridState,
+    "Toggle Phase Angle",
+    "Toggle display of generator phase angle",
+    {
+        //! Toggles leaning generator glyphs by their phase angle.
+        app.note_activity();
+
+        app.show_angle = !app.show_angle;
+        recompute_all(app, state);
+        Ok(None)
+    }
+);
+
+make_method_function!(
+    toggle_reactive_sign,
+    GridState,
+    "Toggle Reactive Sign",
+    "Toggle tinting lines and buses by the sign of reactive power",
+    {
+        //! Switches lines and buses between phase colors and warm (inductive)
+        //! or cool (capacitive) tints, based on the sign of reactive power.
+        app.note_activity();
+
+        app.reactive_sign = !app.reactive_sign;
         recompute_all(app, state);
         Ok(None)
     }
 );
 
+make_method_function!(
+    toggle_tf_legend,
+    GridState,
+    "Toggle Transformer Legend",
+    "Toggle visibility of the transformer legend",
+    {
+        //! Shows or hides the legend explaining transformer tubes.
+        app.note_activity();
+
+        let visible = !app.show_tf_legend;
+        app.set_tf_legend_visible(visible);
+        Ok(None)
+    }
+);
+
+make_method_function!(
+    toggle_title,
+    GridState,
+    "Toggle Title",
+    "Toggle visibility of the dataset title banner",
+    {
+        //! Shows or hides the banner naming the dataset.
+        app.note_activity();
+
+        let visible = !app.show_title;
+        app.set_title_visible(visible);
+        Ok(None)
+    }
+);
+
+make_method_function!(
+    toggle_voltage_units,
+    GridState,
+    "Toggle Voltage Units",
+    "Toggle chart voltage labels between p.u. and kV",
+    {
+        //! Toggles kV chart labels and regenerates probe charts. Datasets
+        //! without a nominal voltage keep p.u. labels.
+        app.note_activity();
+
+        if app.system.nominal_voltage_kv.is_none() {
+            log::warn!("No nominal voltage known, charts will stay in p.u.");
+        }
+
+        app.show_kv = !app.show_kv;
+
+        for probe in app.probes.iter_mut() {
+            probe.dirty = Some(probe.world_pos);
+        }
+
+        app.probe_move_request_signal.send(true).unwrap();
+        Ok(None)
+    }
+);
+
+// =============================================================================
+
+make_method_function!(set_exaggeration,
+GridState,
+"Set Exaggeration",
+"Set the vertical exaggeration of line heights",
+| exaggeration : Value : "Floating point multiplier, must be positive" |,
+{
+    //! Sets the vertical exaggeration factor and rebuilds the scene.
+    //!
+    //! Values that are not positive and finite are rejected.
+    app.note_activity();
+
+    let exaggeration : f32 = from_cbor(exaggeration).unwrap_or_default();
+
+    if !(exaggeration > 0.0 && exaggeration.is_finite()) {
+        return Err(MethodException {
+            code: INVALID_PARAMETERS,
+            message: Some(format!("Exaggeration {exaggeration} must be positive and finite")),
+            data: None,
+        });
+    }
+
+    app.set_exaggeration(exaggeration);
+    recompute_all(app, state);
+    Ok(None)
+});
+
+// =============================================================================
+
+/// Range that a safety margin is clamped to, in p.u.
+const SAFETY_MARGIN_RANGE: (f32, f32) = (0.005, 0.5);
+
+make_method_function!(set_voltage_limits,
+GridState,
+"Set Voltage Limits",
+"Set the voltage range that is considered safe",
+| low : Value : "Lowest safe voltage, in p.u." |,
+| high : Value : "Highest safe voltage, in p.u." |,
+{
+    //! Sets the safe voltage range and rebuilds hazards and coloring.
+    app.note_activity();
+
+    let low : f32 = from_cbor(low).unwrap_or_default();
+    let high : f32 = from_cbor(high).unwrap_or_default();
+
+    if !(low > 0.0 && low < high && high.is_finite()) {
+        return Err(MethodException {
+            code: INVALID_PARAMETERS,
+            message: Some(format!("Low limit {low} must be positive and below finite high limit {high}")),
+            data: None,
+        });
+    }
+
+    app.set_voltage_limits(state, low, high);
+    recompute_all(app, state);
+    Ok(None)
+});
+
+make_method_function!(set_voltage_range,
+GridState,
+"Set Voltage Range",
+"Set the voltage range mapped onto line heights",
+| low : Value : "Voltage at the floor, in p.u., or null for the default" |,
+| high : Value : "Voltage at the top of the ruler, in p.u., or null for the default" |,
+{
+    //! Sets the voltages drawn at the lowest and highest line heights, and
+    //! redraws the ruler labels to match. Missing values fall back to the
+    //! default range.
+    app.note_activity();
+
+    let defaults = Domain::default();
+
+    let low : f32 = from_cbor(low).unwrap_or(defaults.volt_min);
+    let high : f32 = from_cbor(high).unwrap_or(defaults.volt_max);
+
+    if !(low >= 0.0 && low < high && high.is_finite()) {
+        return Err(MethodException {
+            code: INVALID_PARAMETERS,
+            message: Some(format!("Range low {low} must not be negative and must be below finite high {high}")),
+            data: None,
+        });
+    }
+
+    app.set_voltage_range(state, low, high);
+    recompute_all(app, state);
+    Ok(None)
+});
+
+// =============================================================================
+
+make_method_function!(set_height_range,
+GridState,
+"Set Height Range",
+"Set the heights that the voltage range is drawn between",
+| low : Value : "Height of the lowest voltage, in scene units, or null for the default" |,
+| high : Value : "Height of the highest voltage, in scene units, or null for the default" |,
+{
+    //! Sets the heights, before exaggeration, that the ends of the voltage
+    //! range map to, such as to flatten the scene for clients with little
+    //! vertical room. Lines, buses, transformers, hazards, and rulers all
+    //! follow. Missing values fall back to the default range.
+    app.note_activity();
+
+    let defaults = Domain::default();
+
+    let low : f32 = from_cbor(low).unwrap_or(defaults.volt_height_min);
+    let high : f32 = from_cbor(high).unwrap_or(defaults.volt_height_max);
+
+    if !(low >= 0.0 && low < high && high.is_finite()) {
+        return Err(MethodException {
+            code: INVALID_PARAMETERS,
+            message: Some(format!("Height low {low} must not be negative and must be below high {high}")),
+            data: None,
+        });
+    }
+
+    app.set_height_range(low, high);
+    recompute_all(app, state);
+    Ok(None)
+});
+
+make_method_function!(set_safety_margin,
+GridState,
+"Set Safety Margin",
+"Set the safe voltage range symmetrically around 1.0 p.u.",
+| margin : Value : "Distance from 1.0 p.u. to each limit, e.g. 0.05 for 0.95 - 1.05" |,
+{
+    //! Sets the safe voltage range to `1 - margin` to `1 + margin`, and
+    //! rebuilds hazards and coloring. The margin is clamped to a sensible
+    //! range. Non-finite margins are rejected.
+    app.note_activity();
+
+    let margin : f32 = from_cbor(margin).unwrap_or_default();
+
+    if !margin.is_finite() {
+        return Err(MethodException {
+            code: INVALID_PARAMETERS,
+            message: Some(format!("Safety margin {margin} must be finite")),
+            data: None,
+        });
+    }
+
+    let margin = margin.clamp(SAFETY_MARGIN_RANGE.0, SAFETY_MARGIN_RANGE.1);
+
+    app.set_voltage_limits(state, 1.0 - margin, 1.0 + margin);
+    recompute_all(app, state);
+    Ok(None)
+});
+
+// =============================================================================
+
+make_method_function!(set_generator_scale,
+GridState,
+"Set Generator Scale",
+"Set the size multiplier of generator glyphs",
+| scale : Value : "Floating point multiplier, must be positive" |,
+{
+    //! Sets the generator glyph size multiplier, independent of line tubes.
+    //!
+    //! Values that are not positive and finite are rejected.
+    app.note_activity();
+
+    let scale : f32 = from_cbor(scale).unwrap_or_default();
+
+    if !(scale > 0.0 && scale.is_finite()) {
+        return Err(MethodException {
+            code: INVALID_PARAMETERS,
+            message: Some(format!("Generator scale {scale} must be positive and finite")),
+            data: None,
+        });
+    }
+
+    app.domain.gen_size_scale = scale;
+    recompute_all(app, state);
+    Ok(None)
+});
+
+// =============================================================================
+
+make_method_function!(
+    toggle_generator_capacity,
+    GridState,
+    "Toggle Generator Capacity",
+    "Toggle sizing generators by nameplate capacity rather than output",
+    {
+        //! Toggles whether generators with a known capacity are sized by it,
+        //! with output shown as brightness, so large idle plants stand out
+        //! from small busy ones.
+        app.note_activity();
+
+        app.gen_capacity = !app.gen_capacity;
+        recompute_all(app, state);
+        Ok(None)
+    }
+);
+
+// =============================================================================
+
+make_method_function!(
+    toggle_flow_phases,
+    GridState,
+    "Toggle Flow Phases",
+    "Toggle animating line flow on every visible phase, rather than phase A only",
+    {
+        //! Toggles flow chevrons on phases B and C, each following its own
+        //! conductor, for comparing flow across phases. Hidden phases have
+        //! no flow either way.
+        app.note_activity();
+
+        app.flow_all_phases = !app.flow_all_phases;
+        recompute_all(app, state);
+        Ok(None)
+    }
+);
+
+// =============================================================================
+
+make_method_function!(set_phases,
+GridState,
+"Set Phases",
+"Choose which phases are rendered",
+| mask : Value : "Integer bitmask of phases. A = 1, B = 2, C = 4" |,
+{
+    //! Sets which phases are rendered and rebuilds the scene.
+    //!
+    //! Unknown bits are ignored.
+    app.note_activity();
+
+    let mask : u8 = from_cbor(mask).unwrap_or(PhaseMask::all().bits());
+
+    app.set_phases(state, PhaseMask::from_bits_truncate(mask));
+    recompute_all(app, state);
+    Ok(None)
+});
+
+// =============================================================================
+
+make_method_function!(solo_phase,
+GridState,
+"Solo Phase",
+"Render only one phase",
+| phase : Value : "\"A\", \"B\", or \"C\" to render only that phase, or \"*\" for all phases" |,
+{
+    //! Sets the rendered phases to a single phase and rebuilds the scene.
+    //! While one phase is soloed, the rulers name it.
+    app.note_activity();
+
+    let phase : String = from_cbor(phase).unwrap_or_default();
+
+    let phases = match phase.trim() {
+        "*" => PhaseMask::all(),
+        text => {
+            let mut chars = text.chars();
+
+            match (chars.next().and_then(PhaseMask::from_letter), chars.next()) {
+                (Some(mask), None) => mask,
+                _ => {
+                    return Err(MethodException {
+                        code: INVALID_PARAMETERS,
+                        message: Some(format!(
+                            "Unknown phase {phase:?}, expected \"A\", \"B\", \"C\", or \"*\""
+                        )),
+                        data: None,
+                    });
+                }
+            }
+        }
+    };
+
+    app.set_phases(state, phases);
+    recompute_all(app, state);
+    Ok(None)
+});
+
+// =============================================================================
+
+make_method_function!(set_probe_offsets,
+GridState,
+"Set Probe Offsets",
+"Set where probe charts float relative to their probes",
+| handle : Value : "Array of three floats, the chart handle offset from the probe, or null to keep" |,
+| chart : Value : "Array of three floats, the chart offset from its handle, or null to keep" |,
+{
+    //! Moves the handles and charts of all probes. Offsets are in domain
+    //! units; the defaults are (0.25, 1, 0) for the handle and (0, 0.25, 0)
+    //! for the chart. Anything other than three numbers keeps the current
+    //! offset.
+    app.note_activity();
+
+    let handle : Option<Vec<f32>> = from_cbor(handle).unwrap_or_default();
+    let chart : Option<Vec<f32>> = from_cbor(chart).unwrap_or_default();
+
+    if let Some(&[x, y, z]) = handle.as_deref() {
+        app.domain.probe_handle_offset = glm::vec3(x, y, z);
+    }
+
+    if let Some(&[x, y, z]) = chart.as_deref() {
+        app.domain.probe_chart_offset = glm::vec3(x, y, z);
+    }
+
+    for probe in &app.probes {
+        probe.apply_offsets(&app.domain);
+    }
+
+    Ok(None)
+});
+
+// =============================================================================
+
+make_method_function!(set_violations_only,
+GridState,
+"Set Violations Only",
+"Only draw lines that violate voltage limits",
+| enabled : Value : "Boolean, true to hide lines with safe voltage" |,
+{
+    //! Filters lines to those with unsafe voltage. The filter is
+    //! re-evaluated on every time step.
+    app.note_activity();
+
+    let enabled : bool = from_cbor(enabled).unwrap_or_default();
+
+    app.violations_only = enabled;
+    recompute_all(app, state);
+    Ok(None)
+});
+
+// =============================================================================
+
+make_method_function!(set_view_position,
+GridState,
+"Set View Position",
+"Report the viewer position, so distant lines can be culled",
+| position : Value : "Array of three floats, or null to draw every line" |,
+{
+    //! Stores the viewer position. Lines farther than `--view-radius` from
+    //! it are not drawn. Anything other than three numbers clears the
+    //! position, so every line is drawn again.
+    app.note_activity();
+
+    let position : Option<Vec<f32>> = from_cbor(position).unwrap_or_default();
+
+    app.view_position = match position.as_deref() {
+        Some(&[x, y, z]) => Some(glm::vec3(x, y, z)),
+        _ => None,
+    };
+
+    recompute_all(app, state);
+    Ok(None)
+});
+
+// =============================================================================
+
+make_method_function!(
+    toggle_hazards,
+    GridState,
+    "Toggle Hazards",
+    "Toggle hazard cells where lines cross the voltage limits",
+    {
+        //! Toggles hazard cell generation. While off, lines are not tested
+        //! against the limits at all, which speeds up playback on large
+        //! systems.
+        app.note_activity();
+
+        app.hazards_enabled = !app.hazards_enabled;
+        recompute_all(app, state);
+        Ok(None)
+    }
+);
+
+// =============================================================================
+
+make_method_function!(set_hazard_window,
+GridState,
+"Set Hazard Window",
+"Set the number of recent time steps hazard cells are accumulated over",
+| steps : Value : "Integer number of time steps. Zero shows only the current step" |,
+{
+    //! Sets how many recent time steps hazard cells are counted over. Cells
+    //! are colored by how often they were crossed in that window, which
+    //! picks out chronic problem spots. The window fills as time advances.
+    app.note_activity();
+
+    let steps : usize = from_cbor(steps).unwrap_or_default();
+
+    app.hazard_history.set_window(steps);
+    recompute_all(app, state);
+    Ok(None)
+});
+
+// =============================================================================
+
+make_method_function!(set_hazard_appearance,
+GridState,
+"Set Hazard Appearance",
+"Set the color and opacity of hazard cells",
+| rgba : Value : "Array of red, green, blue, and alpha, each from 0 to 1, or null for the default" |,
+{
+    //! Changes the base color and opacity of every hazard cell, such as to
+    //! fade them when they hide the lines beneath. Severity colors are
+    //! tinted by this color.
+    app.note_activity();
+
+    let rgba : Option<Vec<f32>> = from_cbor(rgba).unwrap_or_default();
+
+    let color = match rgba.as_deref() {
+        None => HAZARD_COLOR,
+        Some(&[r, g, b, a]) if [r, g, b, a].iter().all(|v| (0.0..=1.0).contains(v)) => {
+            [r, g, b, a]
+        }
+        _ => {
+            return Err(MethodException {
+                code: INVALID_PARAMETERS,
+                message: Some("Expected four values from 0 to 1, or null".into()),
+                data: None,
+            });
+        }
+    };
+
+    app.set_hazard_color(color);
+    Ok(None)
+});
+
+// =============================================================================
+
+make_method_function!(set_trail_length,
+GridState,
+"Set Trail Length",
+"Draw a faded trail of previous time steps behind the lines",
+| length : Value : "Integer number of previous time steps to draw, 0 to disable" |,
+{
+    //! Sets how many previous time steps are drawn as a trail. The trail is
+    //! rebuilt on every time step.
+    app.note_activity();
+
+    let length : usize = from_cbor(length).unwrap_or_default();
+
+    app.trail_length = length;
+    recompute_all(app, state);
+    Ok(None)
+});
+
+// =============================================================================
+
+make_method_function!(set_line_coloring,
+GridState,
+"Set Line Coloring",
+"Choose the data that drives line color",
+| source : Value : "Text, either \"voltage\" for voltage safety or \"load\" for line load" |,
+{
+    //! Switches line coloring between voltage safety and line load, and
+    //! updates the color legend.
+    //!
+    //! Unknown sources are ignored.
+    app.note_activity();
+
+    let source : String = from_cbor(source).unwrap_or_default();
+
+    let coloring = match source.to_lowercase().as_str() {
+        "voltage" => LineColoring::VoltageSafety,
+        "load" => LineColoring::LineLoad,
+        _ => {
+            log::warn!("Ignoring unknown line coloring {source:?}");
+            return Ok(None);
+        }
+    };
+
+    app.set_line_coloring(state, coloring);
+    recompute_all(app, state);
+    Ok(None)
+});
+
+// =============================================================================
+
+make_method_function!(set_bus_style,
+GridState,
+"Set Bus Style",
+"Choose the shape used to draw buses",
+| style : Value : "Text, either \"tube\" for power-sized tubes or \"sphere\" for voltage-sized spheres" |,
+{
+    //! Switches buses between tubes sized by power and spheres sized by
+    //! voltage deviation. Both are colored by voltage safety.
+    app.note_activity();
+
+    let style : String = from_cbor(style).unwrap_or_default();
+
+    let style = match style.to_lowercase().as_str() {
+        "tube" => BusStyle::Tube,
+        "sphere" => BusStyle::Sphere,
+        _ => {
+            return Err(MethodException {
+                code: INVALID_PARAMETERS,
+                message: Some(format!("Unknown bus style {style:?}")),
+                data: None,
+            });
+        }
+    };
+
+    app.set_bus_style(state, style);
+    recompute_all(app, state);
+    Ok(None)
+});
+
+// =============================================================================
+
+make_method_function!(
+    reset_view,
+    GridState,
+    "Reset View",
+    "Recompute the view bounds from the current data",
+    {
+        //! Recenters the domain on the data and rebuilds dependent scenery.
+        app.note_activity();
+
+        app.reset_view(state);
+        Ok(None)
+    }
+);
+
+// =============================================================================
+
+make_method_function!(
+    snapshot_scene,
+    GridState,
+    "Snapshot Scene",
+    "Freeze a tinted copy of the current scene for comparison",
+    {
+        //! Copies the current buses, lines, and transformers into a tinted
+        //! snapshot that stays in place as time changes. Returns the time
+        //! step captured.
+        app.note_activity();
+
+        let time_step = app.snapshot_scene(state);
+
+        Ok(Some(cbor_count(time_step)))
+    }
+);
+
+make_method_function!(
+    clear_snapshots,
+    GridState,
+    "Clear Snapshots",
+    "Remove all frozen scene snapshots",
+    {
+        //! Removes every snapshot. Returns the time steps they captured.
+        app.note_activity();
+
+        let steps = app
+            .snapshots
+            .drain(..)
+            .map(|s| cbor_count(s.time_step))
+            .collect();
+
+        Ok(Some(Value::Array(steps)))
+    }
+);
+
+// =============================================================================
+
+make_method_function!(set_compare_step,
+GridState,
+"Set Compare Step",
+"Draw a reference time step beside the live scene",
+| step : Value : "Time step to draw for comparison" |,
+| offset : Value : "Distance along X from the live scene, or null to keep the current offset" |,
+{
+    //! Turns on compare mode, drawing the buses, lines, and transformers of
+    //! `step` beside the live scene. The live scene keeps following playback.
+    //! Display settings such as phases and coloring apply to both.
+    app.note_activity();
+
+    let count = app.system.lines.len();
+
+    let Some(step) = from_cbor::<usize>(step).ok().filter(|&s| s < count) else {
+        return Err(MethodException {
+            code: INVALID_PARAMETERS,
+            message: Some(format!("Step must be a time step below {count}")),
+            data: None,
+        });
+    };
+
+    if let Ok(offset) = from_cbor::<f32>(offset) {
+        app.compare_offset = offset;
+    }
+
+    app.set_compare_step(state, step);
+    recompute_all(app, state);
+    Ok(None)
+});
+
+make_method_function!(
+    clear_compare,
+    GridState,
+    "Clear Compare",
+    "Stop drawing the reference time step",
+    {
+        //! Turns off compare mode, removing the compared entities. Returns
+        //! whether compare mode was on.
+        app.note_activity();
+
+        Ok(Some(Value::Bool(app.clear_compare())))
+    }
+);
+
+// =============================================================================
+
+make_method_function!(set_billboard,
+GridState,
+"Set Billboard",
+"Choose whether a group of charts turns to face each viewer",
+| group : Value : "Text, either \"summary\" for the overview chart or \"probes\" for probe charts" |,
+| enabled : Value : "Boolean, true to face each viewer" |,
+{
+    //! Turns billboarding on or off for the overview chart or all probe
+    //! charts. The probe setting also applies to charts created later.
+    app.note_activity();
+
+    let group : String = from_cbor(group).unwrap_or_default();
+    let enabled : bool = from_cbor(enabled).unwrap_or_default();
+
+    match group.to_lowercase().as_str() {
+        "summary" => {
+            // Also applies if the chart is still being built
+            app.billboard_summary = enabled;
+
+            if let Some(summary) = &app.summary {
+                summary.set_billboard(enabled);
+            }
+        }
+        "probes" => {
+            app.billboard_probes = enabled;
+
+            let update = ServerEntityStateUpdatable {
+                billboard: Some(enabled),
+                ..Default::default()
+            };
+
+            for chart in app.probes.iter().filter_map(|p| p.chart.as_ref()) {
+                update.patch(chart);
+            }
+        }
+        _ => {
+            return Err(MethodException {
+                code: INVALID_PARAMETERS,
+                message: Some(format!("Unknown chart group {group:?}")),
+                data: None,
+            });
+        }
+    }
+
+    Ok(None)
+});
+
+// =============================================================================
+
+make_method_function!(set_summary_metric,
+GridState,
+"Set Summary Metric",
+"Choose the line quantity plotted on the overview chart",
+| metric : Value : "Text, one of \"voltage\", \"real\", \"reactive\", or \"load\"" |,
+{
+    //! Redraws the overview chart with a different phase A line quantity.
+    app.note_activity();
+
+    let metric : String = from_cbor(metric).unwrap_or_default();
+
+    let Some(metric) = SummaryMetric::parse(&metric) else {
+        return Err(MethodException {
+            code: INVALID_PARAMETERS,
+            message: Some(format!("Unknown summary metric {metric:?}")),
+            data: None,
+        });
+    };
+
+    let time_frac = app.time_frac();
+
+    let Some(summary) = &mut app.summary else {
+        return Err(MethodException {
+            code: INVALID_REQUEST,
+            message: Some("The overview chart is disabled".into()),
+            data: None,
+        });
+    };
+
+    summary.set_metric(metric, state);
+
+    // The plot area may have moved with the new axis labels
+    summary.set_time_normalized(time_frac);
+
+    Ok(None)
+});
+
+// =============================================================================
+
+make_method_function!(set_indicator_color,
+GridState,
+"Set Indicator Color",
+"Set the color of the time indicator on the overview chart",
+| rgba : Value : "Array of red, green, blue, and alpha, each from 0 to 1, or null for the default red" |,
+{
+    //! Recolors the time indicator, such as when it is lost against a themed
+    //! chart. The color is kept if the chart is still being built.
+    app.note_activity();
+
+    let rgba : Option<Vec<f32>> = from_cbor(rgba).unwrap_or_default();
+
+    let color = match rgba.as_deref() {
+        None => INDICATOR_COLOR,
+        Some(&[r, g, b, a]) if [r, g, b, a].iter().all(|v| (0.0..=1.0).contains(v)) => {
+            [r, g, b, a]
+        }
+        _ => {
+            return Err(MethodException {
+                code: INVALID_PARAMETERS,
+                message: Some("Expected four values from 0 to 1, or null".into()),
+                data: None,
+            });
+        }
+    };
+
+    app.indicator_color = color;
+
+    if let Some(summary) = &app.summary {
+        summary.set_indicator_color(color);
+    }
+
+    Ok(None)
+});
+
+// =============================================================================
+
+make_method_function!(set_chart_resolution,
+GridState,
+"Set Chart Resolution",
+"Set the pixel size of probe and overview charts",
+| width : Value : "Integer width in pixels" |,
+| height : Value : "Integer height in pixels" |,
+{
+    //! Sets the image size of probe and overview charts, then redraws the
+    //! overview chart and every probe chart. Charts keep their height in
+    //! the scene, and are widened or narrowed to the new aspect ratio. Each
+    //! side must be within [`CHART_SIDE_RANGE`], and the supersampled image
+    //! within [`MAX_CHART_RENDER_PIXELS`].
+    app.note_activity();
+
+    let width : u32 = from_cbor(width).unwrap_or_default();
+    let height : u32 = from_cbor(height).unwrap_or_default();
+
+    if !CHART_SIDE_RANGE.contains(&width) || !CHART_SIDE_RANGE.contains(&height) {
+        return Err(MethodException {
+            code: INVALID_PARAMETERS,
+            message: Some(format!(
+                "Chart sides must be from {} to {} pixels, got {width} x {height}",
+                CHART_SIDE_RANGE.start(),
+                CHART_SIDE_RANGE.end()
+            )),
+            data: None,
+        });
+    }
+
+    let supersample = app.chart_supersample.max(1) as u64;
+
+    if width as u64 * height as u64 * supersample * supersample > MAX_CHART_RENDER_PIXELS {
+        return Err(MethodException {
+            code: INVALID_PARAMETERS,
+            message: Some(format!(
+                "Chart of {width} x {height} is too large at {supersample}x supersampling"
+            )),
+            data: None,
+        });
+    }
+
+    app.chart_size = (width, height);
+
+    let time_frac = app.time_frac();
+
+    if let Some(summary) = &mut app.summary {
+        summary.set_size((width, height), state);
+
+        // The plot area is measured in pixels, so it has moved
+        summary.set_time_normalized(time_frac);
+    }
+
+    for probe in app.probes.iter_mut() {
+        probe.dirty = Some(probe.world_pos);
+    }
+
+    app.probe_move_request_signal.send(true).unwrap();
+    Ok(None)
+});
+
+// =============================================================================
+
+make_method_function!(
+    get_hazard_stats,
+    GridState,
+    "Get Hazard Stats",
+    "Get the number of voltage hazard cells at the current time",
+    {
+        //! Returns a map of `total`, `low`, and `high` hazard cell counts for
+        //! the current step, and the hazard `window` in time steps.
+        app.note_activity();
+
+        let stats = app.hazard_stats;
+
+        Ok(Some(cbor_map(vec![
+            ("total", cbor_count(stats.total())),
+            ("low", cbor_count(stats.low)),
+            ("high", cbor_count(stats.high)),
+            ("window", cbor_count(app.hazard_history.window())),
+        ])))
+    }
+);
+
+// =============================================================================
+
+make_method_function!(
+    get_voltage_stats,
+    GridState,
+    "Get Voltage Stats",
+    "Get the min, max, and mean line voltage at the current time",
+    {
+        //! Returns a map of `min`, `max`, and `mean` line voltage in p.u.,
+        //! taken over both ends of every line on the rendered phases.
+        app.note_activity();
+
+        let stats = app.voltage_stats;
+
+        Ok(Some(cbor_map(vec![
+            ("min", Value::Float(stats.min as f64)),
+            ("max", Value::Float(stats.max as f64)),
+            ("mean", Value::Float(stats.mean as f64)),
+        ])))
+    }
+);
+
+// =============================================================================
+
+make_method_function!(
+    get_flow_stats,
+    GridState,
+    "Get Flow Stats",
+    "Get the number of lines with forward, reverse, and zero real power at the current time",
+    {
+        //! Returns a map from phase (`a`, `b`, `c`) to a map of `forward`,
+        //! `reverse`, and `zero` line counts. Direction follows the sign of
+        //! real power at the start of each line, over every line in the
+        //! dataset.
+        app.note_activity();
+
+        let stats = app.flow_stats;
+
+        let phases = ["a", "b", "c"]
+            .into_iter()
+            .zip(stats.phases)
+            .map(|(phase, counts)| {
+                (
+                    phase,
+                    cbor_map(vec![
+                        ("forward", cbor_count(counts.forward)),
+                        ("reverse", cbor_count(counts.reverse)),
+                        ("zero", cbor_count(counts.zero)),
+                    ]),
+                )
+            })
+            .collect();
+
+        Ok(Some(cbor_map(phases)))
+    }
+);
+
+// =============================================================================
+
+make_method_function!(
+    get_diagnostics,
+    GridState,
+    "Get Diagnostics",
+    "Get a summary of the loaded dataset and server state",
+    {
+        //! Returns a map describing the dataset, domain calibration,
+        //! probes, instance bytes uploaded since startup, and whether the
+        //! scene has been filled in yet. This is read-only.
+        app.note_activity();
+
+        let system = &app.system;
+        let domain = &app.domain;
+        let uploads = app.upload_stats;
+
+        let pair = |v: glm::DVec2| Value::Array(vec![Value::Float(v.x), Value::Float(v.y)]);
+
+        Ok(Some(cbor_map(vec![
+            ("title", Value::Text(system.title.clone())),
+            ("lines", cbor_count(system.line_meta.len())),
+            (
+                "transformers",
+                cbor_count(system.tfs.first().map_or(0, |t| t.len())),
+            ),
+            (
+                "generators",
+                cbor_count(system.pvs.first().map_or(0, |g| g.len())),
+            ),
+            ("time_steps", cbor_count(app.max_time_step)),
+            ("data_x", pair(domain.data_x)),
+            ("data_y", pair(domain.data_y)),
+            ("watt_bounds", Value::Float(domain.watt_bounds as f64)),
+            ("volt_min", Value::Float(domain.volt_min as f64)),
+            ("volt_max", Value::Float(domain.volt_max as f64)),
+            ("probes", cbor_count(app.probes.len())),
+            ("floorplan", Value::Bool(system.floor_plan.is_some())),
+            ("ready", Value::Bool(app.ready)),
+            (
+                "uploads",
+                cbor_map(vec![
+                    ("total", cbor_count(uploads.total)),
+                    ("changed", cbor_count(uploads.changed)),
+                    ("sent", cbor_count(uploads.sent)),
+                ]),
+            ),
+        ])))
+    }
+);
+
+// =============================================================================
+
+make_method_function!(dump_instances,
+GridState,
+"Dump Instances",
+"Get the raw instance buffer of a render group, for debugging",
+| group : Value : "Text name of the group, such as \"lines\" or \"buses\"" |,
+{
+    //! Returns a map of the group's instance `bytes`, as last computed, the
+    //! instance `count`, and the `stride` in bytes of one instance. This is
+    //! read-only, and only advertised with `--allow-debug-methods`.
+    app.note_activity();
+
+    let group : String = from_cbor(group).unwrap_or_default();
+
+    let Some(item) = app.instance_group(&group) else {
+        return Err(MethodException {
+            code: INVALID_PARAMETERS,
+            message: Some(format!(
+                "Unknown group {group:?}, expected one of {}",
+                INSTANCE_GROUPS.join(", ")
+            )),
+            data: None,
+        });
+    };
+
+    Ok(Some(cbor_map(vec![
+        ("bytes", Value::Bytes(item.buffer.clone())),
+        ("count", cbor_count(item.buffer.len() / item.stride())),
+        ("stride", cbor_count(item.stride())),
+    ])))
+});
+
+// =============================================================================
+
+make_method_function!(focus_line,
+GridState,
+"Focus Line",
+"Get a suggested camera target for a line",
+| name_or_index : Value : "Line index, or text to match against line names" |,
+{
+    //! Looks up a line by index or case-insensitive name substring, and
+    //! returns a map with the world space `position` of its midpoint and a
+    //! `radius` that encloses it.
+    app.note_activity();
+
+    let Some(line_i) = app.find_line(&name_or_index) else {
+        return Err(MethodException {
+            code: INVALID_PARAMETERS,
+            message: Some(format!("No line matches {name_or_index:?}")),
+            data: None,
+        });
+    };
+
+    let (center, radius) = app.line_extent(line_i);
+
+    Ok(Some(cbor_map(vec![
+        ("index", cbor_count(line_i)),
+        ("name", Value::Text(line_name(app, line_i))),
+        (
+            "position",
+            Value::Array(center.iter().map(|&v| Value::Float(v as f64)).collect()),
+        ),
+        ("radius", Value::Float(radius as f64)),
+    ])))
+});
+
+// =============================================================================
+
+make_method_function!(
+    list_lines,
+    GridState,
+    "List Lines",
+    "List the index and name of every line",
+    {
+        //! Returns an array with a map for each line, holding its `index`
+        //! and `name`, for building line search on the client. Transformers
+        //! and generators have no names, so are not listed.
+        app.note_activity();
+
+        let lines = app
+            .system
+            .line_meta
+            .iter()
+            .enumerate()
+            .map(|(line_i, name)| {
+                cbor_map(vec![
+                    ("index", cbor_count(line_i)),
+                    ("name", Value::Text(name.clone())),
+                ])
+            })
+            .collect();
+
+        Ok(Some(Value::Array(lines)))
+    }
+);
+
+// =============================================================================
+
+make_method_function!(pick_lines,
+GridState,
+"Pick Lines",
+"List the lines closest to a point",
+| position : [f32;3] : "Point to search around, as vec3" |,
+| count : Value : "Most lines to return" |,
+{
+    //! Returns an array of up to `count` visible lines nearest to
+    //! `position` along the ground, nearest first. Each entry is a map with
+    //! the line `index`, `name`, and `distance` in domain units.
+    app.note_activity();
+
+    let Some(count) = from_cbor::<usize>(count).ok().filter(|&c| c > 0) else {
+        return Err(MethodException {
+            code: INVALID_PARAMETERS,
+            message: Some("Count must be a positive integer".into()),
+            data: None,
+        });
+    };
+
+    let position = glm::vec2(position[0], position[2]);
+
+    let picked = pick_lines(app, position, count)
+        .into_iter()
+        .map(|(line_i, distance)| {
+            cbor_map(vec![
+                ("index", cbor_count(line_i)),
+                ("name", Value::Text(line_name(app, line_i))),
+                ("distance", Value::Float(distance as f64)),
+            ])
+        })
+        .collect();
+
+    Ok(Some(Value::Array(picked)))
+});
+
+// =============================================================================
+
+make_method_function!(highlight_line,
+GridState,
+"Highlight Line",
+"Draw a line in a fixed color until cleared",
+| name_or_index : Value : "Line index, or text to match against line names" |,
+| hue : Value : "Hue of the highlight, from 0 to 1 (0 red, 0.33 green, 0.66 blue)" |,
+{
+    //! Overrides the color of every phase of a line with a fully saturated
+    //! hue. The highlight persists through time steps and display changes
+    //! until cleared. Returns the index of the highlighted line.
+    app.note_activity();
+
+    let Some(line_i) = app.find_line(&name_or_index) else {
+        return Err(MethodException {
+            code: INVALID_PARAMETERS,
+            message: Some(format!("No line matches {name_or_index:?}")),
+            data: None,
+        });
+    };
+
+    let Some(hue) = from_cbor::<f32>(hue).ok().filter(|h| (0.0..=1.0).contains(h)) else {
+        return Err(MethodException {
+            code: INVALID_PARAMETERS,
+            message: Some("Hue must be a number from 0 to 1".into()),
+            data: None,
+        });
+    };
+
+    app.highlights.insert(line_i, hue);
+    recompute_all(app, state);
+
+    Ok(Some(cbor_count(line_i)))
+});
+
+make_method_function!(clear_highlight,
+GridState,
+"Clear Highlight",
+"Restore the normal color of a highlighted line",
+| name_or_index : Value : "Line index, text to match against line names, or null for all lines" |,
+{
+    //! Removes the highlight from a line, or from every line if given null.
+    //! Returns the number of highlights removed.
+    app.note_activity();
+
+    let removed = if name_or_index.is_null() {
+        let count = app.highlights.len();
+        app.highlights.clear();
+        count
+    } else {
+        app.find_line(&name_or_index)
+            .and_then(|line_i| app.highlights.remove(&line_i))
+            .map_or(0, |_| 1)
+    };
+
+    if removed > 0 {
+        recompute_all(app, state);
+    }
+
+    Ok(Some(cbor_count(removed)))
+});
+
 // =============================================================================
 
 /// Handles click events on entities, possibly deleting a probe.
@@ -376,6 +2083,7 @@ make_method_function!(activate,
     | kind : Option<ciborium::Value> : "Activation context"|,
     {
         //! Invokes an activation action on an entity (usually a probe or hazard).
+        app.note_activity();
 
         on_click(app, state, context, kind);
         Ok(None)
@@ -1,4 +1,5 @@
-use std::collections::HashMap;
+use std::collections::{BinaryHeap, HashMap};
+use std::path::Path;
 
 use colabrodo_common::components::*;
 use colabrodo_common::nooid::EntityID;
@@ -6,13 +7,19 @@ use colabrodo_server::{server::*, server_messages::*};
 use nalgebra::distance;
 use nalgebra_glm::{self as glm, vec3, Mat4, Vec2};
 use nalgebra_glm::{vec2, Vec3};
+use serde::{Deserialize, Serialize};
 
+use crate::domain::Domain;
+use crate::dots::EndedPosition;
 use crate::geometry::{make_plane, make_sphere};
 use crate::state::GridStatePtr;
 use crate::texture::texture_from_bytes;
 use crate::GridState;
 use crate::{chart::*, ruler::make_obj};
 
+/// Width and height of a probe chart in the scene, at the default chart size
+const CHART_PLANE: (f32, f32) = (0.5, 0.4);
+
 /// Represents a movable probe in the visualization space.
 ///
 /// Probes can attach to nearby lines, generate charts, and be interactively manipulated.
@@ -50,11 +57,7 @@ impl Probe {
         let geometry = make_sphere(state, glm::vec3(0.0, 0.0, 1.0), 0.05);
 
         // Position the handle slightly above and offset from the probe's world position
-        let placement: [f32; 16] = {
-            let spot: Vec3 = self.world_pos;
-            let tf = glm::translation(&(spot + glm::vec3(0.25, 1.0, 0.0)));
-            tf.as_slice().try_into().unwrap()
-        };
+        let placement = self.handle_placement(&gs.domain);
 
         self.handle = Some(state.entities.new_component(ServerEntityState {
             name: Some("Chart Handle".to_string()),
@@ -73,14 +76,39 @@ impl Probe {
         }));
     }
 
-    /// Finds the closest line segment to the probe in 2D space.
+    /// Transform of the chart handle, offset from the probe's world position.
+    fn handle_placement(&self, domain: &Domain) -> [f32; 16] {
+        let tf = glm::translation(&(self.world_pos + domain.probe_handle_offset));
+        tf.as_slice().try_into().unwrap()
+    }
+
+    /// Moves an existing handle and chart to the offsets in `domain`.
+    pub fn apply_offsets(&self, domain: &Domain) {
+        if let Some(handle) = &self.handle {
+            ServerEntityStateUpdatable {
+                transform: Some(self.handle_placement(domain)),
+                ..Default::default()
+            }
+            .patch(handle);
+        }
+
+        if let Some(chart) = &self.chart {
+            ServerEntityStateUpdatable {
+                transform: Some(chart_placement(domain)),
+                ..Default::default()
+            }
+            .patch(chart);
+        }
+    }
+
+    /// Finds the closest visible line segment to the probe in 2D space.
     ///
-    /// Returns both the index and closest point on the line.
+    /// Lines hidden by the phase selection or violation filter are skipped.
+    /// Returns both the index and closest point on the line, or nothing if no
+    /// line is within the snap radius.
     fn get_closest_line(&self, gs: &mut GridState) -> Option<(usize, Vec2)> {
         let lines = gs.system.lines.get(gs.time_step)?;
 
-        let domain = &gs.domain;
-
         let p = self.world_pos.xz();
 
         // Track minimum distance and closest line index
@@ -89,31 +117,13 @@ impl Probe {
         let mut closest_point = vec2(0.0, 0.0);
 
         for (l_i, l) in lines.iter().enumerate() {
-            let a = glm::vec2(
-                domain.lerp_x(l.loc.sx as f32),
-                domain.lerp_y(l.loc.sy as f32),
-            );
-
-            let b = glm::vec2(
-                domain.lerp_x(l.loc.ex as f32),
-                domain.lerp_y(l.loc.ey as f32),
-            );
-
-            let ap = p - a;
-            let ab = b - a;
+            if !gs.line_visible(l) {
+                continue;
+            }
 
-            let t = ap.dot(&ab) / ab.dot(&ab);
-            let c = a + t * ab;
+            let (this_distance, c) = project_onto_line(&gs.domain, &l.loc, p);
 
             // Update closest if this segment is nearer
-            let this_distance = if t < 0.0 {
-                distance(&p.into(), &a.into())
-            } else if t > 1.0 {
-                distance(&p.into(), &b.into())
-            } else {
-                distance(&p.into(), &c.into())
-            };
-
             if this_distance < min_distance {
                 min_distance = this_distance;
                 index = l_i;
@@ -121,7 +131,7 @@ impl Probe {
             }
         }
 
-        if index == usize::MAX {
+        if index == usize::MAX || min_distance > gs.snap_radius {
             None
         } else {
             Some((index, closest_point))
@@ -158,16 +168,15 @@ impl Probe {
         });
 
         // Create a plane rotated to face the user, scaled for aspect ratio
+        let width = chart_plane_width(CHART_PLANE.0, gs.chart_size);
+
         let geometry = {
             let transform = glm::rotate_x(&Mat4::identity(), 90.0f32.to_radians());
-            let transform = glm::scale(&transform, &glm::vec3(0.5, 1.0, 0.4));
+            let transform = glm::scale(&transform, &glm::vec3(width, 1.0, CHART_PLANE.1));
             make_plane(state, transform, chart_mat)
         };
 
-        let placement: [f32; 16] = {
-            let tf = glm::translation(&glm::vec3(0.0, 0.25, 0.0));
-            tf.as_slice().try_into().unwrap()
-        };
+        let placement = chart_placement(&gs.domain);
 
         // Ensure we have a handle to attach the chart to
         if self.handle.is_none() {
@@ -185,7 +194,7 @@ impl Probe {
                         instances: None,
                     },
                 )),
-                billboard: Some(true),
+                billboard: Some(gs.billboard_probes),
                 ..Default::default()
             },
         });
@@ -193,19 +202,20 @@ impl Probe {
         self.chart = Some(entity);
 
         // now install the delete button
-        self.install_delete_buttion(gs, state);
+        self.install_delete_buttion(gs, state, width);
     }
 
-    /// Adds a delete button next to the chart, allowing user to remove the probe.
-    fn install_delete_buttion(&mut self, gs: &mut GridState, state: &mut ServerState) {
+    /// Adds a delete button at the corner of a chart `width` wide, allowing
+    /// user to remove the probe.
+    fn install_delete_buttion(&mut self, gs: &mut GridState, state: &mut ServerState, width: f32) {
         let del_obj = make_obj(
             state,
             "Delete Button",
             [1.0, 0.2, 0.2, 1.0],
             glm::vec3(0.025, 0.025, 0.025),
-            glm::vec3(0.25, 0.25, 0.0),
+            glm::vec3(width / 2.0, 0.25, 0.0),
             self.chart.clone(),
-            include_str!("../assets/close.obj"),
+            &gs.meshes.close,
         );
 
         // Patch delete button with an activation method (click-to-delete)
@@ -221,7 +231,8 @@ impl Probe {
 
     /// Updates the probe's world position and reattaches it to the closest line.
     ///
-    /// If the attached line changes, resets internal reference.
+    /// If the attached line changes, resets internal reference. Probes with no
+    /// line in reach are seated on the ground and lose their chart.
     pub fn update(&mut self, gs: &mut GridState) {
         log::debug!("Updating probe {:?}", self.dirty);
         // new position
@@ -232,13 +243,24 @@ impl Probe {
 
         let Some((closest_line_index, closest_point)) = self.get_closest_line(gs) else {
             // make sure it is at least seated to the ground
-            move_entity(&self.entity, self.world_pos);
+            move_entity(
+                &self.entity,
+                vec3(self.world_pos.x, gs.domain.scene_y_offset, self.world_pos.z),
+            );
+
+            self.line_i = usize::MAX;
+            self.chart_delete = None;
+            self.chart = None;
+            self.handle = None;
             return;
         };
 
         // use closest point to move our probe over
 
-        move_entity(&self.entity, vec3(closest_point.x, 0.0f32, closest_point.y));
+        move_entity(
+            &self.entity,
+            vec3(closest_point.x, gs.domain.scene_y_offset, closest_point.y),
+        );
 
         // If already attached to correct line, no change needed
         if self.line_i == closest_line_index {
@@ -266,6 +288,124 @@ impl Probe {
     }
 }
 
+/// Transform of a probe chart, relative to its handle.
+fn chart_placement(domain: &Domain) -> [f32; 16] {
+    let tf = glm::translation(&domain.probe_chart_offset);
+    tf.as_slice().try_into().unwrap()
+}
+
+/// Projects a ground point onto a line segment in 2D scene space.
+///
+/// Returns the distance from the point to the segment, and the closest point
+/// on the infinite line through it.
+fn project_onto_line(domain: &Domain, loc: &EndedPosition, p: Vec2) -> (f32, Vec2) {
+    let a = glm::vec2(domain.lerp_x(loc.sx as f32), domain.lerp_y(loc.sy as f32));
+    let b = glm::vec2(domain.lerp_x(loc.ex as f32), domain.lerp_y(loc.ey as f32));
+
+    let ap = p - a;
+    let ab = b - a;
+
+    let t = ap.dot(&ab) / ab.dot(&ab);
+    let c = a + t * ab;
+
+    let d = if t < 0.0 {
+        distance(&p.into(), &a.into())
+    } else if t > 1.0 {
+        distance(&p.into(), &b.into())
+    } else {
+        distance(&p.into(), &c.into())
+    };
+
+    (d, c)
+}
+
+/// Finds up to `n` visible lines closest to a ground point, nearest first.
+///
+/// Returns line indices with their distances, in domain units. Unlike probe
+/// snapping, this is not limited by the snap radius.
+pub fn pick_lines(gs: &GridState, p: Vec2, n: usize) -> Vec<(usize, f32)> {
+    let Some(lines) = gs.system.lines.get(gs.time_step) else {
+        return vec![];
+    };
+
+    let candidates = lines
+        .iter()
+        .enumerate()
+        .filter(|(_, l)| gs.line_visible(l))
+        .map(|(l_i, l)| (l_i, project_onto_line(&gs.domain, &l.loc, p).0));
+
+    nearest(candidates, n)
+}
+
+/// Keeps the `n` candidates with the smallest distance, nearest first.
+///
+/// Candidates with a NaN distance, such as degenerate segments, are skipped.
+/// `n` may come from a client, so the heap is sized by the candidate count
+/// rather than by `n`.
+fn nearest(candidates: impl Iterator<Item = (usize, f32)>, n: usize) -> Vec<(usize, f32)> {
+    if n == 0 {
+        return vec![];
+    }
+
+    let (low, high) = candidates.size_hint();
+    let capacity = n.min(high.unwrap_or(low)).saturating_add(1);
+
+    // Max-heap of the best candidates so far; the worst is evicted once the
+    // heap grows past n. Distances are never negative, so their bit patterns
+    // sort the same as their values.
+    let mut best = BinaryHeap::with_capacity(capacity);
+
+    for (i, d) in candidates {
+        if d.is_nan() {
+            continue;
+        }
+
+        best.push((d.to_bits(), i));
+
+        if best.len() > n {
+            best.pop();
+        }
+    }
+
+    best.into_sorted_vec()
+        .into_iter()
+        .map(|(bits, i)| (i, f32::from_bits(bits)))
+        .collect()
+}
+
+/// A probe as stored in a probes file.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SavedProbe {
+    /// World position of the probe
+    pub position: [f32; 3],
+    /// Index of the line the probe was attached to
+    pub line: usize,
+}
+
+/// Writes the positions of all attached probes to a JSON file.
+pub fn save_probes(gs: &GridState, path: &Path) -> Result<(), anyhow::Error> {
+    let saved: Vec<_> = gs
+        .probes
+        .iter()
+        .filter(|p| p.line_i != usize::MAX)
+        .map(|p| SavedProbe {
+            position: p.world_pos.into(),
+            line: p.line_i,
+        })
+        .collect();
+
+    let file = std::fs::File::create(path)?;
+    serde_json::to_writer_pretty(std::io::BufWriter::new(file), &saved)?;
+
+    Ok(())
+}
+
+/// Reads probes from a JSON file written by [`save_probes`].
+pub fn load_probes(path: &Path) -> Result<Vec<SavedProbe>, anyhow::Error> {
+    let file = std::fs::File::open(path)?;
+    Ok(serde_json::from_reader(std::io::BufReader::new(file))?)
+}
+
 /// Possible outcomes of clicking on probe-related UI elements.
 pub enum ClickResult {
     Delete,
@@ -296,7 +436,7 @@ pub fn update_probes(gs: GridStatePtr) {
     // Stage 1: Mark dirty probes and schedule chart generation
     let mut image_to_generate = HashMap::<EntityID, (usize, Vec<u8>)>::default();
 
-    let power_system = {
+    let (power_system, size, supersample, show_kv, show_rating, theme, axis, reference) = {
         // acquire locks
         let mut gs = gs.lock().unwrap();
 
@@ -309,22 +449,61 @@ pub fn update_probes(gs: GridStatePtr) {
 
             item.update(&mut gs);
 
+            // Detached probes have no chart
+            if item.line_i == usize::MAX {
+                continue;
+            }
+
             image_to_generate.insert(item.entity.id(), (item.line_i, vec![]));
         }
 
         // put probes back
         gs.probes = std::mem::take(&mut probes);
 
-        gs.system.clone()
+        (
+            gs.system.clone(),
+            gs.chart_size,
+            gs.chart_supersample,
+            gs.show_kv,
+            gs.show_line_load,
+            gs.chart_theme,
+            gs.time_axis,
+            gs.voltage_reference(),
+        )
     };
 
     // Stage 2: Generate charts for updated probes
-    for item in image_to_generate.values_mut() {
+    for (id, item) in image_to_generate.iter_mut() {
+        // Skip probes that were removed while earlier charts were generated
+        if !gs
+            .lock()
+            .unwrap()
+            .probes
+            .iter()
+            .any(|p| p.entity.id() == *id)
+        {
+            continue;
+        }
+
         // now generate lines
-        // let chart_gen_timer = std::time::Instant::now();
-        let chart_image = generate_chart_for(item.0, &power_system);
+        let chart_gen_timer = std::time::Instant::now();
+        let chart_image = generate_chart_for(
+            item.0,
+            &power_system,
+            size,
+            supersample,
+            show_kv,
+            show_rating,
+            theme,
+            axis,
+            reference,
+        );
         item.1 = chart_image;
-        // println!("Gen: {}", chart_gen_timer.elapsed().as_millis());
+        log::trace!(
+            "Generated chart for line {} in {} ms",
+            item.0,
+            chart_gen_timer.elapsed().as_millis()
+        );
     }
 
     {
@@ -348,5 +527,32 @@ pub fn update_probes(gs: GridStatePtr) {
     }
 
     let since = chart_timer.elapsed();
-    println!("Took: {}", since.as_millis());
+    log::debug!("Probe update took {} ms", since.as_millis());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nearest_orders_nearest_first() {
+        let candidates = [(0, 3.0), (1, 0.5), (2, f32::NAN), (3, 2.0), (4, 1.0)];
+
+        assert_eq!(
+            nearest(candidates.into_iter(), 3),
+            vec![(1, 0.5), (4, 1.0), (3, 2.0)]
+        );
+        assert!(nearest(candidates.into_iter(), 0).is_empty());
+    }
+
+    #[test]
+    fn nearest_caps_count_at_candidates() {
+        let candidates = [(0, 2.0), (1, 1.0)];
+
+        // A client can ask for any count; this must not try to reserve it
+        assert_eq!(
+            nearest(candidates.into_iter(), usize::MAX),
+            vec![(1, 1.0), (0, 2.0)]
+        );
+    }
 }
@@ -2,7 +2,17 @@ use colabrodo_server::{server::*, server_messages::*};
 
 use nalgebra_glm::{self as glm, vec3, Mat4};
 
-use crate::{domain::Domain, geometry::make_plane, texture::*};
+use crate::{
+    chart::{
+        generate_color_legend, generate_ruler, generate_tf_legend, generate_title_banner,
+        ChartTheme, RulerScale, TITLE_SIZE,
+    },
+    domain::Domain,
+    geometry::make_plane,
+    instance::LineColoring,
+    texture::*,
+    PowerSystem,
+};
 
 /// Type of ruler to create, corresponding to different data visualizations.
 ///
@@ -14,15 +24,50 @@ pub enum RulerType {
     LineLoad,
 }
 
+/// Scale printed on a ruler, matching the domain's height mapping. The title
+/// names `phase` if only that phase is rendered.
+fn ruler_scale(domain: &Domain, ty: &RulerType, phase: Option<char>) -> RulerScale {
+    let title = |base: &str| match phase {
+        Some(p) => format!("{base}, Phase {p}"),
+        None => base.to_string(),
+    };
+
+    match ty {
+        RulerType::Voltage => RulerScale {
+            title: title("Voltage (p.u.)"),
+            range: domain.volt_min..domain.volt_max,
+            band: Some(domain.safe_min..domain.safe_max),
+            label: |v| format!("{v:.2}"),
+        },
+        RulerType::LineLoad => RulerScale {
+            title: title("Line Load"),
+            range: 0.0..domain.line_load_max,
+            band: Some(0.0..1.0_f32.min(domain.line_load_max)),
+            label: |v| format!("{:.0}%", v * 100.0),
+        },
+    }
+}
+
 /// Creates a ruler entity with the appropriate texture and transform.
 ///
-/// The ruler is positioned and scaled based on the domain bounds,
-/// and uses either voltage or line load visualization textures.
-pub fn make_ruler(state: &mut ServerState, domain: &Domain, ty: RulerType) -> EntityReference {
-    // Choose the appropriate texture based on ruler type
+/// The ruler is positioned and scaled based on the domain bounds. Its texture
+/// is drawn from the domain's voltage or line load range, so rulers should be
+/// rebuilt when that range or the safe limits change, or when a phase is
+/// soloed.
+pub fn make_ruler(
+    state: &mut ServerState,
+    domain: &Domain,
+    ty: RulerType,
+    phase: Option<char>,
+    supersample: u32,
+    theme: ChartTheme,
+    visible: bool,
+) -> EntityReference {
+    let png = generate_ruler(&ruler_scale(domain, &ty, phase), supersample, theme);
+
     let tex = match ty {
-        RulerType::Voltage => make_ruler_texture(state),
-        RulerType::LineLoad => make_ruler_ll_texture(state),
+        RulerType::Voltage => texture_from_bytes(state, &png, "Ruler"),
+        RulerType::LineLoad => texture_from_bytes(state, &png, "Ruler (LL)"),
     };
 
     let mat = state.materials.new_component(ServerMaterialState {
@@ -47,10 +92,7 @@ pub fn make_ruler(state: &mut ServerState, domain: &Domain, ty: RulerType) -> En
     // Build a transformation matrix to position the ruler flat in space
     let transform = glm::rotate_x(&Mat4::identity(), 90.0f32.to_radians());
     let transform = glm::scale(&transform, &vec3(0.5625, 1.0, 1.5));
-    let transform = glm::translate(
-        &transform,
-        &vec3(0.0, domain.lerp_y(domain.data_y.y as f32), -0.5),
-    );
+    let transform = glm::translate(&transform, &vec3(0.0, domain.far_edge_z(), -0.5));
 
     let geom = make_plane(state, transform, mat);
 
@@ -58,14 +100,213 @@ pub fn make_ruler(state: &mut ServerState, domain: &Domain, ty: RulerType) -> En
     state.entities.new_component(ServerEntityState {
         name: Some("Ruler".into()),
         mutable: ServerEntityStateUpdatable {
-            //transform: Some(transform.as_slice().try_into().unwrap()),
+            transform: Some(ruler_transform(domain)),
+            representation: Some(ServerEntityRepresentation::new_render(
+                ServerRenderRepresentation {
+                    mesh: geom,
+                    instances: None,
+                },
+            )),
+            visible: Some(visible),
+            ..Default::default()
+        },
+    })
+}
+
+/// Computes the entity transform that stretches a ruler over the domain's
+/// height range, with vertical exaggeration, standing on the floor.
+fn ruler_transform(domain: &Domain) -> [f32; 16] {
+    // The ruler plane is built for the default height range
+    let defaults = Domain::default();
+    let span = (domain.volt_height_max - domain.volt_height_min)
+        / (defaults.volt_height_max - defaults.volt_height_min);
+
+    let base = vec3(0.0, domain.exaggerate(domain.volt_height_min), 0.0);
+
+    let tf = glm::translation(&(domain.scene_offset() + base))
+        * glm::scaling(&vec3(1.0, domain.exaggeration * span, 1.0));
+    tf.as_slice().try_into().unwrap()
+}
+
+/// Computes the entity transform that lifts a legend to the floor height.
+fn legend_transform(domain: &Domain) -> [f32; 16] {
+    let tf = glm::translation(&domain.scene_offset());
+    tf.as_slice().try_into().unwrap()
+}
+
+/// Rescales an existing ruler entity after the domain exaggeration or height
+/// range changes.
+pub fn rescale_ruler(ruler: &EntityReference, domain: &Domain) {
+    let update = ServerEntityStateUpdatable {
+        transform: Some(ruler_transform(domain)),
+        ..Default::default()
+    };
+
+    update.patch(ruler);
+}
+
+/// Creates a legend plane explaining the transformer encoding, placed beside
+/// the rulers.
+///
+/// The legend texture is generated from the data, so it reflects the tap
+/// range of the loaded system.
+pub fn make_tf_legend(
+    state: &mut ServerState,
+    domain: &Domain,
+    system: &PowerSystem,
+    supersample: u32,
+    theme: ChartTheme,
+    visible: bool,
+) -> EntityReference {
+    let png = generate_tf_legend(system, supersample, theme);
+    let tex = texture_from_bytes(state, &png, "Transformer Legend");
+
+    let mat = state.materials.new_component(ServerMaterialState {
+        name: Some("Transformer Legend Material".into()),
+        mutable: ServerMaterialStateUpdatable {
+            pbr_info: Some(ServerPBRInfo {
+                base_color: [1.0, 1.0, 1.0, 1.0],
+                metallic: Some(0.0),
+                roughness: Some(0.25),
+                base_color_texture: Some(ServerTextureRef {
+                    texture: tex,
+                    transform: None,
+                    texture_coord_slot: None,
+                }),
+                ..Default::default()
+            }),
+            ..Default::default()
+        },
+    });
+
+    // Stand the plane up to the right of the rulers, resting on the ground
+    let transform = glm::rotate_x(&Mat4::identity(), 90.0f32.to_radians());
+    let transform = glm::scale(&transform, &vec3(0.5, 1.0, 0.5));
+    let transform = glm::translate(&transform, &vec3(1.2, domain.far_edge_z(), -0.5));
+
+    let geom = make_plane(state, transform, mat);
+
+    state.entities.new_component(ServerEntityState {
+        name: Some("Transformer Legend".into()),
+        mutable: ServerEntityStateUpdatable {
+            transform: Some(legend_transform(domain)),
+            representation: Some(ServerEntityRepresentation::new_render(
+                ServerRenderRepresentation {
+                    mesh: geom,
+                    instances: None,
+                },
+            )),
+            visible: Some(visible),
+            ..Default::default()
+        },
+    })
+}
+
+/// Creates a legend plane explaining line colors, placed to the left of the
+/// rulers.
+pub fn make_color_legend(
+    state: &mut ServerState,
+    domain: &Domain,
+    coloring: LineColoring,
+    supersample: u32,
+    theme: ChartTheme,
+) -> EntityReference {
+    let png = generate_color_legend(coloring, domain, supersample, theme);
+    let tex = texture_from_bytes(state, &png, "Color Legend");
+
+    let mat = state.materials.new_component(ServerMaterialState {
+        name: Some("Color Legend Material".into()),
+        mutable: ServerMaterialStateUpdatable {
+            pbr_info: Some(ServerPBRInfo {
+                base_color: [1.0, 1.0, 1.0, 1.0],
+                metallic: Some(0.0),
+                roughness: Some(0.25),
+                base_color_texture: Some(ServerTextureRef {
+                    texture: tex,
+                    transform: None,
+                    texture_coord_slot: None,
+                }),
+                ..Default::default()
+            }),
+            ..Default::default()
+        },
+    });
+
+    // Mirror the transformer legend on the other side of the rulers
+    let transform = glm::rotate_x(&Mat4::identity(), 90.0f32.to_radians());
+    let transform = glm::scale(&transform, &vec3(0.5, 1.0, 0.5));
+    let transform = glm::translate(&transform, &vec3(-1.2, domain.far_edge_z(), -0.5));
+
+    let geom = make_plane(state, transform, mat);
+
+    state.entities.new_component(ServerEntityState {
+        name: Some("Color Legend".into()),
+        mutable: ServerEntityStateUpdatable {
+            transform: Some(legend_transform(domain)),
+            representation: Some(ServerEntityRepresentation::new_render(
+                ServerRenderRepresentation {
+                    mesh: geom,
+                    instances: None,
+                },
+            )),
+            ..Default::default()
+        },
+    })
+}
+
+/// Width of the title banner, in domain units.
+const TITLE_WIDTH: f32 = 2.0;
+
+/// Creates a banner with the dataset title, lying on the ground along the
+/// near edge of the data so it reads from the default bird's eye view.
+pub fn make_title_banner(
+    state: &mut ServerState,
+    domain: &Domain,
+    system: &PowerSystem,
+    supersample: u32,
+    theme: ChartTheme,
+    visible: bool,
+) -> EntityReference {
+    let png = generate_title_banner(system, supersample, theme);
+    let tex = texture_from_bytes(state, &png, "Title");
+
+    let mat = state.materials.new_component(ServerMaterialState {
+        name: Some("Title Material".into()),
+        mutable: ServerMaterialStateUpdatable {
+            pbr_info: Some(ServerPBRInfo {
+                base_color: [1.0, 1.0, 1.0, 1.0],
+                metallic: Some(0.0),
+                roughness: Some(0.25),
+                base_color_texture: Some(ServerTextureRef {
+                    texture: tex,
+                    transform: None,
+                    texture_coord_slot: None,
+                }),
+                ..Default::default()
+            }),
+            ..Default::default()
+        },
+    });
+
+    let depth = TITLE_WIDTH * TITLE_SIZE.1 as f32 / TITLE_SIZE.0 as f32;
+
+    // Just in front of the data, lifted off the base map to avoid fighting it
+    let transform = glm::translation(&vec3(0.0, 0.002, domain.near_edge_z() + depth));
+    let transform = glm::scale(&transform, &vec3(TITLE_WIDTH, 1.0, depth));
+
+    let geom = make_plane(state, transform, mat);
+
+    state.entities.new_component(ServerEntityState {
+        name: Some("Title".into()),
+        mutable: ServerEntityStateUpdatable {
+            transform: Some(legend_transform(domain)),
             representation: Some(ServerEntityRepresentation::new_render(
                 ServerRenderRepresentation {
                     mesh: geom,
                     instances: None,
                 },
             )),
-            visible: Some(ty == RulerType::Voltage),
+            visible: Some(visible),
             ..Default::default()
         },
     })
@@ -1,25 +1,30 @@
 use std::{
-    collections::VecDeque,
+    borrow::Cow,
+    collections::{HashMap, VecDeque},
+    path::PathBuf,
     sync::{Arc, Mutex},
 };
 
 use crate::{
+    arguments::Arguments,
     basemap::make_basemap,
-    domain::Domain,
+    chart::{ChartTheme, SummaryMetric, TimeAxis, VoltageReference, CHART_SIZE},
+    domain::{Domain, VoltageSafety},
     instance::*,
     instanced_item::{
         make_bus_element, make_generator_element, make_hazard_element, make_line_element,
-        make_line_flow_element, make_transformer_element, InstancedItem,
+        make_line_flow_element, make_line_trail_element, make_transformer_element, InstancedItem,
     },
+    mesh_assets::MeshAssets,
     methods::*,
     probe::Probe,
     ruler::{
-        make_ruler,
+        make_color_legend, make_ruler, make_tf_legend, make_title_banner, rescale_ruler,
         RulerType::{LineLoad, Voltage},
     },
-    summary::SummaryItem,
+    summary::{render_chart, SummaryItem, INDICATOR_COLOR},
     texture::{make_chevron_texture, make_hsv_texture},
-    PowerSystem,
+    GeneratorState, LineState, PowerSystem, TransformerState,
 };
 
 use colabrodo_common::components::{BufferState, TextureRef};
@@ -27,10 +32,151 @@ use colabrodo_server::{server::*, server_messages::*};
 
 use nalgebra_glm::{self as glm};
 
-/// Small offset used to visually separate phases (A/B/C) in space.
-///
-/// Prevents overlapping geometry like lines and transformers.
-const PHASE_OFFSET: glm::Vec3 = glm::Vec3::new(0.001, 0.0, -0.001);
+/// Number of interpolated frames shown per time step when interpolation is on.
+pub const INTERPOLATION_STEPS: u32 = 5;
+
+/// Opacity of the newest step in the line trail
+const TRAIL_MAX_ALPHA: f32 = 0.5;
+
+/// Default base color and opacity of hazard cells
+pub const HAZARD_COLOR: [f32; 4] = [0.75, 0.75, 1.0, 0.9];
+
+/// Base color of snapshot materials, which darkens them against the live scene
+const SNAPSHOT_TINT: [f32; 4] = [0.45, 0.45, 0.6, 1.0];
+
+/// Color texture coordinates for each phase
+const BAND_RED: f32 = 0.0;
+const BAND_GREEN: f32 = 0.33;
+const BAND_BLUE: f32 = 0.66;
+
+bitflags::bitflags! {
+    /// Set of phases (A/B/C) that should be rendered.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct PhaseMask: u8 {
+        const A = 0b001;
+        const B = 0b010;
+        const C = 0b100;
+    }
+}
+
+impl PhaseMask {
+    /// Phase letters, in bit order.
+    const LETTERS: [(PhaseMask, char); 3] = [
+        (PhaseMask::A, 'A'),
+        (PhaseMask::B, 'B'),
+        (PhaseMask::C, 'C'),
+    ];
+
+    /// Parses a single phase letter, ignoring case.
+    pub fn from_letter(letter: char) -> Option<Self> {
+        Self::LETTERS
+            .into_iter()
+            .find(|(_, l)| l.eq_ignore_ascii_case(&letter))
+            .map(|(mask, _)| mask)
+    }
+
+    /// Returns the letter of the only phase in the set, if there is exactly
+    /// one.
+    pub fn solo(self) -> Option<char> {
+        Self::LETTERS
+            .into_iter()
+            .find(|(mask, _)| self == *mask)
+            .map(|(_, l)| l)
+    }
+}
+
+/// Line voltage summary (p.u.) for the displayed time step.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct VoltageStats {
+    pub min: f32,
+    pub max: f32,
+    pub mean: f32,
+}
+
+impl VoltageStats {
+    /// Computes stats over both ends of each line, for every phase in
+    /// `phases`. With no lines or phases, all values are zero.
+    fn compute(lines: &[LineState], phases: PhaseMask) -> Self {
+        let values = lines.iter().flat_map(|l| {
+            let v = &l.voltage;
+            [
+                (PhaseMask::A, v.sa),
+                (PhaseMask::A, v.ea),
+                (PhaseMask::B, v.sb),
+                (PhaseMask::B, v.eb),
+                (PhaseMask::C, v.sc),
+                (PhaseMask::C, v.ec),
+            ]
+            .into_iter()
+            .filter(|(p, _)| phases.contains(*p))
+            .map(|(_, v)| v)
+        });
+
+        let mut count = 0usize;
+        let mut sum = 0.0f64;
+        let mut min = f32::INFINITY;
+        let mut max = f32::NEG_INFINITY;
+
+        for v in values {
+            count += 1;
+            sum += v as f64;
+            min = min.min(v);
+            max = max.max(v);
+        }
+
+        if count == 0 {
+            return Self::default();
+        }
+
+        Self {
+            min,
+            max,
+            mean: (sum / count as f64) as f32,
+        }
+    }
+}
+
+/// Number of lines carrying real power in each direction, for one phase.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct FlowCounts {
+    /// Power flows from the start of the line to the end
+    pub forward: usize,
+    /// Power flows from the end of the line back to the start
+    pub reverse: usize,
+    /// No real power
+    pub zero: usize,
+}
+
+/// Line power flow directions for the displayed time step.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct FlowStats {
+    /// Counts for phases A, B, and C
+    pub phases: [FlowCounts; 3],
+}
+
+impl FlowStats {
+    /// Counts lines by the sign of the real power at their start, which is
+    /// also what points their flow chevrons.
+    fn compute(lines: &[LineState]) -> Self {
+        let mut stats = Self::default();
+
+        for l in lines {
+            let p = &l.real_power;
+
+            for (counts, watt) in stats.phases.iter_mut().zip([p.sa, p.sb, p.sc]) {
+                if watt > 0.0 {
+                    counts.forward += 1;
+                } else if watt < 0.0 {
+                    counts.reverse += 1;
+                } else {
+                    counts.zero += 1;
+                }
+            }
+        }
+
+        stats
+    }
+}
 
 /// Core application state used for visualization logic and render management.
 ///
@@ -42,51 +188,239 @@ pub struct GridState {
     pub time_step: usize,
     pub time_step_direction: i32,
     pub max_time_step: usize,
+
+    /// Blend between time steps during playback
+    pub interpolate: bool,
+    /// Fraction (0.0 - 1.0) of the way from `time_step` to the next step
+    pub sub_step: f32,
+
+    /// First time step of the playback loop
+    pub loop_start: usize,
+    /// Last time step (inclusive) of the playback loop
+    pub loop_end: usize,
     pub previous_show_line_load: bool,
     pub show_line_load: bool,
+    /// Encode generator phase angle in glyph orientation
+    pub show_angle: bool,
+    /// Only draw lines whose voltage is outside the safe range
+    pub violations_only: bool,
+    /// Viewer position reported by the client. Lines far from it are culled
+    pub view_position: Option<glm::Vec3>,
+    /// Distance from the viewer, along the ground, beyond which lines are culled
+    pub view_radius: f32,
+    /// Generate hazard cells where lines cross the voltage limits
+    pub hazards_enabled: bool,
+    /// Number of previous time steps drawn as a faded trail behind the lines
+    pub trail_length: usize,
+    /// Data that drives line color
+    pub line_coloring: LineColoring,
+    /// Tint lines and buses by the sign of reactive power, rather than by phase
+    pub reactive_sign: bool,
+    /// Size generators by nameplate capacity, where known, rather than output
+    pub gen_capacity: bool,
+    /// Animate flow on phases B and C as well as A
+    pub flow_all_phases: bool,
+    /// Shape used to draw buses
+    bus_style: BusStyle,
+    /// Hue that overrides the color of a line, by line index, until cleared
+    pub highlights: HashMap<usize, f32>,
+
+    /// Phases to render; disabled phases are skipped entirely
+    pub phases: PhaseMask,
 
     pub domain: Domain,
 
     pub hazard: InstancedItem,
+    /// Material shared by all hazard cells
+    hazard_mat: MaterialReference,
+
+    /// Hazard cell counts from the most recent recompute
+    pub hazard_stats: HazardStats,
+    /// Hazard cells of recent time steps, for drawing persistent hazards
+    pub hazard_history: HazardHistory,
+
+    /// For each time step, whether any line violates voltage limits
+    violation_steps: Vec<bool>,
 
-    _base_map: Option<EntityReference>,
+    /// Line voltage summary from the most recent recompute, over the
+    /// rendered phases
+    pub voltage_stats: VoltageStats,
+
+    /// Line flow directions from the most recent recompute
+    pub flow_stats: FlowStats,
+
+    /// Floorplan planes; more than one if the image was tiled
+    base_map: Vec<EntityReference>,
+    basemap_max_texture: u32,
+    /// Stretch the floorplan over the data bounds, ignoring its coordinates
+    fit_basemap: bool,
 
     ruler: EntityReference,
     line_load_ruler: EntityReference,
 
+    tf_legend: EntityReference,
+    pub show_tf_legend: bool,
+
+    /// Never read, but holding it keeps the legend in the scene. Replacing it
+    /// drops the old legend
+    #[allow(dead_code)]
+    color_legend: EntityReference,
+
+    title_banner: EntityReference,
+    pub show_title: bool,
+
     //pub axis_selector: VerticalAxisSelector,
-    pub summary: SummaryItem,
+    /// Overview chart, once built, unless disabled with `--no-summary`
+    pub summary: Option<SummaryItem>,
+    /// Build the overview chart when warming up
+    summary_enabled: bool,
+    /// The overview chart turns to face each viewer
+    pub billboard_summary: bool,
+    /// Color of the overview chart's time indicator
+    pub indicator_color: [f32; 4],
+    /// Whether the first full recompute has finished
+    pub ready: bool,
+    /// Probe charts turn to face each viewer
+    pub billboard_probes: bool,
+
+    /// Size of probe and overview charts, in pixels
+    pub chart_size: (u32, u32),
+    /// Supersampling factor used when rendering charts
+    pub chart_supersample: u32,
+    pub chart_theme: ChartTheme,
+    /// Time axis labeling and event marker for probe charts
+    pub time_axis: TimeAxis,
+    /// Voltage (p.u.) of the nominal level on probe charts
+    chart_nominal: f32,
+    /// Mark the safe voltage limits on probe charts
+    chart_limits: bool,
+    /// Label chart voltages in kV rather than p.u.
+    pub show_kv: bool,
 
     pub move_func: Option<MethodReference>,
     pub activate_func: Option<MethodReference>,
 
     pub probes: VecDeque<Probe>,
+    /// Greatest distance, along the ground, at which a probe attaches to a line
+    pub snap_radius: f32,
+    /// File probe positions are restored from and saved to
+    pub probes_path: Option<PathBuf>,
+    /// Meshes for glyphs and widgets created after startup
+    pub meshes: MeshAssets,
 
     bus: InstancedItem,
     line: InstancedItem,
+    line_trail: InstancedItem,
     line_flow: InstancedItem,
     transformer: InstancedItem,
     generator: InstancedItem,
 
+    /// Material shared by lines, buses, and transformers
+    line_mat: MaterialReference,
+    /// Tinted material for scene snapshots
+    snapshot_mat: MaterialReference,
+    /// Frozen copies of the scene, oldest first
+    pub snapshots: Vec<SceneSnapshot>,
+
+    /// Reference time step drawn beside the live scene, if comparing
+    pub compare: Option<CompareScene>,
+    /// Distance along X from the live scene to the compared step
+    pub compare_offset: f32,
+
+    /// Largest number of instances drawn in any one group
+    max_instances: Option<usize>,
+    /// Skip uploading instance groups that match their last upload
+    pub diff_buffers: bool,
+    /// Append the dataset line index to each line instance
+    pub line_ids: bool,
+    /// Append the start and end saturation to each line instance
+    pub line_gradient: bool,
+    /// Instance bytes handled since startup
+    pub upload_stats: UploadStats,
+    /// Advertise diagnostic methods that expose internal buffers
+    allow_debug_methods: bool,
+
+    /// When a client last invoked a method
+    last_activity: std::time::Instant,
+    /// Playback pauses after this long without client activity
+    pub idle_timeout: Option<std::time::Duration>,
+
     pub active_timer: Option<tokio::sync::oneshot::Sender<bool>>,
     pub send_back: Option<tokio::sync::mpsc::Sender<bool>>,
 
     pub probe_move_request_signal: tokio::sync::mpsc::UnboundedSender<bool>,
+    /// Long running tasks, aborted on shutdown
+    background: Vec<tokio::task::JoinHandle<()>>,
 }
 
 pub type GridStatePtr = Arc<Mutex<GridState>>;
 
+/// Names of the instanced groups that can be looked up with
+/// [`GridState::instance_group`].
+pub const INSTANCE_GROUPS: [&str; 7] = [
+    "buses",
+    "lines",
+    "line_trail",
+    "line_flows",
+    "hazards",
+    "transformers",
+    "generators",
+];
+
+/// Instance data handled by buffer uploads, in bytes.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct UploadStats {
+    /// Size of every group that was due an upload
+    pub total: usize,
+    /// Bytes in instances that differ from the previous upload
+    pub changed: usize,
+    /// Bytes actually sent to clients
+    pub sent: usize,
+}
+
+impl std::ops::AddAssign for UploadStats {
+    fn add_assign(&mut self, rhs: Self) {
+        self.total += rhs.total;
+        self.changed += rhs.changed;
+        self.sent += rhs.sent;
+    }
+}
+
+/// A frozen copy of the buses, lines, and transformers at one time step.
+///
+/// Recomputes never touch a snapshot; it is removed when dropped.
+pub struct SceneSnapshot {
+    /// Time step the snapshot was taken at
+    pub time_step: usize,
+    /// Parent of the snapshot entities, named after the time step
+    _root: EntityReference,
+    _items: Vec<InstancedItem>,
+}
+
+/// A second copy of the buses, lines, and transformers, showing a fixed
+/// reference time step beside the live scene.
+pub struct CompareScene {
+    /// Time step shown
+    pub time_step: usize,
+    /// Parent of the compared entities, offset from the live scene
+    root: EntityReference,
+    bus: InstancedItem,
+    line: InstancedItem,
+    transformer: InstancedItem,
+}
+
 impl GridState {
     /// Initializes a new GridState with geometry, materials, and system configuration.
     ///
     /// Sets up all buffers, entities, materials, rulers, and spawns background tasks.
-    pub fn new(state: ServerStatePtr, system: PowerSystem) -> GridStatePtr {
+    pub fn new(state: ServerStatePtr, system: PowerSystem, args: &Arguments) -> GridStatePtr {
         let mut state_lock = state.lock().unwrap();
 
         // Load texture and build material for color-mapped lines
         let hsv_texture = make_hsv_texture(&mut state_lock);
 
-        // Build a material for lines
+        // Build a material for lines, buses, and transformers. Only double
+        // sided if asked, as it costs clients fill rate
         let line_mat = state_lock.materials.new_component(ServerMaterialState {
             name: Some("Line Material".into()),
             mutable: ServerMaterialStateUpdatable {
@@ -101,6 +435,7 @@ impl GridState {
                     roughness: Some(0.5),
                     ..Default::default()
                 }),
+                double_sided: args.double_sided.then_some(true),
                 ..Default::default()
             },
         });
@@ -128,109 +463,309 @@ impl GridState {
             },
         });
 
+        // Build a translucent material for the trail of previous line states
+        let line_trail_mat = state_lock.materials.new_component(ServerMaterialState {
+            name: Some("Line Trail Material".into()),
+            mutable: ServerMaterialStateUpdatable {
+                pbr_info: Some(ServerPBRInfo {
+                    base_color: [1.0, 1.0, 1.0, TRAIL_MAX_ALPHA],
+                    base_color_texture: Some(TextureRef {
+                        texture: hsv_texture.clone(),
+                        transform: None,
+                        texture_coord_slot: None,
+                    }),
+                    metallic: Some(0.0),
+                    roughness: Some(1.0),
+                    ..Default::default()
+                }),
+                use_alpha: Some(true),
+                ..Default::default()
+            },
+        });
+
         // build a material for hazard blocks
         let hazard_mat = state_lock.materials.new_component(ServerMaterialState {
             name: None,
+            mutable: ServerMaterialStateUpdatable {
+                pbr_info: Some(hazard_pbr(HAZARD_COLOR)),
+                use_alpha: Some(true),
+                ..Default::default()
+            },
+        });
+
+        // Build a tinted material for frozen snapshots of the scene
+        let snapshot_mat = state_lock.materials.new_component(ServerMaterialState {
+            name: Some("Snapshot Material".into()),
             mutable: ServerMaterialStateUpdatable {
                 pbr_info: Some(ServerPBRInfo {
-                    base_color: [0.75, 0.75, 1.0, 0.9],
+                    base_color: SNAPSHOT_TINT,
+                    base_color_texture: Some(TextureRef {
+                        texture: hsv_texture.clone(),
+                        transform: None,
+                        texture_coord_slot: None,
+                    }),
                     metallic: Some(0.0),
                     roughness: Some(1.0),
                     ..Default::default()
                 }),
-                use_alpha: Some(true),
                 ..Default::default()
             },
         });
 
+        let meshes = MeshAssets::load(args).expect("loading mesh overrides");
+
         // Create empty instanced geometry containers
-        let bus = make_bus_element(&mut state_lock, line_mat.clone());
-        let line = make_line_element(&mut state_lock, line_mat.clone());
-        let line_flow = make_line_flow_element(&mut state_lock, line_flow_mat);
-        let transformer = make_transformer_element(&mut state_lock, line_mat);
-        let generator = make_generator_element(&mut state_lock, hsv_texture);
-        let hazard = make_hazard_element(&mut state_lock, hazard_mat);
+        let bus = make_bus_element(&mut state_lock, line_mat.clone(), args.bus_style);
+        let mut line = make_line_element(&mut state_lock, line_mat.clone());
+        line.extra_floats = usize::from(args.line_ids) + 2 * usize::from(args.line_gradient);
+        let line_trail = make_line_trail_element(&mut state_lock, line_trail_mat);
+        let line_flow = make_line_flow_element(&mut state_lock, line_flow_mat, &meshes.line_flow);
+        let transformer = make_transformer_element(&mut state_lock, line_mat.clone());
+        let generator = make_generator_element(&mut state_lock, hsv_texture, &meshes.generator);
+        let hazard = make_hazard_element(&mut state_lock, hazard_mat.clone(), &meshes.hazard);
 
         let ts_len = system.lines.len();
 
+        let start_step = initial_time_step(args.start_step, ts_len);
+
         // determine bounding box
-        let mut bounds_min = glm::DVec2::new(1E9, 1E9);
-        let mut bounds_max = glm::DVec2::new(-1E9, -1E9);
+        let (bounds_min, bounds_max) = compute_bounds(&system);
 
-        // Compute the bounding box of the power system based on all line endpoints
-        for time_step in &system.lines {
-            for line in time_step {
-                let pa = glm::DVec2::new(line.loc.sx, line.loc.sy);
-                let pb = glm::DVec2::new(line.loc.ex, line.loc.ey);
+        let mut domain = Domain::new(bounds_min, bounds_max, args.domain_fit);
 
-                bounds_min = glm::min2(&glm::min2(&bounds_min, &pa), &pb);
-                bounds_max = glm::max2(&glm::max2(&bounds_max, &pa), &pb);
-            }
+        if let Some(exaggeration) = args.exaggeration {
+            domain.exaggeration = exaggeration;
+        }
+
+        if let Some(tube_min) = args.tube_min {
+            domain.tube_min = tube_min;
+        }
+
+        if let Some(tube_max) = args.tube_max {
+            domain.tube_max = tube_max;
         }
 
-        let domain = Domain::new(bounds_min, bounds_max);
+        domain.open_tube_max = args.open_tube_max;
+
+        if let Some(phase_offset) = args.phase_offset {
+            domain.phase_offset = phase_offset;
+        }
+
+        if let Some(tf_phase_radius) = args.tf_phase_radius {
+            domain.tf_phase_radius = tf_phase_radius;
+        }
+
+        if let Some(line_load_max) = args.line_load_max {
+            domain.line_load_max = line_load_max;
+        }
+
+        if let Some(gen_watt_bounds) = args.gen_watt_bounds {
+            domain.gen_watt_bounds = gen_watt_bounds;
+        }
+
+        if let Some(gen_size_scale) = args.gen_size_scale {
+            domain.gen_size_scale = gen_size_scale;
+        }
+
+        domain.scene_y_offset = args.scene_y_offset;
+        domain.y_flip = !args.no_y_flip;
+        domain.hazard_planes = args.hazard_planes.clone();
+        domain.ground_dash = args.ground_dash;
+        domain.ground_gap = args.ground_gap;
+
+        let violation_steps = compute_violation_steps(&system, &domain);
 
         log::info!("Loaded powersystem with {ts_len} timesteps");
         log::info!("Bounds {bounds_min:?} {bounds_max:?}");
         log::info!("Domain {domain:?}");
 
         // Optionally add floorplan and rulers to the scene
-        let base_map = make_basemap(&mut state_lock, &system, &domain);
-
-        let ruler = make_ruler(&mut state_lock, &domain, Voltage);
-        let ruler_ll = make_ruler(&mut state_lock, &domain, LineLoad);
+        let base_map = make_basemap(
+            &mut state_lock,
+            &system,
+            &domain,
+            args.basemap_max_texture,
+            args.fit_basemap,
+        );
+
+        let ruler = make_ruler(
+            &mut state_lock,
+            &domain,
+            Voltage,
+            None,
+            args.chart_supersample,
+            args.chart_theme,
+            true,
+        );
+        let ruler_ll = make_ruler(
+            &mut state_lock,
+            &domain,
+            LineLoad,
+            None,
+            args.chart_supersample,
+            args.chart_theme,
+            false,
+        );
+
+        let color_legend = make_color_legend(
+            &mut state_lock,
+            &domain,
+            LineColoring::default(),
+            args.chart_supersample,
+            args.chart_theme,
+        );
+
+        let tf_legend = make_tf_legend(
+            &mut state_lock,
+            &domain,
+            &system,
+            args.chart_supersample,
+            args.chart_theme,
+            true,
+        );
+
+        let title_banner = make_title_banner(
+            &mut state_lock,
+            &domain,
+            &system,
+            args.chart_supersample,
+            args.chart_theme,
+            !args.no_title,
+        );
 
         let (probe_signal_tx, probe_signal_rx) = tokio::sync::mpsc::unbounded_channel::<bool>();
 
-        let summary_item = SummaryItem::new(&system, &domain, &mut state_lock);
+        let system = Arc::new(system);
 
         // Construct shared GridState instance
         let ret = Arc::new(Mutex::new(GridState {
             state: state.clone(),
-            system: Arc::new(system),
-            time_step: (ts_len / 2).clamp(0, ts_len),
+            system,
+            time_step: start_step,
             time_step_direction: 0,
             max_time_step: ts_len,
+            interpolate: false,
+            sub_step: 0.0,
+            loop_start: 0,
+            loop_end: ts_len.saturating_sub(1),
             previous_show_line_load: false,
             show_line_load: false,
+            show_angle: false,
+            violations_only: false,
+            line_coloring: LineColoring::default(),
+            reactive_sign: false,
+            gen_capacity: args.gen_capacity,
+            flow_all_phases: args.flow_all_phases,
+            bus_style: args.bus_style,
+            highlights: HashMap::new(),
+            phases: PhaseMask::all(),
             bus,
             line,
+            line_trail,
+            trail_length: args.trail_length,
+            hazards_enabled: true,
+            view_position: None,
+            view_radius: args.view_radius,
             line_flow,
             transformer,
             generator,
+            line_mat,
+            snapshot_mat,
+            snapshots: Vec::new(),
+            compare: None,
+            compare_offset: args.compare_offset,
+            max_instances: args.max_instances.map(|max| max as usize),
+            diff_buffers: args.diff_buffers,
+            line_ids: args.line_ids,
+            line_gradient: args.line_gradient,
+            upload_stats: UploadStats::default(),
+            allow_debug_methods: args.allow_debug_methods,
+            last_activity: std::time::Instant::now(),
+            idle_timeout: args.idle_timeout.0,
             domain,
             hazard,
-            _base_map: base_map,
+            hazard_mat,
+            hazard_stats: HazardStats::default(),
+            hazard_history: HazardHistory::new(args.hazard_window),
+            violation_steps,
+            voltage_stats: VoltageStats::default(),
+            flow_stats: FlowStats::default(),
+            base_map,
+            basemap_max_texture: args.basemap_max_texture,
+            fit_basemap: args.fit_basemap,
             ruler,
             line_load_ruler: ruler_ll,
-            summary: summary_item,
+            tf_legend,
+            show_tf_legend: true,
+            color_legend,
+            title_banner,
+            show_title: !args.no_title,
+            summary: None,
+            summary_enabled: !args.no_summary,
+            billboard_summary: args.billboard_summary,
+            indicator_color: INDICATOR_COLOR,
+            ready: false,
+            billboard_probes: true,
+            chart_size: CHART_SIZE,
+            chart_supersample: args.chart_supersample,
+            chart_theme: args.chart_theme,
+            time_axis: TimeAxis {
+                x_origin: args.time_origin,
+                event: args.event_step,
+                max_points: args.chart_points.map(|max| max as usize),
+            },
+            chart_nominal: args.chart_nominal,
+            chart_limits: args.chart_limits,
+            show_kv: false,
             move_func: None,
             activate_func: None,
             probes: Default::default(),
+            snap_radius: args.snap_radius,
+            probes_path: args.probes.clone(),
+            meshes,
             active_timer: None,
             send_back: None,
             probe_move_request_signal: probe_signal_tx,
+            background: Vec::new(),
         }));
 
         // Spawn time-step advancement watcher
         {
             let (tx, rx) = tokio::sync::mpsc::channel(16);
 
-            tokio::spawn(crate::methods::advance_watcher(ret.clone(), rx));
+            let task = tokio::spawn(crate::methods::advance_watcher(ret.clone(), rx));
 
             let mut lock = ret.lock().unwrap();
 
             lock.send_back = Some(tx);
+            lock.background.push(task);
         }
 
         // Spawn probe update service (handles probe movement + chart generation)
         {
-            tokio::spawn(crate::methods::probe_service(ret.clone(), probe_signal_rx));
+            let task = tokio::spawn(crate::methods::probe_service(ret.clone(), probe_signal_rx));
+
+            ret.lock().unwrap().background.push(task);
         }
 
         ret
     }
 
+    /// Stops playback and the background tasks, ahead of exiting.
+    pub fn shutdown(&mut self) {
+        self.time_step_direction = 0;
+
+        if let Some(timer) = self.active_timer.take() {
+            let _ = timer.send(true);
+        }
+
+        self.send_back = None;
+
+        for task in self.background.drain(..) {
+            task.abort();
+        }
+    }
+
     /// Registers method handlers with the server after initial setup.
     ///
     /// Attaches user-interactable methods like time controls and probe creation.
@@ -252,269 +787,1605 @@ impl GridState {
             .methods
             .new_owned_component(create_create_probe(app_state.clone()));
 
+        let create_probe_at = state_lock
+            .methods
+            .new_owned_component(create_create_probe_at(app_state.clone()));
+
         let create_activate = state_lock
             .methods
             .new_owned_component(create_activate(app_state.clone()));
 
-        let create_line_load = state_lock
+        let comp_interpolation = state_lock
             .methods
-            .new_owned_component(create_toggle_line_load(app_state.clone()));
+            .new_owned_component(create_toggle_interpolation(app_state.clone()));
 
-        // Register methods to be advertised by the server
-        state_lock.update_document(ServerDocumentUpdate {
-            methods_list: Some(vec![
-                comp_set_time,
-                comp_step_time,
-                comp_adv_time,
-                create_probe,
-                create_line_load,
-            ]),
-            signals_list: None,
-        });
+        let comp_angle = state_lock
+            .methods
+            .new_owned_component(create_toggle_angle(app_state.clone()));
 
-        // Cache references to shared interaction methods
-        let move_func = state_lock
+        let comp_reactive_sign = state_lock
             .methods
-            .new_owned_component(create_set_position(app_state.clone()));
+            .new_owned_component(create_toggle_reactive_sign(app_state.clone()));
 
-        {
-            let mut app_lock = app_state.lock().unwrap();
-            app_lock.move_func = Some(move_func);
-            app_lock.activate_func = Some(create_activate);
+        let comp_voltage_units = state_lock
+            .methods
+            .new_owned_component(create_toggle_voltage_units(app_state.clone()));
 
-            let time_frac = app_lock.time_frac();
+        let comp_gen_scale = state_lock
+            .methods
+            .new_owned_component(create_set_generator_scale(app_state.clone()));
+        let comp_gen_capacity = state_lock
+            .methods
+            .new_owned_component(create_toggle_generator_capacity(app_state.clone()));
+        let comp_flow_phases = state_lock
+            .methods
+            .new_owned_component(create_toggle_flow_phases(app_state.clone()));
 
-            app_lock.summary.set_time_normalized(time_frac);
-        }
-    }
+        let comp_clear_probes = state_lock
+            .methods
+            .new_owned_component(create_clear_probes(app_state.clone()));
 
-    /// Returns the current normalized time step (0.0 - 1.0).
-    pub fn time_frac(&self) -> f32 {
-        self.time_step as f32 / self.max_time_step as f32
-    }
-}
+        let comp_snap_radius = state_lock
+            .methods
+            .new_owned_component(create_set_snap_radius(app_state.clone()));
 
-/// Recomputes all instance data and line visuals based on the current time step.
-///
-/// Clears all geometry buffers, processes per-phase instances, and updates visibility.
-pub fn recompute_all(gstate: &mut GridState, server_state: &mut ServerState) {
-    log::debug!("Recomputing all");
-    gstate.bus.buffer.clear();
-    gstate.line.buffer.clear();
-    gstate.line_flow.buffer.clear();
-    gstate.hazard.buffer.clear();
-    gstate.transformer.buffer.clear();
-    gstate.generator.buffer.clear();
+        let comp_save_probes = state_lock
+            .methods
+            .new_owned_component(create_save_probe_positions(app_state.clone()));
 
-    let line_ts = &gstate.system.lines[gstate.time_step];
-    let tf_ts = &gstate.system.tfs[gstate.time_step];
-    let gen_ts = &gstate.system.pvs[gstate.time_step];
+        let comp_violations_only = state_lock
+            .methods
+            .new_owned_component(create_set_violations_only(app_state.clone()));
 
-    // ===
+        let comp_tf_legend = state_lock
+            .methods
+            .new_owned_component(create_toggle_tf_legend(app_state.clone()));
 
-    // Toggle ruler visibility based on active data mode
-    if gstate.show_line_load != gstate.previous_show_line_load {
-        gstate.previous_show_line_load = gstate.show_line_load;
+        let comp_title = state_lock
+            .methods
+            .new_owned_component(create_toggle_title(app_state.clone()));
 
-        let set_invisible = ServerEntityStateUpdatable {
-            visible: Some(false),
-            ..Default::default()
-        };
+        let comp_view_position = state_lock
+            .methods
+            .new_owned_component(create_set_view_position(app_state.clone()));
 
-        let set_visible = ServerEntityStateUpdatable {
-            visible: Some(true),
-            ..Default::default()
-        };
+        let comp_hazards = state_lock
+            .methods
+            .new_owned_component(create_toggle_hazards(app_state.clone()));
+        let comp_hazard_window = state_lock
+            .methods
+            .new_owned_component(create_set_hazard_window(app_state.clone()));
+        let comp_hazard_appearance = state_lock
+            .methods
+            .new_owned_component(create_set_hazard_appearance(app_state.clone()));
 
-        if gstate.show_line_load {
-            set_invisible.patch(&gstate.ruler);
-            set_visible.patch(&gstate.line_load_ruler);
-        } else {
-            set_visible.patch(&gstate.ruler);
-            set_invisible.patch(&gstate.line_load_ruler);
-        }
-    }
+        let comp_trail_length = state_lock
+            .methods
+            .new_owned_component(create_set_trail_length(app_state.clone()));
 
-    // ===
+        let comp_line_coloring = state_lock
+            .methods
+            .new_owned_component(create_set_line_coloring(app_state.clone()));
+        let comp_bus_style = state_lock
+            .methods
+            .new_owned_component(create_set_bus_style(app_state.clone()));
 
-    const BAND_RED: f32 = 0.0;
-    const BAND_GREEN: f32 = 0.33;
-    const BAND_BLUE: f32 = 0.66;
+        let comp_loop_range = state_lock
+            .methods
+            .new_owned_component(create_set_loop_range(app_state.clone()));
 
-    // Recompute bus indicators (for phase A)
-    recompute_buses(
-        line_ts,
-        |s| LineGetterResult {
-            volt_start: s.voltage.sa,
-            volt_end: s.voltage.ea,
-            watt: s.real_power.sa.abs(),
-            vars: s.reactive_power.sa.abs(),
-            line_load: s.line_load.a,
-        },
-        &gstate.domain,
-        PHASE_OFFSET * 0.0,
-        BAND_RED,
-        &mut gstate.bus.buffer,
-        gstate.show_line_load,
-    );
+        let create_line_load = state_lock
+            .methods
+            .new_owned_component(create_toggle_line_load(app_state.clone()));
 
-    // Recompute main line visuals for all three phases
+        let comp_voltage_limits = state_lock
+            .methods
+            .new_owned_component(create_set_voltage_limits(app_state.clone()));
 
-    // Phase A
-    recompute_lines(
-        line_ts,
-        |s| LineGetterResult {
-            volt_start: s.voltage.sa,
-            volt_end: s.voltage.ea,
-            watt: s.real_power.sa,
-            vars: s.reactive_power.sa,
-            line_load: s.line_load.a,
-        },
-        &gstate.domain,
-        PHASE_OFFSET * 0.0,
-        BAND_RED,
-        &mut gstate.line.buffer,
-        &mut gstate.hazard.buffer,
-        gstate.show_line_load,
-    );
+        let comp_voltage_range = state_lock
+            .methods
+            .new_owned_component(create_set_voltage_range(app_state.clone()));
 
-    // Phase B
-    recompute_lines(
-        line_ts,
-        |s| LineGetterResult {
-            volt_start: s.voltage.sb,
-            volt_end: s.voltage.eb,
-            watt: s.real_power.sb,
-            vars: s.reactive_power.sb,
-            line_load: s.line_load.b,
-        },
-        &gstate.domain,
-        PHASE_OFFSET * 1.0,
-        BAND_GREEN,
-        &mut gstate.line.buffer,
-        &mut gstate.hazard.buffer,
-        gstate.show_line_load,
-    );
+        let comp_safety_margin = state_lock
+            .methods
+            .new_owned_component(create_set_safety_margin(app_state.clone()));
 
-    // Phase C
-    recompute_lines(
-        line_ts,
-        |s| LineGetterResult {
-            volt_start: s.voltage.sc,
-            volt_end: s.voltage.ec,
-            watt: s.real_power.sc,
-            vars: s.reactive_power.ec,
-            line_load: s.line_load.c,
-        },
-        &gstate.domain,
-        PHASE_OFFSET * 2.0,
-        BAND_BLUE,
-        &mut gstate.line.buffer,
-        &mut gstate.hazard.buffer,
-        gstate.show_line_load,
-    );
+        let comp_exaggeration = state_lock
+            .methods
+            .new_owned_component(create_set_exaggeration(app_state.clone()));
 
-    // Generate low-lying ground-level connections (topological view)
+        let comp_phases = state_lock
+            .methods
+            .new_owned_component(create_set_phases(app_state.clone()));
+        let comp_solo_phase = state_lock
+            .methods
+            .new_owned_component(create_solo_phase(app_state.clone()));
 
-    recompute_gound_lines(line_ts, &gstate.domain, &mut gstate.line.buffer);
+        let comp_voltage_stats = state_lock
+            .methods
+            .new_owned_component(create_get_voltage_stats(app_state.clone()));
+        let comp_flow_stats = state_lock
+            .methods
+            .new_owned_component(create_get_flow_stats(app_state.clone()));
 
-    // Recompute flowing animation indicators for phase A
+        let comp_next_violation = state_lock
+            .methods
+            .new_owned_component(create_next_violation(app_state.clone()));
 
-    recompute_line_flows(
-        line_ts,
-        |s| LineGetterResult {
-            volt_start: s.voltage.sa,
-            volt_end: s.voltage.ea,
-            watt: s.real_power.sa.abs(),
-            vars: s.reactive_power.sa.abs(),
-            line_load: s.line_load.a,
-        },
-        &gstate.domain,
-        PHASE_OFFSET * 0.0,
-        //BAND_RED,
-        &mut gstate.line_flow.buffer,
-        gstate.show_line_load,
-    );
+        let comp_prev_violation = state_lock
+            .methods
+            .new_owned_component(create_prev_violation(app_state.clone()));
 
-    // Recompute transformer visuals for phases A/B/C
+        let comp_diagnostics = state_lock
+            .methods
+            .new_owned_component(create_get_diagnostics(app_state.clone()));
 
-    recompute_tfs(
-        tf_ts,
-        |s| TfGetterResult {
-            volt_start: s.voltage.sa,
-            volt_end: s.voltage.ea,
-            tap: s.tap.a,
-            tap_change: s.tap_changes.a,
-        },
-        &gstate.domain,
-        PHASE_OFFSET * 0.0,
-        BAND_RED,
-        &mut gstate.transformer.buffer,
-    );
+        let comp_focus_line = state_lock
+            .methods
+            .new_owned_component(create_focus_line(app_state.clone()));
 
-    recompute_tfs(
-        tf_ts,
-        |s| TfGetterResult {
-            volt_start: s.voltage.sb,
-            volt_end: s.voltage.eb,
-            tap: s.tap.b,
-            tap_change: s.tap_changes.b,
-        },
-        &gstate.domain,
-        PHASE_OFFSET * 1.0,
-        BAND_GREEN,
-        &mut gstate.transformer.buffer,
-    );
+        let comp_export_probe_chart = state_lock
+            .methods
+            .new_owned_component(create_export_probe_chart(app_state.clone()));
+        let comp_probe_offsets = state_lock
+            .methods
+            .new_owned_component(create_set_probe_offsets(app_state.clone()));
 
-    recompute_tfs(
-        tf_ts,
-        |s| TfGetterResult {
-            volt_start: s.voltage.sc,
-            volt_end: s.voltage.ec,
-            tap: s.tap.c,
-            tap_change: s.tap_changes.c,
-        },
-        &gstate.domain,
-        PHASE_OFFSET * 2.0,
-        BAND_BLUE,
-        &mut gstate.transformer.buffer,
-    );
+        let comp_list_lines = state_lock
+            .methods
+            .new_owned_component(create_list_lines(app_state.clone()));
 
-    // Recompute generator visuals (single-phase)
+        let comp_highlight_line = state_lock
+            .methods
+            .new_owned_component(create_highlight_line(app_state.clone()));
 
-    recompute_gens(
-        gen_ts,
-        |s| GeneratorGetterResult {
-            voltage: s.voltage.a,
-            angle: s.angle.a,
-            real: s.real,
-            react: s.react,
-            ty: s.ty,
+        let comp_clear_highlight = state_lock
+            .methods
+            .new_owned_component(create_clear_highlight(app_state.clone()));
+
+        let comp_pick_lines = state_lock
+            .methods
+            .new_owned_component(create_pick_lines(app_state.clone()));
+
+        let comp_export_snapshot = state_lock
+            .methods
+            .new_owned_component(create_export_snapshot(app_state.clone()));
+
+        let comp_snapshot_scene = state_lock
+            .methods
+            .new_owned_component(create_snapshot_scene(app_state.clone()));
+
+        let comp_compare_step = state_lock
+            .methods
+            .new_owned_component(create_set_compare_step(app_state.clone()));
+
+        let comp_clear_compare = state_lock
+            .methods
+            .new_owned_component(create_clear_compare(app_state.clone()));
+
+        let comp_clear_snapshots = state_lock
+            .methods
+            .new_owned_component(create_clear_snapshots(app_state.clone()));
+
+        let comp_billboard = state_lock
+            .methods
+            .new_owned_component(create_set_billboard(app_state.clone()));
+
+        let comp_summary_metric = state_lock
+            .methods
+            .new_owned_component(create_set_summary_metric(app_state.clone()));
+
+        let comp_indicator_color = state_lock
+            .methods
+            .new_owned_component(create_set_indicator_color(app_state.clone()));
+
+        let comp_chart_resolution = state_lock
+            .methods
+            .new_owned_component(create_set_chart_resolution(app_state.clone()));
+
+        let comp_line_state = state_lock
+            .methods
+            .new_owned_component(create_get_line_state(app_state.clone()));
+
+        let comp_height_range = state_lock
+            .methods
+            .new_owned_component(create_set_height_range(app_state.clone()));
+
+        let comp_hazard_stats = state_lock
+            .methods
+            .new_owned_component(create_get_hazard_stats(app_state.clone()));
+
+        let comp_reset_view = state_lock
+            .methods
+            .new_owned_component(create_reset_view(app_state.clone()));
+
+        let mut methods_list = vec![
+            comp_set_time,
+            comp_step_time,
+            comp_next_violation,
+            comp_prev_violation,
+            comp_adv_time,
+            comp_loop_range,
+            comp_interpolation,
+            create_probe,
+            create_probe_at,
+            comp_clear_probes,
+            comp_snap_radius,
+            comp_save_probes,
+            create_line_load,
+            comp_tf_legend,
+            comp_title,
+            comp_angle,
+            comp_reactive_sign,
+            comp_voltage_units,
+            comp_exaggeration,
+            comp_voltage_limits,
+            comp_voltage_range,
+            comp_safety_margin,
+            comp_gen_scale,
+            comp_gen_capacity,
+            comp_flow_phases,
+            comp_phases,
+            comp_solo_phase,
+            comp_violations_only,
+            comp_hazards,
+            comp_hazard_window,
+            comp_hazard_appearance,
+            comp_view_position,
+            comp_trail_length,
+            comp_line_coloring,
+            comp_bus_style,
+            comp_reset_view,
+            comp_summary_metric,
+            comp_indicator_color,
+            comp_chart_resolution,
+            comp_line_state,
+            comp_height_range,
+            comp_billboard,
+            comp_hazard_stats,
+            comp_voltage_stats,
+            comp_flow_stats,
+            comp_export_snapshot,
+            comp_snapshot_scene,
+            comp_clear_snapshots,
+            comp_compare_step,
+            comp_clear_compare,
+            comp_focus_line,
+            comp_list_lines,
+            comp_pick_lines,
+            comp_highlight_line,
+            comp_clear_highlight,
+            comp_probe_offsets,
+            comp_export_probe_chart,
+            comp_diagnostics,
+        ];
+
+        // Diagnostic methods are only advertised when asked for
+        if app_state.lock().unwrap().allow_debug_methods {
+            methods_list.push(
+                state_lock
+                    .methods
+                    .new_owned_component(create_dump_instances(app_state.clone())),
+            );
+        }
+
+        // Register methods to be advertised by the server
+        state_lock.update_document(ServerDocumentUpdate {
+            methods_list: Some(methods_list),
+            signals_list: None,
+        });
+
+        // Cache references to shared interaction methods
+        let move_func = state_lock
+            .methods
+            .new_owned_component(create_set_position(app_state.clone()));
+
+        {
+            let mut app_lock = app_state.lock().unwrap();
+            app_lock.move_func = Some(move_func);
+            app_lock.activate_func = Some(create_activate);
+
+            restore_probes(&mut app_lock, &mut state_lock);
+        }
+    }
+
+    /// Records that a client interacted with the server just now.
+    pub fn note_activity(&mut self) {
+        self.last_activity = std::time::Instant::now();
+    }
+
+    /// Whether clients have been quiet for longer than the idle timeout.
+    pub fn is_idle(&self) -> bool {
+        self.idle_timeout
+            .is_some_and(|timeout| self.last_activity.elapsed() > timeout)
+    }
+
+    /// Returns the current normalized time step (0.0 - 1.0).
+    pub fn time_frac(&self) -> f32 {
+        time_fraction(self.time_step as f32 + self.sub_step, self.max_time_step)
+    }
+
+    /// Returns the blend fraction toward the next time step, or zero if the
+    /// current step has no successor in the loop.
+    fn blend_frac(&self) -> f32 {
+        if self.interpolate && self.time_step < self.loop_end {
+            self.sub_step
+        } else {
+            0.0
+        }
+    }
+
+    /// Sets the playback loop range, clamping to valid time steps.
+    ///
+    /// Returns false, leaving the range unchanged, if `start > end`.
+    pub fn set_loop_range(&mut self, start: usize, end: usize) -> bool {
+        if start > end {
+            return false;
+        }
+
+        let last = self.max_time_step.saturating_sub(1);
+
+        self.loop_start = start.min(last);
+        self.loop_end = end.min(last);
+        true
+    }
+
+    /// Moves the time step one step in the playback direction, wrapping
+    /// within the loop range.
+    ///
+    /// With interpolation on, this moves by a fraction of a step, and only
+    /// rolls over into the next time step once the fraction wraps.
+    pub fn advance_time_step(&mut self) {
+        let mut step = self.time_step_direction;
+
+        if self.interpolate {
+            let n = INTERPOLATION_STEPS as f32;
+
+            // snap to the sub step grid to avoid drift
+            let sub = ((self.sub_step + step as f32 / n) * n).round() / n;
+
+            if sub >= 1.0 {
+                self.sub_step = sub - 1.0;
+                step = 1;
+            } else if sub < 0.0 {
+                self.sub_step = sub + 1.0;
+                step = -1;
+            } else {
+                self.sub_step = sub;
+                return;
+            }
+        }
+
+        let start = self.loop_start as i32;
+        let len = (self.loop_end - self.loop_start + 1) as i32;
+
+        let new_time = self.time_step as i32 + step;
+
+        // do a wrapping add within the loop range
+        let new_time = start + (new_time - start).rem_euclid(len);
+
+        self.time_step = new_time.try_into().unwrap();
+    }
+
+    /// Recomputes the domain from the current data bounds.
+    ///
+    /// User-set calibration (voltage limits, tube widths, exaggeration) is kept.
+    /// The basemap, rulers, legends, and title are rebuilt, the summary chart
+    /// is re-placed, and
+    /// all instances are recomputed.
+    pub fn reset_view(&mut self, state: &mut ServerState) {
+        let (bounds_min, bounds_max) = compute_bounds(&self.system);
+
+        self.domain = self.domain.with_bounds(bounds_min, bounds_max);
+
+        log::info!("Reset bounds {bounds_min:?} {bounds_max:?}");
+        log::info!("Domain {:?}", self.domain);
+
+        // Dropping the references deletes the old planes, before the new
+        // ones are made
+        self.base_map.clear();
+        self.base_map = make_basemap(
+            state,
+            &self.system,
+            &self.domain,
+            self.basemap_max_texture,
+            self.fit_basemap,
+        );
+        self.rebuild_rulers(state);
+        self.color_legend = make_color_legend(
+            state,
+            &self.domain,
+            self.line_coloring,
+            self.chart_supersample,
+            self.chart_theme,
+        );
+        self.tf_legend = make_tf_legend(
+            state,
+            &self.domain,
+            &self.system,
+            self.chart_supersample,
+            self.chart_theme,
+            self.show_tf_legend,
+        );
+        self.title_banner = make_title_banner(
+            state,
+            &self.domain,
+            &self.system,
+            self.chart_supersample,
+            self.chart_theme,
+            self.show_title,
+        );
+
+        // New rulers start in voltage mode; let recompute_all fix visibility
+        self.previous_show_line_load = false;
+
+        if let Some(summary) = &self.summary {
+            summary.reposition(&self.domain);
+        }
+
+        recompute_all(self, state);
+    }
+
+    /// Finds a line by index, or by case-insensitive substring of its name.
+    /// The first matching line is returned.
+    pub fn find_line(&self, query: &ciborium::Value) -> Option<usize> {
+        let line_count = self.system.line_meta.len();
+
+        match query {
+            ciborium::Value::Integer(i) => {
+                let i = usize::try_from(i128::from(*i)).ok()?;
+                (i < line_count).then_some(i)
+            }
+            ciborium::Value::Text(text) => {
+                let needle = text.to_lowercase();
+                self.system
+                    .line_meta
+                    .iter()
+                    .position(|name| name.to_lowercase().contains(&needle))
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns the world space midpoint of a line at the current time step,
+    /// and a radius that encloses both of its endpoints.
+    pub fn line_extent(&self, line_i: usize) -> (glm::Vec3, f32) {
+        let d = &self.domain;
+        let state = &self.system.lines[self.time_step][line_i];
+
+        let (height_a, height_b) = if self.show_line_load {
+            let h = d.line_load_to_height(state.line_load.a);
+            (h, h)
+        } else {
+            (
+                d.voltage_to_height(state.voltage.sa),
+                d.voltage_to_height(state.voltage.ea),
+            )
+        };
+
+        let p_a = glm::vec3(
+            d.lerp_x(state.loc.sx as f32),
+            height_a,
+            d.lerp_y(state.loc.sy as f32),
+        );
+        let p_b = glm::vec3(
+            d.lerp_x(state.loc.ex as f32),
+            height_b,
+            d.lerp_y(state.loc.ey as f32),
+        );
+
+        // keep very short lines from producing a degenerate view
+        let radius = (glm::distance(&p_a, &p_b) / 2.0).max(d.tube_max * 4.0);
+
+        ((p_a + p_b) / 2.0 + d.scene_offset(), radius)
+    }
+
+    /// Checks whether a line is drawn with the current phase selection and
+    /// violation filter.
+    ///
+    /// With the filter on, a line is drawn if any selected phase has an
+    /// unsafe average voltage.
+    pub fn line_visible(&self, line: &LineState) -> bool {
+        let v = &line.voltage;
+
+        [
+            (PhaseMask::A, v.sa, v.ea),
+            (PhaseMask::B, v.sb, v.eb),
+            (PhaseMask::C, v.sc, v.ec),
+        ]
+        .into_iter()
+        .filter(|(phase, _, _)| self.phases.contains(*phase))
+        .any(|(_, start, end)| {
+            !self.violations_only
+                || !matches!(
+                    self.domain.voltage_safety((start + end) / 2.0),
+                    VoltageSafety::Safe
+                )
+        })
+    }
+
+    /// Finds the nearest time step with a voltage violation, moving from the
+    /// current step in `direction` (1 or -1) and wrapping around the ends.
+    ///
+    /// The current step is only returned if it is the only violating step.
+    pub fn find_violation(&self, direction: i32) -> Option<usize> {
+        let len = self.violation_steps.len() as i64;
+
+        (1..=len)
+            .map(|i| {
+                (self.time_step as i64 + i * direction.signum() as i64).rem_euclid(len) as usize
+            })
+            .find(|&t| self.violation_steps[t])
+    }
+
+    /// Switches the data that drives line color, and rebuilds the color
+    /// legend to match.
+    ///
+    /// Callers are expected to recompute instances afterwards.
+    pub fn set_line_coloring(&mut self, state: &mut ServerState, coloring: LineColoring) {
+        self.line_coloring = coloring;
+
+        // Replacing the reference drops the old legend
+        self.color_legend = make_color_legend(
+            state,
+            &self.domain,
+            coloring,
+            self.chart_supersample,
+            self.chart_theme,
+        );
+    }
+
+    /// Switches the shape used to draw buses, rebuilding the live and
+    /// compared bus entities. Snapshots keep their shape.
+    ///
+    /// Callers are expected to recompute instances afterwards.
+    pub fn set_bus_style(&mut self, state: &mut ServerState, style: BusStyle) {
+        if style == self.bus_style {
+            return;
+        }
+
+        self.bus_style = style;
+
+        // Replacing the items drops the old entities
+        self.bus = make_bus_element(state, self.line_mat.clone(), style);
+
+        if let Some(compare) = &mut self.compare {
+            compare.bus = make_bus_element(state, self.line_mat.clone(), style);
+            set_parent(&compare.bus.entity, &compare.root);
+        }
+    }
+
+    /// Shows or hides the transformer legend.
+    pub fn set_tf_legend_visible(&mut self, visible: bool) {
+        self.show_tf_legend = visible;
+
+        let update = ServerEntityStateUpdatable {
+            visible: Some(visible),
+            ..Default::default()
+        };
+
+        update.patch(&self.tf_legend);
+    }
+
+    /// Shows or hides the title banner.
+    pub fn set_title_visible(&mut self, visible: bool) {
+        self.show_title = visible;
+
+        let update = ServerEntityStateUpdatable {
+            visible: Some(visible),
+            ..Default::default()
+        };
+
+        update.patch(&self.title_banner);
+    }
+
+    /// Rebuilds both rulers from the current domain, so their labels match
+    /// the voltage and line load ranges. Only the ruler for the active mode is
+    /// visible.
+    pub fn rebuild_rulers(&mut self, state: &mut ServerState) {
+        // Replacing the references drops the old entities
+        self.ruler = make_ruler(
+            state,
+            &self.domain,
+            Voltage,
+            self.phases.solo(),
+            self.chart_supersample,
+            self.chart_theme,
+            !self.show_line_load,
+        );
+        self.line_load_ruler = make_ruler(
+            state,
+            &self.domain,
+            LineLoad,
+            self.phases.solo(),
+            self.chart_supersample,
+            self.chart_theme,
+            self.show_line_load,
+        );
+    }
+
+    /// Changes the rendered phases. The rulers are rebuilt when a phase is
+    /// soloed or restored, so their titles name it.
+    ///
+    /// Callers are expected to recompute instances afterwards.
+    pub fn set_phases(&mut self, state: &mut ServerState, phases: PhaseMask) {
+        let relabel = phases.solo() != self.phases.solo();
+
+        self.phases = phases;
+
+        if relabel {
+            self.rebuild_rulers(state);
+        }
+    }
+
+    /// Reference levels for probe charts. Limits follow the current safe
+    /// voltage range.
+    pub fn voltage_reference(&self) -> VoltageReference {
+        VoltageReference {
+            nominal: Some(self.chart_nominal),
+            limits: self
+                .chart_limits
+                .then_some((self.domain.safe_min, self.domain.safe_max)),
+        }
+    }
+
+    /// Changes the base color and opacity of every hazard cell.
+    pub fn set_hazard_color(&self, color: [f32; 4]) {
+        let update = ServerMaterialStateUpdatable {
+            pbr_info: Some(hazard_pbr(color)),
+            use_alpha: Some(true),
+            ..Default::default()
+        };
+
+        update.patch(&self.hazard_mat);
+    }
+
+    /// Changes the voltage range mapped onto line heights, and rebuilds the
+    /// rulers to match.
+    ///
+    /// Callers are expected to recompute instances afterwards.
+    pub fn set_voltage_range(&mut self, state: &mut ServerState, low: f32, high: f32) {
+        self.domain.volt_min = low;
+        self.domain.volt_max = high;
+
+        self.rebuild_rulers(state);
+    }
+
+    /// Changes the heights that the voltage range maps onto, and rescales the
+    /// rulers to match.
+    ///
+    /// Callers are expected to recompute instances afterwards.
+    pub fn set_height_range(&mut self, low: f32, high: f32) {
+        self.domain.volt_height_min = low;
+        self.domain.volt_height_max = high;
+
+        rescale_ruler(&self.ruler, &self.domain);
+        rescale_ruler(&self.line_load_ruler, &self.domain);
+    }
+
+    /// Changes the vertical exaggeration and rescales the rulers to match.
+    ///
+    /// Callers are expected to recompute instances afterwards.
+    pub fn set_exaggeration(&mut self, exaggeration: f32) {
+        self.domain.exaggeration = exaggeration;
+
+        rescale_ruler(&self.ruler, &self.domain);
+        rescale_ruler(&self.line_load_ruler, &self.domain);
+    }
+
+    /// Looks up an instanced group by one of the [`INSTANCE_GROUPS`] names.
+    pub fn instance_group(&self, name: &str) -> Option<&InstancedItem> {
+        let item = match name {
+            "buses" => &self.bus,
+            "lines" => &self.line,
+            "line_trail" => &self.line_trail,
+            "line_flows" => &self.line_flow,
+            "hazards" => &self.hazard,
+            "transformers" => &self.transformer,
+            "generators" => &self.generator,
+            _ => return None,
+        };
+
+        Some(item)
+    }
+
+    /// Freezes the current buses, lines, and transformers into a snapshot
+    /// drawn with a tinted material. Returns the captured time step.
+    pub fn snapshot_scene(&mut self, state: &mut ServerState) -> usize {
+        let root = state.entities.new_component(ServerEntityState {
+            name: Some(format!("Snapshot of time step {}", self.time_step)),
+            mutable: ServerEntityStateUpdatable::default(),
+        });
+
+        let bus_style = self.bus_style;
+
+        let items = vec![
+            freeze_item(
+                state,
+                &self.bus,
+                |s, m| make_bus_element(s, m, bus_style),
+                &self.snapshot_mat,
+                &root,
+            ),
+            freeze_item(
+                state,
+                &self.line,
+                make_line_element,
+                &self.snapshot_mat,
+                &root,
+            ),
+            freeze_item(
+                state,
+                &self.transformer,
+                make_transformer_element,
+                &self.snapshot_mat,
+                &root,
+            ),
+        ];
+
+        self.snapshots.push(SceneSnapshot {
+            time_step: self.time_step,
+            _root: root,
+            _items: items,
+        });
+
+        self.time_step
+    }
+
+    /// Draws `time_step` beside the live scene, `compare_offset` along X,
+    /// creating the compared entities on first use.
+    ///
+    /// Callers are expected to recompute instances afterwards.
+    pub fn set_compare_step(&mut self, state: &mut ServerState, time_step: usize) {
+        let transform = glm::translation(&glm::vec3(self.compare_offset, 0.0, 0.0));
+        let transform: [f32; 16] = transform.as_slice().try_into().unwrap();
+
+        if let Some(compare) = &mut self.compare {
+            compare.time_step = time_step;
+
+            ServerEntityStateUpdatable {
+                transform: Some(transform),
+                ..Default::default()
+            }
+            .patch(&compare.root);
+
+            return;
+        }
+
+        let root = state.entities.new_component(ServerEntityState {
+            name: Some("Compared Time Step".into()),
+            mutable: ServerEntityStateUpdatable {
+                transform: Some(transform),
+                ..Default::default()
+            },
+        });
+
+        let bus = make_bus_element(state, self.line_mat.clone(), self.bus_style);
+        let mut line = make_line_element(state, self.line_mat.clone());
+        line.extra_floats = self.line.extra_floats;
+        let transformer = make_transformer_element(state, self.line_mat.clone());
+
+        for item in [&bus, &line, &transformer] {
+            set_parent(&item.entity, &root);
+        }
+
+        self.compare = Some(CompareScene {
+            time_step,
+            root,
+            bus,
+            line,
+            transformer,
+        });
+    }
+
+    /// Stops drawing the compared time step. Returns false if compare mode
+    /// was already off.
+    pub fn clear_compare(&mut self) -> bool {
+        // Dropping the scene removes its entities
+        self.compare.take().is_some()
+    }
+
+    /// Changes the safe voltage range, refreshing the violation index and
+    /// color legend that depend on it.
+    ///
+    /// Callers are expected to recompute instances afterwards.
+    pub fn set_voltage_limits(&mut self, state: &mut ServerState, low: f32, high: f32) {
+        self.domain.safe_min = low;
+        self.domain.safe_max = high;
+
+        self.violation_steps = compute_violation_steps(&self.system, &self.domain);
+        self.set_line_coloring(state, self.line_coloring);
+
+        // The rulers shade the safe band
+        self.rebuild_rulers(state);
+    }
+}
+
+/// Returns the element states at time step `t`, blended toward step `t + 1`
+/// by `frac`. Without a blend (or a successor), the stored states are used
+/// directly. Element kinds missing from the dataset have no states.
+fn blend_step<T: Clone>(
+    steps: &[Vec<T>],
+    t: usize,
+    frac: f32,
+    blend: impl Fn(&T, &T, f32) -> T,
+) -> Cow<'_, [T]> {
+    let Some(current) = steps.get(t) else {
+        return Cow::Borrowed(&[]);
+    };
+
+    match steps.get(t + 1) {
+        Some(next) if frac > 0.0 => Cow::Owned(
+            current
+                .iter()
+                .zip(next)
+                .map(|(a, b)| blend(a, b, frac))
+                .collect(),
+        ),
+        _ => Cow::Borrowed(current),
+    }
+}
+
+/// Drops lines whose midpoint is farther than `radius`, measured along the
+/// ground, from the viewer. Without a view position every line is kept.
+///
+/// Also returns the original index of each kept line, or nothing if no lines
+/// were dropped.
+fn cull_to_view<'a>(
+    lines: Cow<'a, [LineState]>,
+    d: &Domain,
+    view: Option<glm::Vec3>,
+    radius: f32,
+) -> (Cow<'a, [LineState]>, Option<Vec<usize>>) {
+    let Some(view) = view else {
+        return (lines, None);
+    };
+
+    let view = view.xz();
+
+    let (indices, kept) = lines
+        .iter()
+        .enumerate()
+        .filter(|(_, l)| {
+            let mid = glm::vec2(
+                d.lerp_x(((l.loc.sx + l.loc.ex) / 2.0) as f32),
+                d.lerp_y(((l.loc.sy + l.loc.ey) / 2.0) as f32),
+            );
+
+            glm::distance(&mid, &view) <= radius
+        })
+        .map(|(i, l)| (i, l.clone()))
+        .unzip();
+
+    (Cow::Owned(kept), Some(indices))
+}
+
+/// Flags each time step that has at least one line, on any phase, whose
+/// average voltage is outside the safe range.
+fn compute_violation_steps(system: &PowerSystem, domain: &Domain) -> Vec<bool> {
+    let unsafe_voltage = |v: f32| !matches!(domain.voltage_safety(v), VoltageSafety::Safe);
+
+    system
+        .lines
+        .iter()
+        .map(|lines| {
+            lines.iter().any(|l| {
+                let v = &l.voltage;
+                unsafe_voltage((v.sa + v.ea) / 2.0)
+                    || unsafe_voltage((v.sb + v.eb) / 2.0)
+                    || unsafe_voltage((v.sc + v.ec) / 2.0)
+            })
+        })
+        .collect()
+}
+
+/// Computes the bounding box of the power system based on all line endpoints.
+fn compute_bounds(system: &PowerSystem) -> (glm::DVec2, glm::DVec2) {
+    let mut bounds_min = glm::DVec2::new(1E9, 1E9);
+    let mut bounds_max = glm::DVec2::new(-1E9, -1E9);
+
+    for time_step in &system.lines {
+        for line in time_step {
+            let pa = glm::DVec2::new(line.loc.sx, line.loc.sy);
+            let pb = glm::DVec2::new(line.loc.ex, line.loc.ey);
+
+            bounds_min = glm::min2(&glm::min2(&bounds_min, &pa), &pb);
+            bounds_max = glm::max2(&glm::max2(&bounds_max, &pa), &pb);
+        }
+    }
+
+    (bounds_min, bounds_max)
+}
+
+/// Builds the overview chart and fills the scene for the first time, then
+/// marks the state ready.
+///
+/// The chart image is rendered without holding any locks, so this can run in
+/// the background while clients connect. They see an empty scene that fills
+/// in once it is done.
+pub fn warm_up(app_state: &GridStatePtr) {
+    let timer = std::time::Instant::now();
+
+    let (system, size, supersample, theme, max_points, summary_enabled) = {
+        let lock = app_state.lock().unwrap();
+
+        (
+            lock.system.clone(),
+            lock.chart_size,
+            lock.chart_supersample,
+            lock.chart_theme,
+            lock.time_axis.max_points,
+            lock.summary_enabled,
+        )
+    };
+
+    let chart = summary_enabled.then(|| {
+        render_chart(
+            &system,
+            SummaryMetric::default(),
+            size,
+            supersample,
+            theme,
+            max_points,
+        )
+    });
+
+    let mut lock = app_state.lock().unwrap();
+    let state_ptr = lock.state.clone();
+    let mut state = state_ptr.lock().unwrap();
+
+    if let Some(chart) = chart {
+        let mut summary = SummaryItem::new(
+            system,
+            &lock.domain,
+            &chart,
+            size,
+            supersample,
+            theme,
+            max_points,
+            lock.billboard_summary,
+            lock.indicator_color,
+            &mut state,
+        );
+
+        summary.set_time_normalized(lock.time_frac());
+        lock.summary = Some(summary);
+    }
+
+    recompute_all(&mut lock, &mut state);
+    lock.ready = true;
+
+    log::info!("Scene ready after {} ms", timer.elapsed().as_millis());
+}
+
+/// Normalizes a (possibly fractional) time step out of `steps`, so the first
+/// step is 0.0 and the last is 1.0. With one step or none, this is 0.0.
+pub fn time_fraction(time: f32, steps: usize) -> f32 {
+    if steps <= 1 {
+        return 0.0;
+    }
+
+    time / (steps - 1) as f32
+}
+
+/// Clamps a requested time step to the last of `steps` time steps. With no
+/// steps at all, this is 0.
+pub fn clamp_time_step(step: i64, steps: usize) -> usize {
+    step.clamp(0, steps.saturating_sub(1) as i64) as usize
+}
+
+/// Picks the time step to start on: `start` if given, or the middle step,
+/// clamped to the data.
+fn initial_time_step(start: Option<usize>, steps: usize) -> usize {
+    start.unwrap_or(steps / 2).min(steps.saturating_sub(1))
+}
+
+/// Recomputes all instance data and line visuals based on the current time step.
+///
+/// Clears all geometry buffers, processes per-phase instances, and updates visibility.
+pub fn recompute_all(gstate: &mut GridState, server_state: &mut ServerState) {
+    log::debug!("Recomputing all");
+    gstate.bus.buffer.clear();
+    gstate.line.buffer.clear();
+    gstate.line_trail.buffer.clear();
+    gstate.line_flow.buffer.clear();
+    gstate.hazard.buffer.clear();
+    gstate.transformer.buffer.clear();
+    gstate.generator.buffer.clear();
+    gstate.hazard_stats = HazardStats::default();
+
+    let frac = gstate.blend_frac();
+
+    let line_ts = blend_step(
+        &gstate.system.lines,
+        gstate.time_step,
+        frac,
+        LineState::lerp,
+    );
+    let tf_ts = blend_step(
+        &gstate.system.tfs,
+        gstate.time_step,
+        frac,
+        TransformerState::lerp,
+    );
+    let gen_ts = blend_step(
+        &gstate.system.pvs,
+        gstate.time_step,
+        frac,
+        GeneratorState::lerp,
+    );
+
+    gstate.voltage_stats = VoltageStats::compute(&line_ts, gstate.phases);
+    gstate.flow_stats = FlowStats::compute(&line_ts);
+
+    // Stats cover every line, but only lines near the viewer are drawn
+    let (line_ts, line_indices) = cull_to_view(
+        line_ts,
+        &gstate.domain,
+        gstate.view_position,
+        gstate.view_radius,
+    );
+
+    let phase_offset = gstate.domain.phase_offset();
+    let scene_offset = gstate.domain.scene_offset();
+
+    // ===
+
+    // Toggle ruler visibility based on active data mode
+    if gstate.show_line_load != gstate.previous_show_line_load {
+        gstate.previous_show_line_load = gstate.show_line_load;
+
+        let set_invisible = ServerEntityStateUpdatable {
+            visible: Some(false),
+            ..Default::default()
+        };
+
+        let set_visible = ServerEntityStateUpdatable {
+            visible: Some(true),
+            ..Default::default()
+        };
+
+        if gstate.show_line_load {
+            set_invisible.patch(&gstate.ruler);
+            set_visible.patch(&gstate.line_load_ruler);
+        } else {
+            set_visible.patch(&gstate.ruler);
+            set_invisible.patch(&gstate.line_load_ruler);
+        }
+    }
+
+    // ===
+
+    // Buses, lines, and transformers share their computation with the
+    // compared time step
+    let mut bus = std::mem::take(&mut gstate.bus.buffer);
+    let mut line = std::mem::take(&mut gstate.line.buffer);
+    let mut hazard = std::mem::take(&mut gstate.hazard.buffer);
+    let mut transformer = std::mem::take(&mut gstate.transformer.buffer);
+
+    // Hazard cells are only found in voltage mode
+    let mut checker = (gstate.hazards_enabled && !gstate.show_line_load)
+        .then(|| HazardCheck::new(&gstate.domain));
+
+    recompute_core(
+        gstate,
+        &line_ts,
+        line_indices.as_deref(),
+        &tf_ts,
+        &mut bus,
+        &mut line,
+        checker.as_mut(),
+        &mut transformer,
+    );
+
+    if let Some(checker) = checker {
+        gstate.hazard_stats = checker.stats();
+
+        let time_step = gstate.time_step;
+        gstate.hazard_history.record(time_step, checker);
+        gstate.hazard_history.create_matrices(&mut hazard);
+    }
+
+    gstate.bus.buffer = bus;
+    gstate.line.buffer = line;
+    gstate.hazard.buffer = hazard;
+    gstate.transformer.buffer = transformer;
+
+    // The compared step is drawn with the same settings, but never blended
+    if let Some(mut compare) = gstate.compare.take() {
+        recompute_compare(gstate, &mut compare, server_state);
+        gstate.compare = Some(compare);
+    }
+
+    // Faded copies of previous time steps, oldest the faintest
+    recompute_trail(gstate);
+
+    // Recompute flowing animation indicators for phase A, and for B and C
+    // if asked
+
+    if gstate.phases.contains(PhaseMask::A) {
+        recompute_line_flows(
+            &line_ts,
+            |s| LineGetterResult {
+                volt_start: s.voltage.sa,
+                volt_end: s.voltage.ea,
+                watt: s.real_power.sa.abs(),
+                vars: s.reactive_power.sa.abs(),
+                inductive: s.reactive_power.sa >= 0.0,
+                line_load: s.line_load.a,
+            },
+            &gstate.domain,
+            scene_offset,
+            0,
+            //BAND_RED,
+            &mut gstate.line_flow.buffer,
+            gstate.show_line_load,
+        );
+    }
+
+    if gstate.flow_all_phases && gstate.phases.contains(PhaseMask::B) {
+        recompute_line_flows(
+            &line_ts,
+            |s| LineGetterResult {
+                volt_start: s.voltage.sb,
+                volt_end: s.voltage.eb,
+                watt: s.real_power.sb.abs(),
+                vars: s.reactive_power.sb.abs(),
+                inductive: s.reactive_power.sb >= 0.0,
+                line_load: s.line_load.b,
+            },
+            &gstate.domain,
+            scene_offset,
+            1,
+            &mut gstate.line_flow.buffer,
+            gstate.show_line_load,
+        );
+    }
+
+    if gstate.flow_all_phases && gstate.phases.contains(PhaseMask::C) {
+        recompute_line_flows(
+            &line_ts,
+            |s| LineGetterResult {
+                volt_start: s.voltage.sc,
+                volt_end: s.voltage.ec,
+                watt: s.real_power.sc.abs(),
+                vars: s.reactive_power.sc.abs(),
+                inductive: s.reactive_power.sc >= 0.0,
+                line_load: s.line_load.c,
+            },
+            &gstate.domain,
+            scene_offset,
+            2,
+            &mut gstate.line_flow.buffer,
+            gstate.show_line_load,
+        );
+    }
+
+    // Recompute generator visuals (single-phase)
+
+    recompute_gens(
+        &gen_ts,
+        |s| GeneratorGetterResult {
+            voltage: s.voltage.a,
+            angle: s.angle.a,
+            real: s.real,
+            react: s.react,
+            ty: s.ty,
+            capacity: s.capacity,
         },
         &gstate.domain,
-        PHASE_OFFSET * 0.0,
+        scene_offset + phase_offset * 0.0,
         &mut gstate.generator.buffer,
         gstate.show_line_load,
+        gstate.show_angle,
+        gstate.gen_capacity,
     );
 
+    // Thin out groups too large for clients to handle
+    if let Some(max) = gstate.max_instances {
+        for (name, element) in [
+            ("buses", &mut gstate.bus),
+            ("lines", &mut gstate.line),
+            ("line trail", &mut gstate.line_trail),
+            ("line flows", &mut gstate.line_flow),
+            ("hazards", &mut gstate.hazard),
+            ("transformers", &mut gstate.transformer),
+            ("generators", &mut gstate.generator),
+        ] {
+            let dropped = subsample_instances(&mut element.buffer, element.stride(), max);
+
+            if dropped > 0 {
+                log::debug!("Dropped {dropped} {name} instances to stay under {max}");
+            }
+        }
+    }
+
     // Upload all instance buffers to the GPU and patch renderables
 
+    let diff = gstate.diff_buffers;
+    let mut stats = UploadStats::default();
+
     for element in [
-        &gstate.bus,
-        &gstate.line,
-        &gstate.line_flow,
-        &gstate.hazard,
-        &gstate.transformer,
-        &gstate.generator,
+        &mut gstate.bus,
+        &mut gstate.line,
+        &mut gstate.line_trail,
+        &mut gstate.line_flow,
+        &mut gstate.hazard,
+        &mut gstate.transformer,
+        &mut gstate.generator,
     ] {
-        update_buffers(server_state, element);
+        stats += update_buffers(server_state, element, diff);
     }
+
+    log::debug!(
+        "Sent {} of {} instance bytes ({} changed)",
+        stats.sent,
+        stats.total,
+        stats.changed
+    );
+
+    gstate.upload_stats += stats;
+}
+
+/// Refills and uploads the buses, lines, and transformers of the compared
+/// time step.
+fn recompute_compare(
+    gstate: &GridState,
+    compare: &mut CompareScene,
+    server_state: &mut ServerState,
+) {
+    let (Some(lines), Some(tfs)) = (
+        gstate.system.lines.get(compare.time_step),
+        gstate.system.tfs.get(compare.time_step),
+    ) else {
+        return;
+    };
+
+    let (line_ts, line_indices) = cull_to_view(
+        Cow::Borrowed(lines),
+        &gstate.domain,
+        gstate.view_position,
+        gstate.view_radius,
+    );
+
+    compare.bus.buffer.clear();
+    compare.line.buffer.clear();
+    compare.transformer.buffer.clear();
+
+    // Hazard cells are only drawn for the live step
+    recompute_core(
+        gstate,
+        &line_ts,
+        line_indices.as_deref(),
+        tfs,
+        &mut compare.bus.buffer,
+        &mut compare.line.buffer,
+        None,
+        &mut compare.transformer.buffer,
+    );
+
+    for element in [
+        &mut compare.bus,
+        &mut compare.line,
+        &mut compare.transformer,
+    ] {
+        if let Some(max) = gstate.max_instances {
+            subsample_instances(&mut element.buffer, element.stride(), max);
+        }
+
+        update_buffers(server_state, element, gstate.diff_buffers);
+    }
+}
+
+/// Fills instance buffers for the buses, lines (with ground lines), and
+/// transformers of one time step, using the current display settings.
+///
+/// Shared by the live scene and the compared time step. `line_indices` maps
+/// each of `line_ts` back to its index in the dataset, if lines were culled,
+/// so highlights land on the right lines. Lines are tested against the
+/// voltage limits into `hazards`, if given.
+#[allow(clippy::too_many_arguments)]
+fn recompute_core(
+    gstate: &GridState,
+    line_ts: &[LineState],
+    line_indices: Option<&[usize]>,
+    tf_ts: &[TransformerState],
+    bus: &mut Vec<u8>,
+    line: &mut Vec<u8>,
+    mut hazards: Option<&mut HazardCheck>,
+    transformer: &mut Vec<u8>,
+) {
+    let phase_offset = gstate.domain.phase_offset();
+    let scene_offset = gstate.domain.scene_offset();
+
+    let highlight = |i: usize| {
+        let line_i = line_indices.map_or(i, |indices| indices[i]);
+        gstate.highlights.get(&line_i).copied()
+    };
+
+    // Lines carry their dataset index, as a float, for client side picking
+    let line_id = |i: usize, dest: &mut Vec<u8>| {
+        if gstate.line_ids {
+            let line_i = line_indices.map_or(i, |indices| indices[i]);
+            dest.extend_from_slice(&(line_i as f32).to_le_bytes());
+        }
+    };
+
+    // Recompute bus indicators (for phase A)
+    if gstate.phases.contains(PhaseMask::A) {
+        recompute_buses(
+            line_ts,
+            |s| LineGetterResult {
+                volt_start: s.voltage.sa,
+                volt_end: s.voltage.ea,
+                watt: s.real_power.sa.abs(),
+                vars: s.reactive_power.sa.abs(),
+                inductive: s.reactive_power.sa >= 0.0,
+                line_load: s.line_load.a,
+            },
+            &gstate.domain,
+            scene_offset + phase_offset * 0.0,
+            BAND_RED,
+            bus,
+            gstate.show_line_load,
+            gstate.reactive_sign,
+            gstate.bus_style,
+        );
+    }
+
+    // Recompute main line visuals for each enabled phase
+
+    // Phase A
+    if gstate.phases.contains(PhaseMask::A) {
+        recompute_lines(
+            line_ts,
+            |s| LineGetterResult::phase(s, 0),
+            &gstate.domain,
+            scene_offset,
+            0,
+            BAND_RED,
+            line,
+            hazards.as_deref_mut(),
+            gstate.show_line_load,
+            gstate.violations_only,
+            gstate.line_coloring,
+            gstate.reactive_sign,
+            highlight,
+            line_id,
+            gstate.line_gradient,
+        );
+    }
+
+    // Phase B
+    if gstate.phases.contains(PhaseMask::B) {
+        recompute_lines(
+            line_ts,
+            |s| LineGetterResult::phase(s, 1),
+            &gstate.domain,
+            scene_offset,
+            1,
+            BAND_GREEN,
+            line,
+            hazards.as_deref_mut(),
+            gstate.show_line_load,
+            gstate.violations_only,
+            gstate.line_coloring,
+            gstate.reactive_sign,
+            highlight,
+            line_id,
+            gstate.line_gradient,
+        );
+    }
+
+    // Phase C
+    if gstate.phases.contains(PhaseMask::C) {
+        recompute_lines(
+            line_ts,
+            |s| LineGetterResult::phase(s, 2),
+            &gstate.domain,
+            scene_offset,
+            2,
+            BAND_BLUE,
+            line,
+            hazards.as_deref_mut(),
+            gstate.show_line_load,
+            gstate.violations_only,
+            gstate.line_coloring,
+            gstate.reactive_sign,
+            highlight,
+            line_id,
+            gstate.line_gradient,
+        );
+    }
+
+    // Generate low-lying ground-level connections (topological view)
+
+    // Ground lines share the line layout, so carry a flat gradient
+    recompute_gound_lines(line_ts, &gstate.domain, line, |i, dest| {
+        line_id(i, dest);
+        if gstate.line_gradient {
+            let saturation = GROUND_LINE_TEX[1].to_le_bytes();
+            dest.extend_from_slice(&saturation);
+            dest.extend_from_slice(&saturation);
+        }
+    });
+
+    // Recompute transformer visuals for enabled phases A/B/C
+
+    if gstate.phases.contains(PhaseMask::A) {
+        recompute_tfs(
+            tf_ts,
+            |s| TfGetterResult {
+                volt_start: s.voltage.sa,
+                volt_end: s.voltage.ea,
+                tap: s.tap.a,
+                tap_change: s.tap_changes.a,
+            },
+            &gstate.domain,
+            scene_offset + gstate.domain.tf_phase_offset(0),
+            BAND_RED,
+            transformer,
+        );
+    }
+
+    if gstate.phases.contains(PhaseMask::B) {
+        recompute_tfs(
+            tf_ts,
+            |s| TfGetterResult {
+                volt_start: s.voltage.sb,
+                volt_end: s.voltage.eb,
+                tap: s.tap.b,
+                tap_change: s.tap_changes.b,
+            },
+            &gstate.domain,
+            scene_offset + gstate.domain.tf_phase_offset(1),
+            BAND_GREEN,
+            transformer,
+        );
+    }
+
+    if gstate.phases.contains(PhaseMask::C) {
+        recompute_tfs(
+            tf_ts,
+            |s| TfGetterResult {
+                volt_start: s.voltage.sc,
+                volt_end: s.voltage.ec,
+                tap: s.tap.c,
+                tap_change: s.tap_changes.c,
+            },
+            &gstate.domain,
+            scene_offset + gstate.domain.tf_phase_offset(2),
+            BAND_BLUE,
+            transformer,
+        );
+    }
+}
+
+/// Fills the trail buffer with the lines of the previous `trail_length` time
+/// steps, fading out with age.
+fn recompute_trail(gstate: &mut GridState) {
+    let count = gstate.trail_length;
+
+    let phases: [(PhaseMask, fn(&LineState) -> LineGetterResult, usize, f32); 3] = [
+        (PhaseMask::A, |s| LineGetterResult::phase(s, 0), 0, BAND_RED),
+        (
+            PhaseMask::B,
+            |s| LineGetterResult::phase(s, 1),
+            1,
+            BAND_GREEN,
+        ),
+        (
+            PhaseMask::C,
+            |s| LineGetterResult::phase(s, 2),
+            2,
+            BAND_BLUE,
+        ),
+    ];
+
+    let scene_offset = gstate.domain.scene_offset();
+
+    for age in 1..=count.min(gstate.time_step) {
+        let (step, _) = cull_to_view(
+            Cow::Borrowed(&gstate.system.lines[gstate.time_step - age]),
+            &gstate.domain,
+            gstate.view_position,
+            gstate.view_radius,
+        );
+        let alpha = (count + 1 - age) as f32 / count as f32;
+
+        for (mask, getter, phase, band) in phases {
+            if !gstate.phases.contains(mask) {
+                continue;
+            }
+
+            recompute_line_trail(
+                &step,
+                getter,
+                &gstate.domain,
+                scene_offset,
+                phase,
+                band,
+                alpha,
+                &mut gstate.line_trail.buffer,
+                gstate.show_line_load,
+                gstate.line_coloring,
+                gstate.reactive_sign,
+            );
+        }
+    }
+}
+
+/// Copies the instances of `source` into a new element built by `make`,
+/// drawn with `material` and parented to `parent`.
+fn freeze_item(
+    state: &mut ServerState,
+    source: &InstancedItem,
+    make: impl Fn(&mut ServerState, MaterialReference) -> InstancedItem,
+    material: &MaterialReference,
+    parent: &EntityReference,
+) -> InstancedItem {
+    let mut item = make(state, material.clone());
+    item.buffer = source.buffer.clone();
+    item.extra_floats = source.extra_floats;
+
+    set_parent(&item.entity, parent);
+    update_buffers(state, &mut item, false);
+
+    item
+}
+
+/// Material properties for hazard cells of the given base color.
+fn hazard_pbr(color: [f32; 4]) -> ServerPBRInfo {
+    ServerPBRInfo {
+        base_color: color,
+        metallic: Some(0.0),
+        roughness: Some(1.0),
+        ..Default::default()
+    }
+}
+
+/// Attaches an entity to a parent entity.
+fn set_parent(entity: &EntityReference, parent: &EntityReference) {
+    let update = ServerEntityStateUpdatable {
+        parent: Some(parent.clone()),
+        ..Default::default()
+    };
+
+    update.patch(entity);
 }
 
 /// Uploads instance buffer data to the GPU and applies it to the target entity.
 ///
-/// Wraps the buffer in a view and patches the entity’s instance data.
-fn update_buffers(lock: &mut ServerState, element: &InstancedItem) {
+/// Wraps the buffer in a view and patches the entity’s instance data. Groups
+/// with no instances are hidden instead of being given an empty buffer, which
+/// some clients reject.
+///
+/// With `diff`, a group identical to its last upload is not sent again.
+/// Clients cannot patch part of a buffer, so a group with any changed
+/// instance is still sent whole; the changed bytes are counted to show what
+/// partial updates would save.
+fn update_buffers(lock: &mut ServerState, element: &mut InstancedItem, diff: bool) -> UploadStats {
+    let changed = changed_bytes(element.sent.as_deref(), &element.buffer, element.stride());
+
+    let mut stats = UploadStats {
+        total: element.buffer.len(),
+        changed,
+        sent: 0,
+    };
+
+    if diff && element.sent.is_some() && changed == 0 {
+        return stats;
+    }
+
+    element.sent = Some(element.buffer.clone());
+
+    if element.buffer.is_empty() {
+        instances_update(element, None).patch(&element.entity);
+        return stats;
+    }
+
+    stats.sent = element.buffer.len();
+
     // Allocate a GPU buffer for the new instance data
     let line_buffer = lock
         .buffers
@@ -525,19 +2396,160 @@ fn update_buffers(lock: &mut ServerState, element: &InstancedItem) {
         .buffer_views
         .new_component(ServerBufferViewState::new_from_whole_buffer(line_buffer));
 
-    let update = ServerEntityStateUpdatable {
+    let instances = ServerGeometryInstance {
+        view,
+        // Clients assume the standard layout without a stride
+        stride: (element.extra_floats > 0).then(|| element.stride() as u32),
+        bb: None,
+    };
+
+    instances_update(element, Some(instances)).patch(&element.entity);
+
+    stats
+}
+
+/// Builds the entity update showing `instances` of a group. Without any, the
+/// group is hidden and its representation left alone, so no empty buffer
+/// reaches clients.
+fn instances_update(
+    element: &InstancedItem,
+    instances: Option<ServerGeometryInstance>,
+) -> ServerEntityStateUpdatable {
+    let Some(instances) = instances else {
+        return ServerEntityStateUpdatable {
+            visible: Some(false),
+            ..Default::default()
+        };
+    };
+
+    ServerEntityStateUpdatable {
         representation: Some(ServerEntityRepresentation::new_render(
             ServerRenderRepresentation {
                 mesh: element.geometry.clone(),
-                instances: Some(ServerGeometryInstance {
-                    view,
-                    stride: None,
-                    bb: None,
-                }),
+                instances: Some(instances),
             },
         )),
+        visible: Some(true),
         ..Default::default()
+    }
+}
+
+/// Counts the bytes in instances of `buffer`, each `stride` bytes, that
+/// differ from `previous`.
+///
+/// Everything counts as changed if there is no previous upload or the
+/// instance count differs.
+fn changed_bytes(previous: Option<&[u8]>, buffer: &[u8], stride: usize) -> usize {
+    let Some(previous) = previous.filter(|p| p.len() == buffer.len()) else {
+        return buffer.len();
     };
 
-    update.patch(&element.entity);
+    previous
+        .chunks(stride)
+        .zip(buffer.chunks(stride))
+        .filter(|(a, b)| a != b)
+        .map(|(_, b)| b.len())
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn changed_bytes_counts_whole_instances() {
+        let previous: Vec<u8> = (0..12).collect();
+
+        assert_eq!(changed_bytes(Some(&previous), &previous, 4), 0);
+
+        // A change in the last instance, at the very end of the buffer
+        let mut buffer = previous.clone();
+        buffer[11] = 0xFF;
+        assert_eq!(changed_bytes(Some(&previous), &buffer, 4), 4);
+
+        // And in the first one too
+        buffer[0] = 0xFF;
+        assert_eq!(changed_bytes(Some(&previous), &buffer, 4), 8);
+    }
+
+    #[test]
+    fn changed_bytes_edge_cases() {
+        let previous: Vec<u8> = (0..12).collect();
+
+        // Nothing to compare against, or a different instance count
+        assert_eq!(changed_bytes(None, &previous, 4), 12);
+        assert_eq!(changed_bytes(Some(&previous[..8]), &previous, 4), 12);
+
+        assert_eq!(changed_bytes(Some(&[]), &[], 4), 0);
+        assert_eq!(changed_bytes(None, &[], 4), 0);
+
+        // A stride longer than the buffer compares it as one instance
+        let mut buffer = previous.clone();
+        buffer[5] = 0xFF;
+        assert_eq!(changed_bytes(Some(&previous), &buffer, 64), 12);
+    }
+
+    #[test]
+    fn empty_group_sends_no_instances() {
+        let state = ServerState::new();
+        let mut lock = state.lock().unwrap();
+
+        let material = lock.materials.new_component(ServerMaterialState {
+            name: None,
+            mutable: Default::default(),
+        });
+
+        let mut element = make_line_element(&mut lock, material);
+
+        let stats = update_buffers(&mut lock, &mut element, false);
+
+        assert_eq!(stats.total, 0);
+        assert_eq!(stats.sent, 0);
+        assert_eq!(element.sent.as_deref(), Some(&[][..]));
+
+        let update = instances_update(&element, None);
+
+        assert!(update.representation.is_none());
+        assert_eq!(update.visible, Some(false));
+    }
+
+    #[test]
+    fn initial_time_step_is_in_range() {
+        for steps in [0usize, 1, 2, 1000] {
+            let last = steps.saturating_sub(1);
+
+            for start in [None, Some(0), Some(1), Some(steps), Some(usize::MAX)] {
+                let step = initial_time_step(start, steps);
+                assert!(step <= last, "{start:?} of {steps} gave {step}");
+            }
+        }
+
+        assert_eq!(initial_time_step(None, 0), 0);
+        assert_eq!(initial_time_step(None, 1), 0);
+        assert_eq!(initial_time_step(None, 2), 1);
+        assert_eq!(initial_time_step(None, 1000), 500);
+        assert_eq!(initial_time_step(Some(10), 1000), 10);
+    }
+
+    #[test]
+    fn clamp_time_step_is_in_range() {
+        for steps in [0usize, 1, 2, 1000] {
+            let last = steps.saturating_sub(1);
+
+            assert_eq!(clamp_time_step(-5, steps), 0);
+            assert_eq!(clamp_time_step(0, steps), 0);
+            assert_eq!(clamp_time_step(1, steps), 1.min(last));
+            assert_eq!(clamp_time_step(i64::MAX, steps), last);
+        }
+    }
+
+    #[test]
+    fn time_fraction_is_finite() {
+        assert_eq!(time_fraction(0.0, 0), 0.0);
+        assert_eq!(time_fraction(0.0, 1), 0.0);
+        assert_eq!(time_fraction(0.0, 2), 0.0);
+        assert_eq!(time_fraction(1.0, 2), 1.0);
+        assert_eq!(time_fraction(0.0, 1000), 0.0);
+        assert_eq!(time_fraction(999.0, 1000), 1.0);
+    }
 }
@@ -1,6 +1,9 @@
+use std::sync::Arc;
+
 use colabrodo_common::components::TextureRef;
 use colabrodo_server::{server::*, server_messages::*};
 
+use crate::chart::{self, ChartTheme, SummaryMetric, TimeChart};
 use crate::domain::Domain;
 use crate::dots::PowerSystem;
 use crate::{
@@ -14,87 +17,171 @@ use nalgebra_glm::{self as glm, Mat4, Vec3};
 pub struct SummaryItem {
     chart: EntityReference,
     indicator: EntityReference,
-}
+    indicator_mat: MaterialReference,
+
+    /// Horizontal extent of the plot area, in chart-local units
+    plot_x: (f32, f32),
 
-const PX_WIDTH: u32 = 1024;
-const PX_HEIGHT: u32 = 768;
+    system: Arc<PowerSystem>,
+    /// Line quantity currently plotted
+    metric: SummaryMetric,
+    /// Size of the chart image, in pixels
+    size: (u32, u32),
+    supersample: u32,
+    theme: ChartTheme,
+    /// Most points plotted per line
+    max_points: Option<usize>,
+}
 
 const CHART_SIZE: f32 = 0.5;
-const ASPECT_W_H: f32 = (PX_WIDTH as f32) / (PX_HEIGHT as f32);
+const ASPECT_W_H: f32 = (chart::CHART_SIZE.0 as f32) / (chart::CHART_SIZE.1 as f32);
 
 const SUMMARY_HEIGHT: f32 = CHART_SIZE;
 const SUMMARY_WIDTH: f32 = CHART_SIZE * ASPECT_W_H;
 
-impl SummaryItem {
-    pub fn new(ps: &PowerSystem, domain: &Domain, state: &mut ServerState) -> Self {
-        let chart = crate::chart::generate_time_chart(ps, PX_WIDTH, PX_HEIGHT);
-
-        //std::fs::write("temp.png", &chart).unwrap();
-
-        let tex = texture_from_bytes(state, &chart, "Voltage for Line");
-
-        // margins are 60 px on each side
-
-        let chart_mat = state.materials.new_component(ServerMaterialState {
-            name: Some("Chart Material".into()),
-            mutable: ServerMaterialStateUpdatable {
-                pbr_info: Some(ServerPBRInfo {
-                    base_color: [1.0, 1.0, 1.0, 1.0],
-                    base_color_texture: Some(TextureRef {
-                        texture: tex,
-                        transform: None,
-                        texture_coord_slot: None,
-                    }),
-                    metallic: Some(0.0),
-                    roughness: Some(1.0),
-                    ..Default::default()
-                }),
-                ..Default::default()
-            },
-        });
+/// Default color of the time indicator.
+pub const INDICATOR_COLOR: [f32; 4] = [1.0, 0.01, 0.01, 1.0];
 
-        let geometry = {
-            let transform = glm::rotate_x(&Mat4::identity(), 90.0f32.to_radians());
-            let transform = glm::scale(&transform, &glm::vec3(SUMMARY_WIDTH, 1.0, SUMMARY_HEIGHT));
-            make_plane(state, transform, chart_mat)
-        };
+impl SummaryItem {
+    /// Creates the chart entities from an image of the default metric, made
+    /// with [`render_chart`] at `size`. The time indicator is drawn in
+    /// `indicator_color`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        ps: Arc<PowerSystem>,
+        domain: &Domain,
+        chart: &TimeChart,
+        size: (u32, u32),
+        supersample: u32,
+        theme: ChartTheme,
+        max_points: Option<usize>,
+        billboard: bool,
+        indicator_color: [f32; 4],
+        state: &mut ServerState,
+    ) -> Self {
+        let metric = SummaryMetric::default();
 
-        let placement: [f32; 16] = {
-            let spot: Vec3 = glm::vec3(domain.lerp_x(domain.x_bounds.x as f32), 0.5, -0.5);
-            let tf = glm::translation(&spot);
-            tf.as_slice().try_into().unwrap()
-        };
+        let (geometry, plot_x) = make_chart_plane(chart, size, state);
 
         let chart = state.entities.new_component(ServerEntityState {
             name: Some("Time Chart".into()),
             mutable: ServerEntityStateUpdatable {
-                transform: Some(placement),
+                transform: Some(chart_placement(domain)),
                 representation: Some(ServerEntityRepresentation::new_render(
                     ServerRenderRepresentation {
                         mesh: geometry,
                         instances: None,
                     },
                 )),
+                billboard: billboard.then_some(true),
                 ..Default::default()
             },
         });
 
-        let indicator = make_indicator(&chart, state);
+        let (indicator, indicator_mat) = make_indicator(&chart, indicator_color, state);
 
-        Self { chart, indicator }
+        Self {
+            chart,
+            indicator,
+            indicator_mat,
+            plot_x,
+            system: ps,
+            metric,
+            size,
+            supersample,
+            theme,
+            max_points,
+        }
     }
 
-    pub fn set_time_normalized(&mut self, frac: f32) {
-        // x is horizontal.
+    /// Switches the plotted metric, redrawing the chart in place.
+    ///
+    /// New axis labels can move the plot area, so the indicator should be
+    /// placed again.
+    pub fn set_metric(&mut self, metric: SummaryMetric, state: &mut ServerState) {
+        if metric == self.metric {
+            return;
+        }
+
+        self.metric = metric;
+        self.redraw(state);
+    }
+
+    /// Redraws the chart at a new image size. The chart keeps its height in
+    /// the scene, and its width follows the aspect ratio of the image.
+    ///
+    /// The plot area is recomputed, so the indicator should be placed again.
+    pub fn set_size(&mut self, size: (u32, u32), state: &mut ServerState) {
+        if size == self.size {
+            return;
+        }
+
+        self.size = size;
+        self.redraw(state);
+    }
+
+    /// Renders the chart with the current settings and swaps it in.
+    fn redraw(&mut self, state: &mut ServerState) {
+        let chart = render_chart(
+            &self.system,
+            self.metric,
+            self.size,
+            self.supersample,
+            self.theme,
+            self.max_points,
+        );
+        let (geometry, plot_x) = make_chart_plane(&chart, self.size, state);
+
+        let update = ServerEntityStateUpdatable {
+            representation: Some(ServerEntityRepresentation::new_render(
+                ServerRenderRepresentation {
+                    mesh: geometry,
+                    instances: None,
+                },
+            )),
+            ..Default::default()
+        };
+
+        update.patch(&self.chart);
+
+        self.plot_x = plot_x;
+    }
+
+    /// Turns the chart to always face each viewer, or fixes it in place.
+    pub fn set_billboard(&self, enabled: bool) {
+        let update = ServerEntityStateUpdatable {
+            billboard: Some(enabled),
+            ..Default::default()
+        };
+
+        update.patch(&self.chart);
+    }
+
+    /// Changes the color of the time indicator.
+    pub fn set_indicator_color(&self, color: [f32; 4]) {
+        let update = ServerMaterialStateUpdatable {
+            pbr_info: Some(indicator_pbr(color)),
+            ..Default::default()
+        };
 
-        // the chart is 0.5 wide.
-        // 0.5 -> 1024 pixels
-        // and we have to remove the margins
-        // the margins are set at 60, but if you measure it, its actually 70
-        const EFFECTIVE_WIDTH: f32 =
-            ((PX_WIDTH as f32 - (70.0 * 2.0)) / PX_WIDTH as f32) * SUMMARY_WIDTH;
+        update.patch(&self.indicator_mat);
+    }
+
+    /// Moves the chart to match a new domain.
+    pub fn reposition(&self, domain: &Domain) {
+        let update = ServerEntityStateUpdatable {
+            transform: Some(chart_placement(domain)),
+            ..Default::default()
+        };
 
-        let new_x = -EFFECTIVE_WIDTH / 2.0 + EFFECTIVE_WIDTH * frac;
+        update.patch(&self.chart);
+    }
+
+    /// Moves the time indicator to a normalized time (0.0 - 1.0) along the
+    /// plot area of the chart.
+    pub fn set_time_normalized(&mut self, frac: f32) {
+        // x is horizontal.
+        let new_x = indicator_x(self.plot_x, frac);
 
         // Something changed, but it wasn't a probe. So we just accept it.
         let placement: [f32; 16] = {
@@ -112,12 +199,47 @@ impl SummaryItem {
     }
 }
 
-fn make_indicator(parent: &EntityReference, state: &mut ServerState) -> EntityReference {
-    let mat = state.materials.new_component(ServerMaterialState {
-        name: Some("Indicator Mat".into()),
+/// Renders the chart image for a metric. This is the slow part of building
+/// the chart, and needs no server state.
+pub fn render_chart(
+    ps: &PowerSystem,
+    metric: SummaryMetric,
+    size: (u32, u32),
+    supersample: u32,
+    theme: ChartTheme,
+    max_points: Option<usize>,
+) -> TimeChart {
+    crate::chart::generate_time_chart(ps, metric, size.0, size.1, supersample, theme, max_points)
+}
+
+/// Places a rendered chart onto a textured plane.
+///
+/// Returns the plane and the horizontal extent of the plot area, in
+/// chart-local units. `size` is the size of the chart image, in pixels.
+fn make_chart_plane(
+    chart: &TimeChart,
+    size: (u32, u32),
+    state: &mut ServerState,
+) -> (GeometryReference, (f32, f32)) {
+    //std::fs::write("temp.png", &chart.png).unwrap();
+
+    let tex = texture_from_bytes(state, &chart.png, "Voltage for Line");
+
+    let plot_x = (
+        pixel_to_local(chart.plot_x.start as f32, size),
+        pixel_to_local(chart.plot_x.end as f32, size),
+    );
+
+    let chart_mat = state.materials.new_component(ServerMaterialState {
+        name: Some("Chart Material".into()),
         mutable: ServerMaterialStateUpdatable {
             pbr_info: Some(ServerPBRInfo {
-                base_color: [1.0, 0.01, 0.01, 1.0],
+                base_color: [1.0, 1.0, 1.0, 1.0],
+                base_color_texture: Some(TextureRef {
+                    texture: tex,
+                    transform: None,
+                    texture_coord_slot: None,
+                }),
                 metallic: Some(0.0),
                 roughness: Some(1.0),
                 ..Default::default()
@@ -126,11 +248,63 @@ fn make_indicator(parent: &EntityReference, state: &mut ServerState) -> EntityRe
         },
     });
 
+    let geometry = {
+        let transform = glm::rotate_x(&Mat4::identity(), 90.0f32.to_radians());
+        let width = chart::chart_plane_width(SUMMARY_WIDTH, size);
+        let transform = glm::scale(&transform, &glm::vec3(width, 1.0, SUMMARY_HEIGHT));
+        make_plane(state, transform, chart_mat)
+    };
+
+    (geometry, plot_x)
+}
+
+/// Computes where the chart should sit for a given domain.
+fn chart_placement(domain: &Domain) -> [f32; 16] {
+    let spot: Vec3 = glm::vec3(
+        domain.lerp_x(domain.x_bounds.x as f32),
+        0.5 + domain.scene_y_offset,
+        -0.5,
+    );
+    let tf = glm::translation(&spot);
+    tf.as_slice().try_into().unwrap()
+}
+
+/// Converts a horizontal pixel coordinate on a chart image of `size` pixels
+/// into chart-local units, where the chart is centered on the origin.
+fn pixel_to_local(px: f32, size: (u32, u32)) -> f32 {
+    (px / size.0 as f32 - 0.5) * chart::chart_plane_width(SUMMARY_WIDTH, size)
+}
+
+/// Material properties for the time indicator.
+fn indicator_pbr(color: [f32; 4]) -> ServerPBRInfo {
+    ServerPBRInfo {
+        base_color: color,
+        metallic: Some(0.0),
+        roughness: Some(1.0),
+        ..Default::default()
+    }
+}
+
+/// Creates the time indicator under the chart, returning it with its
+/// material so the color can be changed later.
+fn make_indicator(
+    parent: &EntityReference,
+    color: [f32; 4],
+    state: &mut ServerState,
+) -> (EntityReference, MaterialReference) {
+    let mat = state.materials.new_component(ServerMaterialState {
+        name: Some("Indicator Mat".into()),
+        mutable: ServerMaterialStateUpdatable {
+            pbr_info: Some(indicator_pbr(color)),
+            ..Default::default()
+        },
+    });
+
     let tf = glm::scaling(&glm::vec3(0.01, 0.4, 0.01));
 
-    let geom = make_cyl(state, tf, mat);
+    let geom = make_cyl(state, tf, mat.clone());
 
-    state.entities.new_component(ServerEntityState {
+    let indicator = state.entities.new_component(ServerEntityState {
         name: Some("Indicator".into()),
         mutable: ServerEntityStateUpdatable {
             parent: Some(parent.clone()),
@@ -142,5 +316,34 @@ fn make_indicator(parent: &EntityReference, state: &mut ServerState) -> EntityRe
             )),
             ..Default::default()
         },
-    })
+    });
+
+    (indicator, mat)
+}
+
+/// Horizontal position of the time indicator at a normalized time, within
+/// the plot area `plot_x`.
+fn indicator_x(plot_x: (f32, f32), frac: f32) -> f32 {
+    plot_x.0 + (plot_x.1 - plot_x.0) * frac
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::time_fraction;
+
+    #[test]
+    fn indicator_spans_plot_area() {
+        let plot_x = (
+            pixel_to_local(70.0, chart::CHART_SIZE),
+            pixel_to_local(960.0, chart::CHART_SIZE),
+        );
+        let steps = 25;
+
+        let at_step = |step: usize| indicator_x(plot_x, time_fraction(step as f32, steps));
+
+        assert!((at_step(0) - plot_x.0).abs() < 1e-6);
+        assert!((at_step(steps - 1) - plot_x.1).abs() < 1e-6);
+        assert!((at_step(12) - (plot_x.0 + plot_x.1) / 2.0).abs() < 1e-6);
+    }
 }
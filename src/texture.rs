@@ -55,17 +55,3 @@ const CHEV_TEXTURE_BYTES: &[u8; 12756] = include_bytes!("../assets/chevron_left.
 pub fn make_chevron_texture(state: &mut ServerState) -> TextureReference {
     texture_from_bytes(state, CHEV_TEXTURE_BYTES, "Line Flow")
 }
-
-// Embeds static ruler images into the binary at compile time.
-const RULER_TEXTURE_BYTES: &[u8; 430225] = include_bytes!("../assets/ruler.png");
-const RULER_LL_TEXTURE_BYTES: &[u8; 444139] = include_bytes!("../assets/ruler_line_load.png");
-
-/// Creates and registers a pre-defined ruler texture.
-pub fn make_ruler_texture(state: &mut ServerState) -> TextureReference {
-    texture_from_bytes(state, RULER_TEXTURE_BYTES, "Ruler")
-}
-
-/// Creates and registers a pre-defined ruler texture for line load visualization.
-pub fn make_ruler_ll_texture(state: &mut ServerState) -> TextureReference {
-    texture_from_bytes(state, RULER_LL_TEXTURE_BYTES, "Ruler (LL)")
-}
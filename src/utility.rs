@@ -3,7 +3,8 @@ use std::ops::{Add, Div, Mul, Sub};
 
 /// A trait for types that can perform linear interpolation.
 ///
-/// Provides `lerp` and `clamped_lerp` functions.
+/// Provides `lerp` and `clamped_lerp` functions, along with a variant that
+/// clamps only the low end of the output range.
 ///
 /// Implemented for `f32` and `f64`.
 pub trait LerpTrait:
@@ -18,8 +19,15 @@ pub trait LerpTrait:
     /// Linearly interpolates a value from an input range (`x0`..`x1`) to an output range (`y0`..`y1`).
     ///
     /// No clamping is performed: the result may be outside the output range if `self` is outside the input range.
+    ///
+    /// An empty input range (`x0 == x1`) is a step: values above it map to
+    /// `y1`, and the rest to `y0`, rather than dividing by zero.
     #[inline]
     fn lerp(&self, x0: Self, x1: Self, y0: Self, y1: Self) -> Self {
+        if x0 == x1 {
+            return if *self > x0 { y1 } else { y0 };
+        }
+
         y0 + (*self - x0) * ((y1 - y0) / (x1 - x0))
     }
 
@@ -30,6 +38,15 @@ pub trait LerpTrait:
     fn clamped_lerp(&self, x0: Self, x1: Self, y0: Self, y1: Self) -> Self {
         num_traits::clamp(self.lerp(x0, x1, y0, y1), y0, y1)
     }
+
+    /// Linearly interpolates a value from an input range to an output range,
+    /// clamping only the low end.
+    ///
+    /// The output never drops below `y0`, but may grow past `y1`.
+    #[inline]
+    fn clamped_lerp_low(&self, x0: Self, x1: Self, y0: Self, y1: Self) -> Self {
+        num_traits::clamp_min(self.lerp(x0, x1, y0, y1), y0)
+    }
 }
 
 // Provide LerpTrait implementations for basic floats
@@ -67,18 +84,100 @@ pub fn roll_free_rotation(direction: glm::Vec3) -> glm::Quat {
 /// Transforms a point `[x, y, z]` by a 4x4 transformation matrix.
 ///
 /// The point is treated as a position (i.e., homogeneous coordinate w = 1.0).
+/// If the transformed w is zero, the point is returned untransformed.
 pub fn transform_p(p: [f32; 3], tf: &glm::Mat4) -> [f32; 3] {
     let lp: glm::Vec3 = p.into();
     let lp = glm::vec4(lp.x, lp.y, lp.z, 1.0);
     let lp = tf * lp;
+
+    // A degenerate projection sends the point to infinity. Leave it where it
+    // was rather than emitting NaN
+    if lp.w.abs() < f32::EPSILON {
+        return p;
+    }
+
     (lp.xyz() / lp.w).into()
 }
 
 /// Transforms a normal vector `[x, y, z]` by a 3x3 matrix.
 ///
-/// The vector is normalized after transformation.
+/// The vector is normalized after transformation. A vector that transforms to
+/// zero length is returned as zero, rather than NaN.
 pub fn transform_n(p: [f32; 3], tf: &glm::Mat3) -> [f32; 3] {
     let lp: glm::Vec3 = p.into();
-    let lp = (tf * lp).normalize();
-    lp.into()
+    let lp = tf * lp;
+
+    lp.try_normalize(f32::EPSILON)
+        .unwrap_or_else(glm::Vec3::zeros)
+        .into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clamped_lerp_clamps_both_ends() {
+        assert_eq!((-1.0f32).clamped_lerp(0.0, 10.0, 1.0, 2.0), 1.0);
+        assert_eq!(5.0f32.clamped_lerp(0.0, 10.0, 1.0, 2.0), 1.5);
+        assert_eq!(20.0f32.clamped_lerp(0.0, 10.0, 1.0, 2.0), 2.0);
+    }
+
+    #[test]
+    fn clamped_lerp_low_clamps_low_end() {
+        assert_eq!((-1.0f32).clamped_lerp_low(0.0, 10.0, 1.0, 2.0), 1.0);
+        assert_eq!(5.0f32.clamped_lerp_low(0.0, 10.0, 1.0, 2.0), 1.5);
+        assert_eq!(20.0f32.clamped_lerp_low(0.0, 10.0, 1.0, 2.0), 3.0);
+    }
+
+    #[test]
+    fn empty_input_range_is_a_step() {
+        for v in [-1.0f64, 5.0, 20.0] {
+            let expected = if v > 5.0 { 2.0 } else { 1.0 };
+
+            assert_eq!(v.lerp(5.0, 5.0, 1.0, 2.0), expected);
+            assert_eq!(v.clamped_lerp(5.0, 5.0, 1.0, 2.0), expected);
+            assert_eq!(v.clamped_lerp_low(5.0, 5.0, 1.0, 2.0), expected);
+        }
+    }
+
+    #[test]
+    fn transform_p_survives_degenerate_projection() {
+        // Every point ends up with w = 0
+        let mut tf = glm::Mat4::identity();
+        tf[(3, 3)] = 0.0;
+
+        let p = transform_p([1.0, 2.0, 3.0], &tf);
+
+        assert!(p.iter().all(|v| v.is_finite()));
+        assert_eq!(p, [1.0, 2.0, 3.0]);
+    }
+
+    #[test]
+    fn transform_p_applies_ordinary_transforms() {
+        let tf = glm::translation(&glm::vec3(1.0, -2.0, 0.5));
+
+        assert_eq!(transform_p([1.0, 2.0, 3.0], &tf), [2.0, 0.0, 3.5]);
+    }
+
+    #[test]
+    fn transform_n_survives_zero_length() {
+        let n = transform_n([0.0, 0.0, 0.0], &glm::Mat3::identity());
+
+        assert!(n.iter().all(|v| v.is_finite()));
+        assert_eq!(n, [0.0, 0.0, 0.0]);
+
+        // A matrix that flattens the normal to nothing
+        let n = transform_n([0.0, 1.0, 0.0], &glm::Mat3::zeros());
+
+        assert!(n.iter().all(|v| v.is_finite()));
+        assert_eq!(n, [0.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn transform_n_normalizes_ordinary_normals() {
+        let tf = glm::Mat3::from_diagonal(&glm::vec3(2.0, 2.0, 2.0));
+
+        assert_eq!(transform_n([0.0, 3.0, 0.0], &tf), [0.0, 1.0, 0.0]);
+    }
 }